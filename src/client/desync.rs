@@ -0,0 +1,119 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{driver::GameEntityVecRead, game_engine::{CoolGameEngineTID, CoolVoxel}, game_map::{GameMap, checksums::{ChecksumPart, ResyncRequest, StateChecksums, movement_checksum, stats_checksum, vehicle_position_checksum}, road::Road, vehicle_states::VehicleState}, vehicle::VehicleEntityVecRead};
+
+// entities computed on both sides can be off for a check while events are on their way, only a difference lasting that many checks is a desync
+// chunks only differ when an edit went missing, so they count at once
+const ENTITY_MISMATCHES_FOR_DESYNC:usize = 2;
+// checksums of our own past ticks kept to compare with the server's, they come in late
+const HISTORY_TICKS:usize = 256;
+
+// our entities after one of our ticks, in id order like StateChecksums
+struct OwnChecksums {
+    tick:usize,
+    vehicles:Vec<u64>,
+    movements:Vec<u64>,
+    stats:Vec<u64>,
+}
+
+// Compares the checksums the server sends with the same checksums of our own state at the matching tick, and says which components went their own way
+pub struct DesyncDetector {
+    resync:bool,
+    // parts that differed in the last checks, with how many checks in a row
+    mismatches:HashMap<ChecksumPart, usize>,
+    history:VecDeque<OwnChecksums>,
+    // server tick minus our tick, from the latest state of our vehicle
+    tick_offset:Option<isize>,
+}
+
+impl DesyncDetector {
+    pub fn new(resync:bool) -> Self {
+        Self { resync, mismatches:HashMap::with_capacity(16), history:VecDeque::with_capacity(HISTORY_TICKS), tick_offset:None }
+    }
+    // the server took the state at its tick after getting our inputs up to input_tick
+    pub fn line_up(&mut self, state:&VehicleState) {
+        self.tick_offset = Some(state.server_tick as isize - state.input_tick as isize);
+    }
+    // called once the engine ran our tick
+    pub fn record<'a>(&mut self, tick:usize, drivers:&GameEntityVecRead<'a, CoolGameEngineTID>, vehicles:&VehicleEntityVecRead<'a, CoolGameEngineTID>) {
+        if self.history.len() >= HISTORY_TICKS {
+            self.history.pop_front();
+        }
+        self.history.push_back(OwnChecksums {
+            tick,
+            vehicles:vehicles.position.iter().map(|position| {vehicle_position_checksum(position)}).collect(),
+            movements:drivers.movement.iter().map(|movement| {movement_checksum(movement)}).collect(),
+            stats:drivers.stats.iter().map(|stats| {stats_checksum(stats)}).collect(),
+        });
+    }
+    // own_vehicle is predicted and corrected by VehicleStates already, it is left out
+    // entities are only checked once we know which of our ticks matches the server's and still have it
+    // returns what to ask the server again when resyncing is on and something diverged
+    pub fn check(&mut self, checksums:&StateChecksums, world:&GameMap<CoolVoxel, Road>, own_vehicle:Option<usize>) -> Option<ResyncRequest> {
+        let mut differing = Vec::with_capacity(8);
+        let mut still_differing = HashMap::with_capacity(self.mismatches.len());
+        let own_tick = self.tick_offset.map(|offset| {checksums.tick as isize - offset});
+        match self.history.iter().find(|own| {Some(own.tick as isize) == own_tick}) {
+            Some(own) => {
+                // entities the server has and we don't have yet are still coming, not a desync
+                for (id, (theirs, ours)) in checksums.vehicles.iter().zip(&own.vehicles).enumerate() {
+                    if Some(id) != own_vehicle && ours != theirs {
+                        differing.push(ChecksumPart::VehiclePosition(id));
+                    }
+                }
+                for (id, (theirs, ours)) in checksums.movements.iter().zip(&own.movements).enumerate() {
+                    if ours != theirs {
+                        differing.push(ChecksumPart::Movement(id));
+                    }
+                }
+                for (id, (theirs, ours)) in checksums.stats.iter().zip(&own.stats).enumerate() {
+                    if ours != theirs {
+                        differing.push(ChecksumPart::Stats(id));
+                    }
+                }
+            },
+            // nothing to compare with, entities that differed before keep their count
+            None => still_differing.extend(self.mismatches.iter().filter(|(part, _)| {!matches!(part, ChecksumPart::Chunk(_))}).map(|(part, count)| {(*part, *count)}))
+        }
+        // chunks only change with the server's edits, which come in order with the checksums
+        for (c_pos, checksum) in &checksums.chunks {
+            match world.chunk_checksum(*c_pos) {
+                Some(ours) if ours != *checksum => differing.push(ChecksumPart::Chunk(*c_pos)),
+                _ => ()
+            }
+        }
+
+        for part in &differing {
+            let count = self.mismatches.get(part).copied().unwrap_or(0) + 1;
+            still_differing.insert(*part, count);
+        }
+        self.mismatches = still_differing;
+
+        let mut request = ResyncRequest { vehicles:Vec::new(), drivers:Vec::new(), chunks:Vec::new() };
+        for part in &differing {
+            let count = self.mismatches[part];
+            let needed = match part {
+                ChecksumPart::Chunk(_) => 1,
+                _ => ENTITY_MISMATCHES_FOR_DESYNC
+            };
+            // logged once when it becomes a desync, not on every check after that
+            if count != needed {
+                continue;
+            }
+            println!("[Desync] {} differs from the server (checked at its tick {})", part, checksums.tick);
+            match part {
+                ChecksumPart::VehiclePosition(id) => request.vehicles.push(*id),
+                ChecksumPart::Movement(id) | ChecksumPart::Stats(id) => if !request.drivers.contains(id) {
+                    request.drivers.push(*id);
+                },
+                ChecksumPart::Chunk(c_pos) => request.chunks.push(*c_pos),
+            }
+        }
+        if self.resync && !request.is_empty() {
+            Some(request)
+        }
+        else {
+            None
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::{collections::VecDeque, time::{Duration, Instant}};
+
+use hord3::horde::geometry::{rotation::Orientation, vec3d::Vec3Df};
+
+use crate::{driver::GameEntityVecWrite, game_engine::CoolGameEngineTID, vehicle::VehicleEntityVecWrite};
+
+// positions older than the delay plus that are forgotten
+const HISTORY_MARGIN:Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy)]
+struct Pose {
+    time:Instant,
+    pos:Vec3Df,
+    orient:Orientation,
+}
+
+impl Pose {
+    fn lerp(&self, other:&Pose, t:f32) -> (Vec3Df, Orientation) {
+        (self.pos + (other.pos - self.pos) * t, Orientation::new(
+            self.orient.yaw + (other.orient.yaw - self.orient.yaw) * t,
+            self.orient.pitch + (other.orient.pitch - self.orient.pitch) * t,
+            self.orient.roll + (other.orient.roll - self.orient.roll) * t
+        ))
+    }
+}
+
+// positions of one entity as they came from the server, oldest first
+struct PoseHistory {
+    poses:VecDeque<Pose>,
+}
+
+impl PoseHistory {
+    fn new() -> Self {
+        Self { poses:VecDeque::with_capacity(32) }
+    }
+    fn record(&mut self, now:Instant, pos:Vec3Df, orient:Orientation, tick_duration:Duration, delay:Duration) {
+        match self.poses.back().copied() {
+            Some(last) if last.pos == pos && last.orient == orient => return,
+            // the entity stood still until now, so it only started moving a tick ago
+            Some(last) if now.duration_since(last.time) > delay => self.poses.push_back(Pose { time: now - tick_duration, ..last }),
+            _ => ()
+        }
+        self.poses.push_back(Pose { time: now, pos, orient });
+        while self.poses.len() > 2 && self.poses.front().is_some_and(|oldest| {now.duration_since(oldest.time) > delay + HISTORY_MARGIN}) {
+            self.poses.pop_front();
+        }
+    }
+    fn sample(&self, at:Instant, max_extrapolation:Duration) -> Option<(Vec3Df, Orientation)> {
+        let newest = *self.poses.back()?;
+        if at >= newest.time {
+            return match self.poses.len() {
+                // keeps going at the speed between the two newest positions for a while
+                len if len >= 2 && !max_extrapolation.is_zero() => {
+                    let previous = self.poses[len - 2];
+                    let span = newest.time.duration_since(previous.time).as_secs_f32();
+                    let ahead = at.duration_since(newest.time).min(max_extrapolation).as_secs_f32();
+                    Some(previous.lerp(&newest, 1.0 + ahead / span.max(0.0001)))
+                },
+                _ => Some((newest.pos, newest.orient))
+            };
+        }
+        for i in (0..self.poses.len() - 1).rev() {
+            let (before, after) = (self.poses[i], self.poses[i + 1]);
+            if before.time <= at {
+                let t = at.duration_since(before.time).as_secs_f32() / after.time.duration_since(before.time).as_secs_f32().max(0.0001);
+                return Some(before.lerp(&after, t));
+            }
+        }
+        self.poses.front().map(|oldest| {(oldest.pos, oldest.orient)})
+    }
+}
+
+// Other players' (and AI) drivers and vehicles are drawn a little in the past, between the positions the server sent,
+// so irregular updates don't make them jump around
+// only the mesh_info of entities is changed (what gets rendered), never their position, so the simulation is left alone
+pub struct Interpolator {
+    delay:Duration,
+    max_extrapolation:Duration,
+    tick_duration:Duration,
+    drivers:Vec<PoseHistory>,
+    vehicles:Vec<PoseHistory>,
+}
+
+impl Interpolator {
+    pub fn new(delay:Duration, max_extrapolation:Duration, tickrate:usize) -> Self {
+        Self { delay, max_extrapolation, tick_duration:Duration::from_secs_f64(1.0 / tickrate as f64), drivers:Vec::with_capacity(16), vehicles:Vec::with_capacity(16) }
+    }
+    // own_driver is drawn where the engine puts it, own_vehicle where the prediction shows it
+    pub fn update<'a>(&mut self, drivers:&mut GameEntityVecWrite<'a, CoolGameEngineTID>, vehicles:&mut VehicleEntityVecWrite<'a, CoolGameEngineTID>, own_driver:Option<usize>, own_vehicle:Option<usize>) {
+        let now = Instant::now();
+        let shown_at = now - self.delay;
+        while self.drivers.len() < drivers.movement.len() {
+            self.drivers.push(PoseHistory::new());
+        }
+        while self.vehicles.len() < vehicles.position.len() {
+            self.vehicles.push(PoseHistory::new());
+        }
+        for (id, history) in self.drivers.iter_mut().enumerate() {
+            if Some(id) == own_driver {
+                drivers.mesh_info[id].shown = None;
+                continue;
+            }
+            history.record(now, drivers.movement[id].pos, drivers.movement[id].orient, self.tick_duration, self.delay);
+            drivers.mesh_info[id].shown = history.sample(shown_at, self.max_extrapolation);
+        }
+        for (id, history) in self.vehicles.iter_mut().enumerate() {
+            if Some(id) == own_vehicle {
+                continue;
+            }
+            history.record(now, vehicles.position[id].pos, vehicles.position[id].orientation, self.tick_duration, self.delay);
+            vehicles.mesh_info[id].shown = history.sample(shown_at, self.max_extrapolation);
+        }
+    }
+}
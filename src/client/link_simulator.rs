@@ -0,0 +1,119 @@
+use std::{io::{Read, Write}, net::{Ipv4Addr, Shutdown, TcpListener, TcpStream}, sync::mpmc::{Receiver, channel}, thread, time::{Duration, Instant}};
+
+// a lost packet is sent again after that, or after twice the latency if it's longer
+const RETRANSMIT_DELAY:Duration = Duration::from_millis(200);
+const READ_BUFFER_SIZE:usize = 16384;
+
+enum Trouble {
+    Lost,
+    Reordered,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LinkConditions {
+    // one way, added to everything going through the link in both directions
+    pub latency:Duration,
+    // every packet gets between 0 and that on top of the latency
+    pub jitter:Duration,
+    pub loss_percent:f32,
+    pub reorder_percent:f32,
+}
+
+impl LinkConditions {
+    pub fn is_perfect(&self) -> bool {
+        self.latency.is_zero() && self.jitter.is_zero() && self.loss_percent <= 0.0 && self.reorder_percent <= 0.0
+    }
+    // when a packet read at sent_at reaches the other side, never before the one read before it (ready_before)
+    // hord3 talks over TCP so bytes can't really be lost or swapped without breaking the stream,
+    // losses and reorderings show up like TCP shows them to the game : as stalls until the missing packet comes
+    fn delivery_time(&self, sent_at:Instant, ready_before:Instant) -> (Instant, Option<Trouble>) {
+        let mut ready_at = sent_at + self.latency + Duration::from_millis(fastrand::u64(0..=self.jitter.as_millis() as u64));
+        let mut trouble = None;
+        if fastrand::f32() * 100.0 < self.loss_percent {
+            ready_at += RETRANSMIT_DELAY.max(self.latency * 2);
+            trouble = Some(Trouble::Lost);
+        }
+        else if fastrand::f32() * 100.0 < self.reorder_percent {
+            // overtaken by the next packets, which wait for it on the other side
+            ready_at += self.latency.max(self.jitter);
+            trouble = Some(Trouble::Reordered);
+        }
+        (ready_at.max(ready_before), trouble)
+    }
+}
+
+// Local relay standing between the client and the server, started instead of connecting straight to the server
+// it listens on a free port of localhost and forwards every connection to the server, through the given conditions
+// returns the address the client should connect to
+pub fn start_link_simulator(server:(Ipv4Addr, u16), conditions:LinkConditions) -> std::io::Result<(Ipv4Addr, u16)> {
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let local_port = listener.local_addr()?.port();
+    println!("[Link] Simulating {}ms latency, {}ms jitter, {}% loss, {}% reordering on port {} to {}:{}", conditions.latency.as_millis(), conditions.jitter.as_millis(), conditions.loss_percent, conditions.reorder_percent, local_port, server.0, server.1);
+    thread::spawn(move || {
+        for incoming in listener.incoming() {
+            match incoming {
+                Ok(client) => match TcpStream::connect(server) {
+                    Ok(server_stream) => match relay(client, server_stream, conditions) {
+                        Ok(()) => (),
+                        Err(error) => eprintln!("[Link] Could not relay the connection : {}", error)
+                    },
+                    Err(error) => eprintln!("[Link] Could not reach the server at {}:{} : {}", server.0, server.1, error)
+                },
+                Err(error) => eprintln!("[Link] Could not accept a connection : {}", error)
+            }
+        }
+    });
+    Ok((Ipv4Addr::LOCALHOST, local_port))
+}
+
+fn relay(client:TcpStream, server:TcpStream, conditions:LinkConditions) -> std::io::Result<()> {
+    client.set_nodelay(true)?;
+    server.set_nodelay(true)?;
+    pump("client -> server", client.try_clone()?, server.try_clone()?, conditions);
+    pump("server -> client", server, client, conditions);
+    Ok(())
+}
+
+// one direction of a connection : a thread reading packets and giving them a delivery time, another one writing them when it comes
+fn pump(direction:&'static str, mut from:TcpStream, mut to:TcpStream, conditions:LinkConditions) {
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        let mut buffer = vec![0 ; READ_BUFFER_SIZE];
+        let mut ready_before = Instant::now();
+        let (mut lost, mut reordered) = (0, 0);
+        loop {
+            match from.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => {
+                    let (ready_at, trouble) = conditions.delivery_time(Instant::now(), ready_before);
+                    match trouble {
+                        Some(Trouble::Lost) => lost += 1,
+                        Some(Trouble::Reordered) => reordered += 1,
+                        None => ()
+                    }
+                    ready_before = ready_at;
+                    if sender.send((ready_at, buffer[..read].to_vec())).is_err() {
+                        break
+                    }
+                }
+            }
+        }
+        println!("[Link] {} closed, {} packets lost and {} reordered", direction, lost, reordered);
+    });
+    thread::spawn(move || {
+        deliver(&receiver, &mut to);
+        let _ = to.shutdown(Shutdown::Write);
+    });
+}
+
+fn deliver(receiver:&Receiver<(Instant, Vec<u8>)>, to:&mut TcpStream) {
+    while let Ok((ready_at, packet)) = receiver.recv() {
+        let now = Instant::now();
+        if ready_at > now {
+            thread::sleep(ready_at - now);
+        }
+        if to.write_all(&packet).is_err() {
+            break
+        }
+    }
+}
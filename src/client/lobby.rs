@@ -0,0 +1,93 @@
+use hord3::horde::{frontend::interact::Button, game_engine::world::WorldTunnelsOut};
+
+use crate::{game_engine::{CoolGameEngineTID, CoolVoxel}, game_input_handler::GameInputHandler, game_map::{GameMap, GameMapEvent, session::SessionMessage, lobby::{LobbyEvent, LobbyPhase, LobbyPlayer, LobbyState, MatchSetup}, road::Road}};
+
+// What the server says about the lobby, and what we ask of it : Y toggles ready, N switches to the next vehicle type
+// the vehicle type and the track of the config are asked for once the server knows us
+pub struct LobbyView {
+    state:Option<LobbyState>,
+    vehicle_type:usize,
+    setup:Option<MatchSetup>,
+    config_sent:bool,
+}
+
+impl LobbyView {
+    pub fn new(vehicle_type:usize, setup:Option<MatchSetup>) -> Self {
+        Self { state:None, vehicle_type, setup, config_sent:false }
+    }
+    // vehicles wait on the starting grid, our inputs are ignored
+    pub fn is_counting_down(&self) -> bool {
+        matches!(self.state.as_ref().map(|state| {&state.phase}), Some(LobbyPhase::Countdown(_)))
+    }
+    pub fn handle_events(&mut self, events:Vec<LobbyEvent>, token:u64, driver:Option<usize>, tunnels:&WorldTunnelsOut<GameMap<CoolVoxel, Road>, CoolGameEngineTID>) {
+        for event in events {
+            match event {
+                LobbyEvent::State(state) => {
+                    self.print_changes(&state);
+                    self.state = Some(state);
+                },
+                // our own requests coming back
+                _ => ()
+            }
+        }
+        let (state, own) = match self.own_player(driver) {
+            Some((state, own)) => (state.clone(), own.clone()),
+            None => return
+        };
+        if self.config_sent || state.phase != LobbyPhase::Waiting {
+            return;
+        }
+        if self.vehicle_type != own.vehicle_type {
+            if self.vehicle_type < state.vehicle_types {
+                tunnels.send_event(GameMapEvent::Session(SessionMessage::Lobby(LobbyEvent::PickVehicle { token, vehicle_type:self.vehicle_type })));
+            }
+            else {
+                println!("[Lobby] The server has no vehicle type {}, keeping type {}", self.vehicle_type, own.vehicle_type);
+                self.vehicle_type = own.vehicle_type;
+            }
+        }
+        match &self.setup {
+            Some(setup) if own.host && state.setup_locked => println!("[Lobby] The track of this server can't change anymore, seed {} through {} is kept instead of seed {} through {}", state.setup.seed, state.setup.biome_names(), setup.seed, setup.biome_names()),
+            Some(setup) if own.host => tunnels.send_event(GameMapEvent::Session(SessionMessage::Lobby(LobbyEvent::Setup { token, setup:setup.clone() }))),
+            _ => ()
+        }
+        self.config_sent = true;
+    }
+    pub fn handle_keys(&mut self, input_handler:&GameInputHandler, token:u64, tunnels:&WorldTunnelsOut<GameMap<CoolVoxel, Road>, CoolGameEngineTID>) {
+        let (state, own) = match self.own_player(input_handler.get_driver()) {
+            Some((state, own)) if state.phase == LobbyPhase::Waiting => (state.clone(), own.clone()),
+            _ => return
+        };
+        if input_handler.is_newly_pressed(&Button::Y) {
+            tunnels.send_event(GameMapEvent::Session(SessionMessage::Lobby(LobbyEvent::Ready { token, ready:!own.ready })));
+        }
+        if input_handler.is_newly_pressed(&Button::N) && state.vehicle_types > 1 {
+            self.vehicle_type = (own.vehicle_type + 1) % state.vehicle_types;
+            tunnels.send_event(GameMapEvent::Session(SessionMessage::Lobby(LobbyEvent::PickVehicle { token, vehicle_type:self.vehicle_type })));
+        }
+    }
+    fn own_player(&self, driver:Option<usize>) -> Option<(&LobbyState, &LobbyPlayer)> {
+        let state = self.state.as_ref()?;
+        let driver = driver?;
+        let own = state.players.iter().find(|player| {player.driver == driver})?;
+        Some((state, own))
+    }
+    fn print_changes(&self, state:&LobbyState) {
+        let previous = self.state.as_ref();
+        if previous.map(|previous| {&previous.phase}) != Some(&state.phase) {
+            match &state.phase {
+                LobbyPhase::Waiting => println!("[Lobby] Waiting for every player to be ready, Y to get ready, N to change vehicle"),
+                LobbyPhase::Countdown(seconds) => println!("[Lobby] {}...", seconds),
+                LobbyPhase::Racing => println!("[Lobby] Go !"),
+            }
+        }
+        if previous.map(|previous| {&previous.setup}) != Some(&state.setup) {
+            println!("[Lobby] Track : seed {} through {}", state.setup.seed, state.setup.biome_names());
+        }
+        if state.phase == LobbyPhase::Waiting && previous.map(|previous| {&previous.players}) != Some(&state.players) {
+            for player in &state.players {
+                println!("[Lobby] {}{} : vehicle type {}, {}", player.name, if player.host {" (host)"} else {""}, player.vehicle_type, if player.ready {"ready"} else {"not ready"});
+            }
+        }
+    }
+}
@@ -1,9 +1,11 @@
 
-use std::{collections::HashMap, f32::consts::PI, net::Ipv4Addr, path::PathBuf, simd::Simd, sync::{atomic::{AtomicUsize, Ordering}, mpmc::{self, channel}, Arc, RwLock}, thread, time::{Duration, Instant}};
+use std::{collections::{HashMap, HashSet}, f32::consts::PI, net::Ipv4Addr, path::PathBuf, simd::Simd, sync::{atomic::{AtomicUsize, Ordering}, mpmc::{self, channel}, Arc, RwLock}, thread, time::{Duration, Instant}};
 
-use crate::{client::client_tasks::GameUserEvent, driver::{colliders::AABB, stats::{StaticStats, Stats}}, game_map::road::Road, vehicle::{NewVehicleEntity, VehicleEntityVec, default_vehicles::default_car::get_default_car_type, position::VehiclePosition, vehicle_stats::VehicleStats}};
+use crate::{client::client_tasks::GameUserEvent, driver::{colliders::AABB, stats::{StaticStats, Stats}}, game_map::{GameMapEvent, WorldChunkPos, players::{PlayerEvent, player_id}, session::{SessionMessage, SessionMessages}, road::{Road, RoadVoxels}}, vehicle::{NewVehicleEntity, VehicleEntityVec, default_vehicles::default_car::get_default_car_type, position::VehiclePosition, vehicle_stats::VehicleStats}};
 use cosmic_text::{Color, Font, Metrics};
 use crate::cutscene::{camera_movement::{CameraMovement, CameraMovementDuration, CameraMovementElement, CameraSequence}, demo_cutscene::{get_demo_cutscene, get_empty_cutscene}, game_shader::GameShader, real_demo_cutscene::get_real_demo_cutscene, write_in_the_air::get_positions_of_air_written_text, written_texture::get_written_texture_buffer};
+use crate::config::GameConfig;
+use crate::voxel_registry::voxel_registry;
 use crate::day_night::DayNight;
 use crate::game_3d_models::{clustered_ent_mesh, grey_sphere_mesh, lit_selection_cube, second_spread_out_ent_mesh, simple_line, sphere_mesh, spread_out_ent_mesh, textured_sphere_mesh, wireframe_sphere_mesh, xyz_mesh};
 use crate::game_engine::{CoolGameEngineBase, CoolVoxel, CoolVoxelType, ExtraData};
@@ -15,14 +17,46 @@ use hord3::{defaults::{default_frontends::minifb_frontend::MiniFBWindow, default
 use noise::{NoiseFn, Perlin, Seedable};
 use crate::tile_editor::{get_tile_voxels, TileEditorData};
 use client_tasks::{ClientTask, ClientTaskTaskHandler};
+use prediction::VehiclePredictor;
+use interpolation::Interpolator;
+use desync::DesyncDetector;
+use lobby::LobbyView;
+use race::RaceView;
+use crate::replay::{ReplayRecorder, player::ReplayPlayer};
 
 use crate::{driver::{actions::{Action, ActionKind, ActionSource, ActionTimer, ActionsEvent, ActionsUpdate, StaticGameActions}, director::{llm_director::LLMDirector, Director, DirectorKind, StaticDirector}, planner::StaticPlanner, GameEntityEvent}, game_map::get_voxel_pos, proxima_link::ProximaLink};
 
 pub mod client_tasks;
+pub mod prediction;
+pub mod interpolation;
+pub mod link_simulator;
+pub mod desync;
+pub mod lobby;
+pub mod race;
 
-pub fn client_func() {
-    let mut world = GameMap::new(100, ChunkDims::new(8, 8, 8), get_tile_voxels(), (255,255,255), 1, Road::new(Vec3D::zero(), Vec3Df::new(1.0, 0.0, 0.0)));
-    let mut perlin = Perlin::new(13095);
+// replay_file plays a recorded session instead of joining the server, see replay
+pub fn client_func(config:GameConfig, replay_file:Option<PathBuf>) {
+    let registry = voxel_registry();
+    let mut replay = match replay_file {
+        Some(file) => match ReplayPlayer::open(&file) {
+            Ok(player) => Some(player),
+            Err(error) => {
+                eprintln!("[Replay] Could not open {} : {}", file.display(), error);
+                std::process::exit(1);
+            }
+        },
+        None => None
+    };
+    let world_seed = replay.as_ref().map(|player| {player.get_world_seed()}).unwrap_or(config.world_seed);
+    let mut world = GameMap::new(100, ChunkDims::new(8, 8, 8), get_tile_voxels(), (40,40,40), 1, Road::new(Vec3D::zero(), Vec3Df::new(1.0, 0.0, 0.0), world_seed, RoadVoxels::from_registry(registry))).with_meshing_mode(config.meshing_mode()).with_registry_hash(registry.get_hash());
+    match &replay {
+        Some(player) => world.generator = player.get_generator(),
+        None => ()
+    }
+    let mut perlin = Perlin::new(world_seed as u32);
+    let (air, below_water) = (registry.id("air"), registry.id("water"));
+    // from the water level up
+    let ground_layers = ["sand", "flowers", "grassy_ground", "ground", "rock", "snow"].map(|id| {registry.id(id)});
     let mut world_height = 15.0;
     let mut water_level = 10.0;
     let start = Vec3D::new(-6, -5, -2);
@@ -41,16 +75,16 @@ pub fn client_func() {
                 if ground_at[ground_pos] < pos.z {
                     ground_at[ground_pos] = water_level as i32;
                 }
-                CoolVoxel {voxel_type:7, orient:0, light:VoxelLight::random_light()}
+                CoolVoxel {voxel_type:below_water, orient:0, light:VoxelLight::zero_light(), sky:0}
             }
             else {
                 if ground_at[ground_pos] < pos.z {
                     ground_at[ground_pos] = pos.z;
                 }
-                CoolVoxel {voxel_type:1 + ((actual_height - water_level)/(6.0*world_height * (1.0/6.0))).clamp(0.0, 5.99) as u16, orient:0, light:VoxelLight::random_light()}
+                CoolVoxel {voxel_type:ground_layers[((actual_height - water_level)/(6.0*world_height * (1.0/6.0))).clamp(0.0, 5.99) as usize], orient:0, light:VoxelLight::zero_light(), sky:0}
             }
         } else {
-            CoolVoxel {voxel_type:0, orient:0, light:VoxelLight::zero_light()}
+            CoolVoxel {voxel_type:air, orient:0, light:VoxelLight::zero_light(), sky:0}
         }
     }
     );
@@ -84,7 +118,7 @@ pub fn client_func() {
 
     }
 
-    let (payload_sender, response_receiver) = match ProximaLink::initialize(String::from("HORDE"), String::from("HORDE"), String::from("http://localhost:8085")) {
+    let (payload_sender, response_receiver) = match ProximaLink::initialize(config.llm_username.clone(), config.llm_password.clone(), config.llm_endpoint.clone()) {
         Ok((s, r)) => (s, r),
         Err(_) => (mpmc::channel().0, mpmc::channel().1)
     };
@@ -96,7 +130,7 @@ pub fn client_func() {
 
         writer.new_ent(NewVehicleEntity::new(VehiclePosition::new().with_pos(Vec3Df::new(0.0, 0.0, 40.0)), VehicleStats {static_id:0, nitro_left:100.0, mass:10.0},  MustSync::No, None));
     }
-    let windowing = WindowingHandler::new::<MiniFBWindow>(HordeWindowDimensions::new(1280, 720), HordeColorFormat::ARGB8888);
+    let windowing = WindowingHandler::new::<MiniFBWindow>(HordeWindowDimensions::new(config.window_width, config.window_height), HordeColorFormat::ARGB8888);
     let framebuf = windowing.get_outside_framebuf();
     let mut shader = Arc::new(GameShader::new_default());
     let viewport_data = {
@@ -117,13 +151,26 @@ pub fn client_func() {
     let (waves, waves_handler, stream) = Waves::new(Vec::new(), 10);
     let world_handler = WorldHandler::new(world);
     let (cs, cr) = channel();
+    let player_name = format!("The greatest player of all time{}", fastrand::i16(0..15000));
+    // the token stays secret, the server and the other clients only know our id, see game_map::players
+    let session_token = fastrand::u64(..);
+    let session_id = player_id(session_token);
+    let server_address = match config.link_conditions() {
+        Some(conditions) if replay.is_none() => match link_simulator::start_link_simulator((config.address, config.port), conditions) {
+            Ok(local) => local,
+            Err(error) => {
+                eprintln!("[Link] Could not start the network simulator : {}", error);
+                std::process::exit(1);
+            }
+        },
+        _ => (config.address, config.port)
+    };
     let engine = CoolGameEngineBase::new(
         entity_vec, entity_vec_2, world_handler.clone(), Arc::new(vectorinator.clone()), 
-        HordeMultiModeChoice::Client { adress: Some((Ipv4Addr::new(127, 0, 0, 1), 5678)), name: format!("The greatest player of all time{}", fastrand::i16(0..15000)), chat: cr },
-        ExtraData {payload_sender, tick: Arc::new(AtomicUsize::new(0)), waves:waves_handler.clone(), current_render_data:Arc::new(RwLock::new((Camera::empty(), viewport_data.clone())))}
+        HordeMultiModeChoice::Client { adress: if replay.is_none() {Some(server_address)} else {None}, name: player_name.clone(), chat: cr },
+        ExtraData {payload_sender, tick: Arc::new(AtomicUsize::new(0)), waves:Some(waves_handler.clone()), current_render_data:Some(Arc::new(RwLock::new((Camera::empty(), viewport_data.clone()))))}
     );
 
-    let tickrate = engine.multiplayer.get_tickrate();
     waves_handler.send_gec(engine.clone());
     let mouse = windowing.get_mouse_state();
     let mouse2 = windowing.get_mouse_state();
@@ -372,24 +419,93 @@ pub fn client_func() {
         475
     );
     let mut prev_night_status = false;
-    let tickrate_f = tickrate.unwrap() as f64;
+    // a replay is watched offline, at the tickrate it was recorded at
+    let tickrate_f = match &replay {
+        Some(player) => player.get_tickrate() as f64,
+        None => engine.multiplayer.get_tickrate().unwrap() as f64
+    };
+    let mut recorder = match &config.replay_file {
+        Some(file) => match ReplayRecorder::create(PathBuf::from(file), tickrate_f as usize, &mut world_handler.world.write().unwrap()) {
+            Ok(recorder) => Some(recorder),
+            Err(error) => {
+                eprintln!("[Replay] Could not record to {} : {}, the session will not be recorded", file, error);
+                None
+            }
+        },
+        None => None
+    };
     let mut need_tick = true;
+    // chunks this client dropped because they were out of view, asked back from the server when they come back in view
+    let mut dropped_by_view = HashSet::with_capacity(64);
+    // our own vehicle, moved locally before the server answers once the engine has it
+    let mut own_vehicle:Option<usize> = None;
+    let mut predictor:Option<VehiclePredictor> = None;
+    let mut interpolator = Interpolator::new(Duration::from_millis(config.interpolation_delay_ms), Duration::from_millis(config.max_extrapolation_ms), tickrate_f as usize);
+    let mut desync_detector = DesyncDetector::new(config.resync_on_desync);
+    let mut lobby_view = LobbyView::new(config.vehicle_type, config.match_setup());
+    let mut race_view = RaceView::new();
     for i in 0..75000 {
         println!("{i}");
 
         let mut start = Instant::now();
         input_handler.update_keyboard();
-        let (new_fog_col, new_normal_vec, new_night_state) = day_night.get_next_color();
+        match &mut replay {
+            Some(player) => {
+                player.handle_keys(&input_handler, &engine);
+                player.advance(&engine);
+            },
+            None => ()
+        }
+        // inputs sent last tick go into the prediction, which is put in the engine before the camera follows it
+        let sent_actions = input_handler.take_sent_actions();
+        let session_messages = SessionMessages::from_messages(world_handler.world.write().unwrap().take_session_messages());
+        match own_vehicle {
+            Some(vehicle) if predictor.as_ref().map(|predictor| {predictor.get_vehicle()}) != Some(vehicle) => {
+                let vehicles = engine.vehicles.get_read();
+                if vehicle < vehicles.position.len() {
+                    predictor = Some(VehiclePredictor::new(vehicle, &vehicles));
+                }
+            },
+            Some(_) => (),
+            None => predictor = None
+        }
+        match session_messages.vehicle_states.iter().filter(|state| {Some(state.vehicle) == own_vehicle}).last() {
+            Some(state) => desync_detector.line_up(state),
+            None => ()
+        }
+        match &mut predictor {
+            Some(predictor) => {
+                let vehicles = engine.vehicles.get_read();
+                let world_read = world_handler.world.read().unwrap();
+                match session_messages.vehicle_states.into_iter().filter(|state| {state.vehicle == predictor.get_vehicle()}).last() {
+                    Some(state) => predictor.reconcile(state, &vehicles, &world_read),
+                    None => ()
+                }
+                let tick = engine.extra_data.tick.load(Ordering::Relaxed).saturating_sub(1);
+                predictor.predict(tick, sent_actions, &vehicles, &world_read);
+                drop(vehicles);
+                let mut vehicles = engine.vehicles.get_write();
+                vehicles.position[predictor.get_vehicle()] = predictor.get_predicted_position();
+                vehicles.mesh_info[predictor.get_vehicle()].shown = Some(predictor.get_shown_pose());
+            },
+            None => ()
+        }
+        input_handler.set_frozen(lobby_view.is_counting_down());
+        let (new_fog_col, new_normal_vec, new_night_state, new_sky_light) = day_night.get_next_color();
         let new_camera = {
             let mut writer = vectorinator.get_write();
             //vectorinator.shader_data.do_normals.store(!new_night_state, Ordering::Relaxed);
             *vectorinator.shader_data.sun_dir.write().unwrap() = -new_normal_vec;
             *vectorinator.shader_data.fog_color.write().unwrap() = rgb_to_argb(new_fog_col);
+            *vectorinator.shader_data.sky_tint.write().unwrap() = new_sky_light;
             let read = engine.entity_1.get_read();
             let tick = engine.extra_data.tick.fetch_add(1, Ordering::Relaxed);
             let new_camera = input_handler.get_new_camera(&read, &engine.vehicles.get_read(), tick);
             *writer.camera = new_camera.clone();//(i as f32 / 500.0) * PI/2.0));
-            engine.extra_data.current_render_data.write().unwrap().0 = new_camera.clone();
+            match &engine.extra_data.current_render_data {
+                Some(render_data) => render_data.write().unwrap().0 = new_camera.clone(),
+                None => ()
+            }
 
             new_camera
         };
@@ -413,22 +529,113 @@ pub fn client_func() {
             }
             Err(_) => ()
         }
-        tile_editor.do_mouse_handling(&mut world_handler.world.write().unwrap(), world_handler.tunnels_out.clone());
-        tile_editor.handle_keyboard(&input_handler, &mut world_handler.world.write().unwrap(), world_handler.tunnels_out.clone());
+        // the world of a replay only changes with it
+        if replay.is_none() {
+            tile_editor.do_mouse_handling(&mut world_handler.world.write().unwrap(), world_handler.tunnels_out.clone());
+            tile_editor.handle_keyboard(&input_handler, &mut world_handler.world.write().unwrap(), world_handler.tunnels_out.clone());
+            lobby_view.handle_keys(&input_handler, session_token, &world_handler.tunnels_out);
+        }
+        let rejections = world_handler.world.write().unwrap().take_edit_rejections();
+        tile_editor.handle_edit_rejections(rejections);
+        for event in session_messages.players {
+            match event {
+                PlayerEvent::Assigned { id, driver, vehicle } if id == session_id => {
+                    if input_handler.get_driver() != Some(driver) {
+                        println!("[Players] Joined as driver {} with vehicle {}", driver, vehicle);
+                    }
+                    input_handler.set_driver(Some(driver));
+                    tile_editor.set_player(Some((session_token, driver)));
+                    own_vehicle = Some(vehicle);
+                },
+                PlayerEvent::Refused(id) if id == session_id => {
+                    eprintln!("[Players] The server is full");
+                    std::process::exit(1);
+                },
+                // the server stopped hearing from us, we join again on the next heartbeat
+                PlayerEvent::Left { id, .. } if id == session_id => {
+                    println!("[Players] The server dropped us, joining again");
+                    input_handler.set_driver(None);
+                    tile_editor.set_player(None);
+                    own_vehicle = None;
+                },
+                _ => ()
+            }
+        }
+        lobby_view.handle_events(session_messages.lobby, session_token, input_handler.get_driver(), &world_handler.tunnels_out);
+        race_view.handle_events(session_messages.race, input_handler.get_driver());
+        for checksums in session_messages.checksums {
+            let world_read = world_handler.world.read().unwrap();
+            match desync_detector.check(&checksums, &world_read, predictor.as_ref().map(|predictor| {predictor.get_vehicle()})) {
+                Some(request) => world_handler.tunnels_out.send_event(GameMapEvent::Session(SessionMessage::RequestResync(request))),
+                None => ()
+            }
+        }
         tile_editor.do_rendering(&vectorinator, &world_handler.world.read().unwrap());
-        if need_tick {
+        if i % (tickrate_f as usize) == 0 && replay.is_none() {
+            let mut world_write = world_handler.world.write().unwrap();
+            match world_write.get_registry_mismatch() {
+                Some(server_hash) => {
+                    eprintln!("[Voxels] The server uses another voxel registry (server hash {:016x}, ours {:016x} from {}), use the same voxels file as the server", server_hash, world_write.get_registry_hash(), config.voxel_registry);
+                    std::process::exit(1);
+                },
+                None => ()
+            }
+            match input_handler.get_driver() {
+                Some(_) => world_handler.tunnels_out.send_event(GameMapEvent::Session(SessionMessage::Player(PlayerEvent::Heartbeat(session_token)))),
+                None => world_handler.tunnels_out.send_event(GameMapEvent::Session(SessionMessage::Player(PlayerEvent::Join { id: session_id, name: player_name.clone() }))),
+            }
+            // requests are answered by the server, our own copies of them are useless
+            world_write.take_chunk_requests();
+            world_write.set_view_limit(Some((new_camera.pos, config.view_distance as f32)));
+            let far = world_write.get_chunks_farther_than(&[new_camera.pos], config.view_distance as f32);
+            for (c_pos, _) in world_write.unload_chunks(&far) {
+                dropped_by_view.insert(c_pos);
+            }
+            // chunks the server sent for other players, they were never added
+            dropped_by_view.extend(world_write.take_chunks_out_of_view());
+            let dims = world_write.get_chunk_dims_vector_f();
+            let camera_c = Vec3Df::new(new_camera.pos.x/dims.x, new_camera.pos.y/dims.y, 0.0);
+            let back_in_view:Vec<WorldChunkPos> = dropped_by_view.iter().filter(|c_pos| {Vec3Df::new(c_pos.x as f32 + 0.5, c_pos.y as f32 + 0.5, 0.0).dist(&camera_c) <= config.view_distance as f32}).cloned().collect();
+            for c_pos in &back_in_view {
+                dropped_by_view.remove(c_pos);
+            }
+            if !back_in_view.is_empty() {
+                world_handler.tunnels_out.send_event(GameMapEvent::RequestChunks(back_in_view));
+            }
+        }
+        interpolator.update(&mut engine.entity_1.get_write(), &mut engine.vehicles.get_write(), input_handler.get_driver(), predictor.as_ref().map(|predictor| {predictor.get_vehicle()}));
+        // components of a replay are set by it, the engine must not move them on its own
+        if need_tick && replay.is_none() {
             scheduler.initialise(queue.clone());
         }   
         else {
             scheduler.initialise(tickless_queue.clone());
         }
         scheduler.tick();
+        if replay.is_none() {
+            desync_detector.record(engine.extra_data.tick.load(Ordering::Relaxed).saturating_sub(1), &engine.entity_1.get_read(), &engine.vehicles.get_read());
+        }
+        if let Some(active) = &mut recorder && let Err(error) = active.record_tick(&engine, engine.extra_data.tick.load(Ordering::Relaxed)) {
+            eprintln!("[Replay] Could not record tick {} : {}, recording stopped", i, error);
+            recorder = None;
+        }
         let frametime = Instant::now().checked_duration_since(start).unwrap().as_secs_f64();
         let mut fps = 1.0/frametime;
         println!("FPS : {}", fps);
-        if fps > 100.0 {
-            thread::sleep(Duration::from_secs_f64(1.0/(90.0) - frametime));
+        if fps > tickrate_f {
+            thread::sleep(Duration::from_secs_f64(1.0/tickrate_f - frametime));
         }
     }
+    // one last tick so the server hears about it
+    world_handler.tunnels_out.send_event(GameMapEvent::Session(SessionMessage::Player(PlayerEvent::Leave(session_token))));
+    scheduler.initialise(tickless_queue.clone());
+    scheduler.tick();
     scheduler.end_threads();
+    match recorder {
+        Some(recorder) => match recorder.finish() {
+            Ok(()) => (),
+            Err(error) => eprintln!("[Replay] Could not finish the recording : {}", error)
+        },
+        None => ()
+    }
 }
\ No newline at end of file
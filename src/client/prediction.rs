@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+
+use hord3::horde::{game_engine::entity::ComponentEvent, geometry::{rotation::Orientation, vec3d::Vec3Df}};
+
+use crate::{driver::actions::{Action, ActionResult}, game_engine::{CoolGameEngineTID, CoolVoxel, GRAVITY}, game_map::{GameMap, road::Road, vehicle_states::VehicleState}, vehicle::{VehicleEntityVecRead, locomotion::{Locomotion, LocomotionEvent, LocomotionUpdate}, position::{VehiclePosEvent, VehiclePosUpdate, VehiclePosition}}};
+
+// inputs the server didn't answer after that many ticks are forgotten
+const MAX_UNACKNOWLEDGED_INPUTS:usize = 256;
+// share of the correction still shown after each tick
+const CORRECTION_DECAY:f32 = 0.85;
+// corrections bigger than that (in voxels) are applied at once instead of smoothed
+const SNAP_DISTANCE:f32 = 5.0;
+
+// Client side prediction of the vehicle of the local player
+// Inputs move the predicted vehicle right away with the same locomotion code as the engine (compute_tick then after_main_tick),
+// and are kept until a VehicleState from the server says it used them. The server state is then replayed with the remaining inputs,
+// the difference with the previous prediction being shown as an offset that fades away instead of a teleport
+pub struct VehiclePredictor {
+    vehicle:usize,
+    // one element vecs so the usual component events apply to them
+    position:Vec<VehiclePosition>,
+    locomotion:Vec<Locomotion>,
+    // inputs by client tick, oldest first
+    inputs:VecDeque<(usize, Vec<Action>)>,
+    last_state_tick:Option<usize>,
+    pos_offset:Vec3Df,
+    orient_offset:Orientation,
+}
+
+impl VehiclePredictor {
+    pub fn new<'a>(vehicle:usize, vehicles:&VehicleEntityVecRead<'a, CoolGameEngineTID>) -> Self {
+        Self { vehicle, position:vec![vehicles.position[vehicle].clone()], locomotion:vec![vehicles.locomotion[vehicle].clone()], inputs:VecDeque::with_capacity(MAX_UNACKNOWLEDGED_INPUTS), last_state_tick:None, pos_offset:Vec3Df::zero(), orient_offset:Orientation::zero() }
+    }
+    pub fn get_vehicle(&self) -> usize {
+        self.vehicle
+    }
+    pub fn predict<'a>(&mut self, tick:usize, inputs:Vec<Action>, vehicles:&VehicleEntityVecRead<'a, CoolGameEngineTID>, world:&GameMap<CoolVoxel, Road>) {
+        self.step(&inputs, vehicles, world);
+        self.inputs.push_back((tick, inputs));
+        if self.inputs.len() > MAX_UNACKNOWLEDGED_INPUTS {
+            self.inputs.pop_front();
+        }
+    }
+    pub fn reconcile<'a>(&mut self, state:VehicleState, vehicles:&VehicleEntityVecRead<'a, CoolGameEngineTID>, world:&GameMap<CoolVoxel, Road>) {
+        if state.vehicle != self.vehicle || self.last_state_tick.is_some_and(|last| {state.input_tick < last}) {
+            return
+        }
+        self.last_state_tick = Some(state.input_tick);
+        while self.inputs.front().is_some_and(|(tick, _)| {*tick <= state.input_tick}) {
+            self.inputs.pop_front();
+        }
+        let before = self.position[0].clone();
+        self.position[0] = state.position;
+        self.locomotion[0] = state.locomotion;
+        let remaining:Vec<Vec<Action>> = self.inputs.iter().map(|(_, inputs)| {inputs.clone()}).collect();
+        for inputs in &remaining {
+            self.step(inputs, vehicles, world);
+        }
+        let error = before.pos - self.position[0].pos;
+        if (error + self.pos_offset).norme() > SNAP_DISTANCE {
+            println!("[Prediction] Vehicle {} was {:.2} voxels off, snapping to the server", self.vehicle, error.norme());
+            self.pos_offset = Vec3Df::zero();
+            self.orient_offset = Orientation::zero();
+        }
+        else {
+            self.pos_offset += error;
+            self.orient_offset = Orientation::new(
+                self.orient_offset.yaw + before.orientation.yaw - self.position[0].orientation.yaw,
+                self.orient_offset.pitch + before.orientation.pitch - self.position[0].orientation.pitch,
+                self.orient_offset.roll + before.orientation.roll - self.position[0].orientation.roll
+            );
+        }
+    }
+    // what the engine simulates from
+    pub fn get_predicted_position(&self) -> VehiclePosition {
+        self.position[0].clone()
+    }
+    // where rendering and the camera show the vehicle (mesh_info.shown), the prediction plus what's left of the correction, call once per tick
+    pub fn get_shown_pose(&mut self) -> (Vec3Df, Orientation) {
+        let predicted = &self.position[0];
+        let shown = (predicted.pos + self.pos_offset, Orientation::new(predicted.orientation.yaw + self.orient_offset.yaw, predicted.orientation.pitch + self.orient_offset.pitch, predicted.orientation.roll + self.orient_offset.roll));
+        self.pos_offset *= CORRECTION_DECAY;
+        self.orient_offset = Orientation::new(self.orient_offset.yaw * CORRECTION_DECAY, self.orient_offset.pitch * CORRECTION_DECAY, self.orient_offset.roll * CORRECTION_DECAY);
+        shown
+    }
+    // one engine tick of the vehicle, inputs reach the locomotion at the end of compute_tick like the driver's actions do
+    fn step<'a>(&mut self, inputs:&Vec<Action>, vehicles:&VehicleEntityVecRead<'a, CoolGameEngineTID>, world:&GameMap<CoolVoxel, Road>) {
+        let stats = &vehicles.stats[self.vehicle];
+        let static_locomotion = &vehicles.static_types[stats.static_id].locomotion;
+
+        let (new_eqs, mut spd_change, turn_spd_change) = self.locomotion[0].get_vehicle_and_locomotion_changes(static_locomotion, world, stats, &self.position[0]);
+        spd_change.z -= GRAVITY;
+        self.update_locomotion(LocomotionUpdate::UpdateEverything(new_eqs));
+        self.update_locomotion(LocomotionUpdate::FlushActions);
+        for action in inputs {
+            self.update_locomotion(LocomotionUpdate::AddAction(action.clone(), ActionResult::Done));
+        }
+        self.update_position(VehiclePosUpdate::AddToEverySpeed(spd_change, turn_spd_change));
+
+        let (spd, turn_spd, equipment_pos_adds) = self.locomotion[0].get_vehicle_physics(static_locomotion, world, stats, &self.position[0]);
+        let (pos, orientation) = (self.position[0].pos + spd, self.position[0].orientation + turn_spd);
+        self.update_position(VehiclePosUpdate::UpdateEveryPos(pos, orientation));
+        self.update_position(VehiclePosUpdate::UpdateEverySpeed(spd, turn_spd));
+        self.update_locomotion(LocomotionUpdate::AddToEveryPos(equipment_pos_adds));
+    }
+    fn update_position(&mut self, update:VehiclePosUpdate) {
+        VehiclePosEvent::<CoolGameEngineTID>::new(0, None, update).apply_to_component(&mut self.position);
+    }
+    fn update_locomotion(&mut self, update:LocomotionUpdate) {
+        LocomotionEvent::<CoolGameEngineTID>::new(0, None, update).apply_to_component(&mut self.locomotion);
+    }
+}
@@ -0,0 +1,59 @@
+use crate::game_map::race::{RaceEvent, RaceStandings, format_race_time};
+
+// Logs where we are in the race from what the server sends, see server::race
+pub struct RaceView {
+    last_standings:Option<RaceStandings>,
+    // (place, racers) last logged
+    place:Option<(usize, usize)>,
+    finishers_logged:usize,
+}
+
+impl RaceView {
+    pub fn new() -> Self {
+        Self { last_standings:None, place:None, finishers_logged:0 }
+    }
+    pub fn handle_events(&mut self, events:Vec<RaceEvent>, driver:Option<usize>) {
+        for event in events {
+            match event {
+                RaceEvent::Standings(standings) => {
+                    let place = driver.and_then(|driver| {standings.positions.iter().position(|position| {position.driver == driver})}).map(|index| {(index + 1, standings.positions.len())});
+                    if let Some((place, racers)) = place && self.place != Some((place, racers)) {
+                        let own = &standings.positions[place - 1];
+                        if standings.laps > 0 {
+                            println!("[Race] Place {}/{}, lap {}/{}, {:.0} voxels in", place, racers, (own.lap + 1).min(standings.laps), standings.laps, own.distance);
+                        }
+                        else {
+                            println!("[Race] Place {}/{}, {:.0} voxels in", place, racers, own.distance);
+                        }
+                    }
+                    self.place = place;
+                    self.last_standings = Some(standings);
+                },
+                RaceEvent::Checkpoint { driver:passed_by, lap, checkpoint, time_ms } => if Some(passed_by) == driver {
+                    match self.last_standings.as_ref().map(|standings| {(standings.laps, standings.checkpoints_per_lap)}) {
+                        Some((laps, per_lap)) if laps > 0 => println!("[Race] Checkpoint {}/{} of lap {}/{} at {}", checkpoint, per_lap, lap, laps, format_race_time(time_ms)),
+                        _ => println!("[Race] Checkpoint {} at {}", checkpoint, format_race_time(time_ms))
+                    }
+                },
+                RaceEvent::Leaderboard { entries, complete } => {
+                    // a new race starts over
+                    if entries.len() < self.finishers_logged {
+                        self.finishers_logged = 0;
+                    }
+                    for entry in entries.iter().skip(self.finishers_logged) {
+                        let you = if Some(entry.driver) == driver {" (you)"} else {""};
+                        println!("[Race] {}{} finished in place {} in {}", entry.name, you, entry.place, format_race_time(entry.time_ms));
+                    }
+                    self.finishers_logged = entries.len();
+                    if complete {
+                        println!("[Race] Leaderboard :");
+                        for entry in &entries {
+                            println!("[Race] {}. {} in {}", entry.place, entry.name, format_race_time(entry.time_ms));
+                        }
+                        self.finishers_logged = 0;
+                    }
+                }
+            }
+        }
+    }
+}
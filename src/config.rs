@@ -0,0 +1,424 @@
+use std::{env, fmt::Display, fs, net::Ipv4Addr, path::PathBuf, str::FromStr, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{client::link_simulator::LinkConditions, game_map::{lobby::MatchSetup, mesher::MeshingMode, road::RoadBiome}};
+
+pub const USAGE:&str = "usage : hord3_multiplayer_game [server|client|replay <file>] [--config <file.json>] [--address <ipv4>] [--port <port>] [--tickrate <ticks per second>] [--tick-tolerance <ticks>] [--max-players <count>] [--seed <world seed>] [--llm-endpoint <url>] [--llm-username <name>] [--window-width <pixels>] [--window-height <pixels>] [--view-distance <chunks>] [--save-folder <folder>] [--voxels <file.json>] [--edit-reach <voxels>] [--edits-per-second <count>] [--interpolation-delay <ms>] [--max-extrapolation <ms>] [--sim-latency <ms>] [--sim-jitter <ms>] [--sim-loss <percent>] [--sim-reorder <percent>] [--checksum-interval <ticks>] [--record <file>] [--vehicle <type>] [--track-seed <seed>] [--biomes <desert,plains,city,ravine>] [--checkpoint-segments <count>] [--race-segments <count>] [--track-segments <count>] [--track <file>] [--save-track <file>] [--track-name <name>] [--laps <count>] [--export-road <file.json>] [--no-lobby] [--leavers-to-ai] [--resync-on-desync] [--no-save] [--headless] [--naive-meshing] [--compare-meshing]";
+
+// below that, the corners take most of a closed track
+const MIN_TRACK_SEGMENTS:usize = 16;
+// the LLM password is never given on the command line, where other users can read it, see parse_launch_args
+const LLM_PASSWORD_VAR:&str = "HORDE_LLM_PASSWORD";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LaunchMode {
+    Server,
+    Client,
+    // plays a file recorded with --record, see replay
+    Replay(PathBuf),
+}
+
+// Everything that used to be hard-coded in server_func and client_func
+// Fields missing from a config file keep their default value, unknown fields are an error
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameConfig {
+    pub address:Ipv4Addr,
+    pub port:u16,
+    pub tickrate:usize,
+    pub tick_tolerance:usize,
+    pub max_players:usize,
+    pub world_seed:u64,
+    pub llm_endpoint:String,
+    pub llm_username:String,
+    // only from the config file or HORDE_LLM_PASSWORD
+    pub llm_password:String,
+    pub window_width:usize,
+    pub window_height:usize,
+    pub headless:bool,
+    // in chunks, the server drops chunks farther than that from every vehicle, clients from their camera
+    pub view_distance:usize,
+    // server only, where chunks and the road are saved, nothing is saved if None (the default)
+    // a saved world keeps its own road, --track and --track-segments are refused with it
+    pub save_folder:Option<String>,
+    // merge coplanar voxel faces into bigger quads, off means one quad per face
+    pub greedy_meshing:bool,
+    // server only, prints naive vs greedy triangle counts for generated road chunks
+    pub compare_meshing:bool,
+    // voxel type registry, must be the same file on the server and every client
+    pub voxel_registry:String,
+    // server only, limits on the voxel edits of players, see server::voxel_edits
+    pub edit_reach:f32,
+    pub max_edits_per_second:usize,
+    // server only, an AI director takes over the driver of players who leave instead of keeping it for the next one joining
+    pub leavers_to_ai:bool,
+    // client only, other players' entities are shown that far in the past, interpolated between server positions
+    // and extrapolated for at most max_extrapolation_ms when no newer position came (0 to hold the last one instead)
+    pub interpolation_delay_ms:u64,
+    pub max_extrapolation_ms:u64,
+    // client only, bad network conditions between the client and the server for testing, see client::link_simulator
+    pub sim_latency_ms:u64,
+    pub sim_jitter_ms:u64,
+    pub sim_loss_percent:f32,
+    pub sim_reorder_percent:f32,
+    // server only, state checksums are sent to clients every that many ticks (0 to never send them), see game_map::checksums
+    pub checksum_interval:usize,
+    // client only, asks the server to send diverging entities and chunks again instead of only logging them
+    pub resync_on_desync:bool,
+    // server or client, the whole session is recorded to that file for the replay mode, see replay
+    pub replay_file:Option<String>,
+    // server only, players pick their vehicle and ready up before a countdown starts the race, off starts driving right away, see server::lobby
+    pub lobby:bool,
+    // client only, vehicle type picked in the lobby
+    pub vehicle_type:usize,
+    // client only, track asked for when hosting the lobby : road seed (the world seed if None) and biome names (every biome if empty)
+    pub track_seed:Option<u64>,
+    pub track_biomes:Vec<String>,
+    // server only, a checkpoint every that many road segments, and the finish after race_segments segments (0 for an endless race), see server::race
+    pub checkpoint_segments:usize,
+    pub race_segments:usize,
+    // server only, a closed track of about that many segments planned up front instead of an endless road (0 for an endless road), see Road::into_closed_track
+    // or a track file, saved with save_track or hand-authored (see game_map::track), raced laps times
+    pub track_segments:usize,
+    pub track_file:Option<String>,
+    pub race_laps:usize,
+    // server only, the closed track is saved to that file under that name ("Track <seed>" if None) once it can't change anymore
+    // a .json file is the hand-authored format, see game_map::track
+    pub save_track:Option<String>,
+    pub track_name:Option<String>,
+    // server only, the road as far as it was generated is written to that .json file when the server stops
+    pub export_road:Option<String>,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            address:Ipv4Addr::new(127, 0, 0, 1),
+            port:5678,
+            tickrate:90,
+            tick_tolerance:3,
+            max_players:30,
+            world_seed:13095,
+            llm_endpoint:String::from("http://localhost:8085"),
+            llm_username:String::from("HORDE"),
+            llm_password:String::from("HORDE"),
+            window_width:1280,
+            window_height:720,
+            headless:false,
+            view_distance:16,
+            save_folder:None,
+            greedy_meshing:true,
+            compare_meshing:false,
+            voxel_registry:String::from("voxels.json"),
+            edit_reach:12.0,
+            max_edits_per_second:20,
+            leavers_to_ai:false,
+            interpolation_delay_ms:100,
+            max_extrapolation_ms:50,
+            sim_latency_ms:0,
+            sim_jitter_ms:0,
+            sim_loss_percent:0.0,
+            sim_reorder_percent:0.0,
+            checksum_interval:90,
+            resync_on_desync:false,
+            replay_file:None,
+            lobby:true,
+            vehicle_type:0,
+            track_seed:None,
+            track_biomes:Vec::new(),
+            checkpoint_segments:10,
+            race_segments:0,
+            track_segments:0,
+            track_file:None,
+            race_laps:1,
+            save_track:None,
+            track_name:None,
+            export_road:None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    UnknownMode(String),
+    UnknownArgument(String),
+    MissingValue(String),
+    InvalidValue {argument:String, value:String, expected:&'static str},
+    CouldNotReadFile(PathBuf, std::io::Error),
+    CouldNotParseFile(PathBuf, serde_json::Error),
+    Invalid(String),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownMode(mode) => write!(f, "unknown mode \"{}\", expected \"server\", \"client\" or \"replay <file>\"", mode),
+            Self::UnknownArgument(arg) => write!(f, "unknown argument \"{}\"", arg),
+            Self::MissingValue(arg) => write!(f, "argument \"{}\" needs a value", arg),
+            Self::InvalidValue { argument, value, expected } => write!(f, "invalid value \"{}\" for \"{}\", expected {}", value, argument, expected),
+            Self::CouldNotReadFile(path, error) => write!(f, "could not read config file {} : {}", path.display(), error),
+            Self::CouldNotParseFile(path, error) => write!(f, "could not parse config file {} : {}", path.display(), error),
+            Self::Invalid(reason) => write!(f, "invalid configuration : {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+
+}
+
+impl GameConfig {
+    pub fn from_file(path:PathBuf) -> Result<Self, ConfigError> {
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(error) => return Err(ConfigError::CouldNotReadFile(path, error))
+        };
+        match serde_json::from_str(&text) {
+            Ok(config) => Ok(config),
+            Err(error) => Err(ConfigError::CouldNotParseFile(path, error))
+        }
+    }
+    pub fn validate(&self, mode:&LaunchMode) -> Result<(), ConfigError> {
+        if self.port == 0 {
+            return Err(ConfigError::Invalid(String::from("port must not be 0")));
+        }
+        if self.tickrate == 0 || self.tickrate > 1000 {
+            return Err(ConfigError::Invalid(format!("tickrate must be between 1 and 1000, got {}", self.tickrate)));
+        }
+        if self.max_players == 0 {
+            return Err(ConfigError::Invalid(String::from("max_players must be at least 1")));
+        }
+        if self.window_width == 0 || self.window_height == 0 {
+            return Err(ConfigError::Invalid(format!("window size must not be empty, got {}x{}", self.window_width, self.window_height)));
+        }
+        if !(self.llm_endpoint.starts_with("http://") || self.llm_endpoint.starts_with("https://")) {
+            return Err(ConfigError::Invalid(format!("llm_endpoint must be an http(s) url, got \"{}\"", self.llm_endpoint)));
+        }
+        if self.view_distance < 2 {
+            return Err(ConfigError::Invalid(format!("view_distance must be at least 2 chunks, got {}", self.view_distance)));
+        }
+        if let Some(folder) = &self.save_folder && folder.trim().is_empty() {
+            return Err(ConfigError::Invalid(String::from("save_folder must not be empty, use --no-save to disable saving")));
+        }
+        if self.voxel_registry.trim().is_empty() {
+            return Err(ConfigError::Invalid(String::from("voxel_registry must not be empty")));
+        }
+        if !(self.edit_reach > 0.0) {
+            return Err(ConfigError::Invalid(format!("edit_reach must be positive, got {}", self.edit_reach)));
+        }
+        if self.max_edits_per_second == 0 {
+            return Err(ConfigError::Invalid(String::from("max_edits_per_second must be at least 1")));
+        }
+        if self.interpolation_delay_ms > 1000 {
+            return Err(ConfigError::Invalid(format!("interpolation_delay_ms must be at most 1000, got {}", self.interpolation_delay_ms)));
+        }
+        if self.max_extrapolation_ms > 1000 {
+            return Err(ConfigError::Invalid(format!("max_extrapolation_ms must be at most 1000, got {}", self.max_extrapolation_ms)));
+        }
+        if !(0.0..100.0).contains(&self.sim_loss_percent) {
+            return Err(ConfigError::Invalid(format!("sim_loss_percent must be at least 0 and below 100, got {}", self.sim_loss_percent)));
+        }
+        if !(0.0..=100.0).contains(&self.sim_reorder_percent) {
+            return Err(ConfigError::Invalid(format!("sim_reorder_percent must be between 0 and 100, got {}", self.sim_reorder_percent)));
+        }
+        if self.link_conditions().is_some() && *mode == LaunchMode::Server {
+            return Err(ConfigError::Invalid(String::from("network simulation (--sim-*) is done by clients, start the server without it")));
+        }
+        if let Some(file) = &self.replay_file && file.trim().is_empty() {
+            return Err(ConfigError::Invalid(String::from("replay_file must not be empty")));
+        }
+        if self.replay_file.is_some() && matches!(mode, LaunchMode::Replay(_)) {
+            return Err(ConfigError::Invalid(String::from("--record can't be used while watching a replay")));
+        }
+        if let Some(name) = self.track_biomes.iter().find(|name| {RoadBiome::from_name(name).is_none()}) {
+            return Err(ConfigError::Invalid(format!("unknown biome \"{}\" in track_biomes, expected desert, plains, city or ravine", name)));
+        }
+        if self.checkpoint_segments == 0 {
+            return Err(ConfigError::Invalid(String::from("checkpoint_segments must be at least 1")));
+        }
+        if self.track_segments > 0 && self.track_segments < MIN_TRACK_SEGMENTS {
+            return Err(ConfigError::Invalid(format!("track_segments must be 0 for an endless road or at least {}, got {}", MIN_TRACK_SEGMENTS, self.track_segments)));
+        }
+        if let Some(file) = &self.track_file && file.trim().is_empty() {
+            return Err(ConfigError::Invalid(String::from("track_file must not be empty")));
+        }
+        if self.track_file.is_some() && self.track_segments > 0 {
+            return Err(ConfigError::Invalid(String::from("--track races a saved track, it can't be used with --track-segments")));
+        }
+        if self.race_laps == 0 {
+            return Err(ConfigError::Invalid(String::from("race_laps must be at least 1")));
+        }
+        if self.race_laps > 1 && !self.closed_track() {
+            return Err(ConfigError::Invalid(String::from("several laps need a closed track, use --track-segments or --track")));
+        }
+        if let Some(file) = &self.save_track && file.trim().is_empty() {
+            return Err(ConfigError::Invalid(String::from("save_track must not be empty")));
+        }
+        if self.save_track.is_some() && self.track_segments == 0 {
+            return Err(ConfigError::Invalid(String::from("--save-track saves a closed track planned with --track-segments")));
+        }
+        if let Some(file) = &self.export_road && !file.trim().ends_with(".json") {
+            return Err(ConfigError::Invalid(format!("export_road must be a .json file, got \"{}\"", file)));
+        }
+        if self.headless && *mode != LaunchMode::Server {
+            return Err(ConfigError::Invalid(String::from("--headless only makes sense for the server")));
+        }
+        Ok(())
+    }
+    // None when the link to the server is left alone
+    pub fn link_conditions(&self) -> Option<LinkConditions> {
+        let conditions = LinkConditions { latency:Duration::from_millis(self.sim_latency_ms), jitter:Duration::from_millis(self.sim_jitter_ms), loss_percent:self.sim_loss_percent, reorder_percent:self.sim_reorder_percent };
+        if conditions.is_perfect() {
+            None
+        }
+        else {
+            Some(conditions)
+        }
+    }
+    // None when the host leaves the track to the server
+    pub fn match_setup(&self) -> Option<MatchSetup> {
+        if self.track_seed.is_none() && self.track_biomes.is_empty() {
+            None
+        }
+        else {
+            Some(MatchSetup { seed:self.track_seed.unwrap_or(self.world_seed), biomes:self.track_biomes.iter().filter_map(|name| {RoadBiome::from_name(name)}).collect() })
+        }
+    }
+    pub fn closed_track(&self) -> bool {
+        self.track_segments > 0 || self.track_file.is_some()
+    }
+    pub fn meshing_mode(&self) -> MeshingMode {
+        if self.greedy_meshing {
+            MeshingMode::Greedy
+        }
+        else {
+            MeshingMode::Naive
+        }
+    }
+}
+
+fn parse_value<T:FromStr>(argument:&str, value:Option<&String>, expected:&'static str) -> Result<T, ConfigError> {
+    match value {
+        Some(value) => match value.trim().parse() {
+            Ok(parsed) => Ok(parsed),
+            Err(_) => Err(ConfigError::InvalidValue { argument: argument.to_string(), value: value.clone(), expected })
+        },
+        None => Err(ConfigError::MissingValue(argument.to_string()))
+    }
+}
+
+// args is everything after the executable name
+// the config file is read first, then every other argument overrides it
+pub fn parse_launch_args(args:&[String]) -> Result<(LaunchMode, GameConfig), ConfigError> {
+    let (mode, args) = match args.first() {
+        Some(mode) if !mode.starts_with("--") => match mode.trim() {
+            "server" => (LaunchMode::Server, &args[1..]),
+            "client" => (LaunchMode::Client, &args[1..]),
+            "replay" => match args.get(1) {
+                Some(file) if !file.starts_with("--") => (LaunchMode::Replay(PathBuf::from(file.trim())), &args[2..]),
+                _ => return Err(ConfigError::MissingValue(String::from("replay")))
+            },
+            other => return Err(ConfigError::UnknownMode(other.to_string()))
+        },
+        _ => (LaunchMode::Client, args)
+    };
+
+    let mut config = GameConfig::default();
+    let mut i = 0;
+    while i < args.len() {
+        if args[i].trim() == "--config" {
+            let path:PathBuf = parse_value("--config", args.get(i + 1), "a file path")?;
+            config = GameConfig::from_file(path)?;
+        }
+        i += 1;
+    }
+    match env::var(LLM_PASSWORD_VAR) {
+        Ok(password) => config.llm_password = password,
+        Err(_) => ()
+    }
+
+    let mut i = 0;
+    while i < args.len() {
+        let argument = args[i].trim();
+        let value = args.get(i + 1);
+        match argument {
+            "--headless" => {
+                config.headless = true;
+                i += 1;
+                continue;
+            },
+            "--no-save" => {
+                config.save_folder = None;
+                i += 1;
+                continue;
+            },
+            "--naive-meshing" => {
+                config.greedy_meshing = false;
+                i += 1;
+                continue;
+            },
+            "--compare-meshing" => {
+                config.compare_meshing = true;
+                i += 1;
+                continue;
+            },
+            "--leavers-to-ai" => {
+                config.leavers_to_ai = true;
+                i += 1;
+                continue;
+            },
+            "--resync-on-desync" => {
+                config.resync_on_desync = true;
+                i += 1;
+                continue;
+            },
+            "--no-lobby" => {
+                config.lobby = false;
+                i += 1;
+                continue;
+            },
+            "--config" => (),
+            "--address" => config.address = parse_value(argument, value, "an IPv4 address like 127.0.0.1")?,
+            "--port" => config.port = parse_value(argument, value, "a port between 1 and 65535")?,
+            "--tickrate" => config.tickrate = parse_value(argument, value, "a positive number of ticks per second")?,
+            "--tick-tolerance" => config.tick_tolerance = parse_value(argument, value, "a positive number of ticks")?,
+            "--max-players" => config.max_players = parse_value(argument, value, "a positive number of players")?,
+            "--seed" => config.world_seed = parse_value(argument, value, "a positive integer")?,
+            "--llm-endpoint" => config.llm_endpoint = parse_value(argument, value, "an url")?,
+            "--llm-username" => config.llm_username = parse_value(argument, value, "a username")?,
+            "--llm-password" => return Err(ConfigError::Invalid(format!("the LLM password can't be given on the command line, put it in the config file or in {}", LLM_PASSWORD_VAR))),
+            "--window-width" => config.window_width = parse_value(argument, value, "a width in pixels")?,
+            "--window-height" => config.window_height = parse_value(argument, value, "a height in pixels")?,
+            "--view-distance" => config.view_distance = parse_value(argument, value, "a positive number of chunks")?,
+            "--save-folder" => config.save_folder = Some(parse_value(argument, value, "a folder path")?),
+            "--voxels" => config.voxel_registry = parse_value(argument, value, "a file path")?,
+            "--edit-reach" => config.edit_reach = parse_value(argument, value, "a positive number of voxels")?,
+            "--edits-per-second" => config.max_edits_per_second = parse_value(argument, value, "a positive number of edits")?,
+            "--interpolation-delay" => config.interpolation_delay_ms = parse_value(argument, value, "a number of milliseconds")?,
+            "--max-extrapolation" => config.max_extrapolation_ms = parse_value(argument, value, "a number of milliseconds")?,
+            "--sim-latency" => config.sim_latency_ms = parse_value(argument, value, "a number of milliseconds")?,
+            "--sim-jitter" => config.sim_jitter_ms = parse_value(argument, value, "a number of milliseconds")?,
+            "--sim-loss" => config.sim_loss_percent = parse_value(argument, value, "a percentage")?,
+            "--sim-reorder" => config.sim_reorder_percent = parse_value(argument, value, "a percentage")?,
+            "--record" => config.replay_file = Some(parse_value(argument, value, "a file path")?),
+            "--checksum-interval" => config.checksum_interval = parse_value(argument, value, "a number of ticks")?,
+            "--checkpoint-segments" => config.checkpoint_segments = parse_value(argument, value, "a positive number of road segments")?,
+            "--race-segments" => config.race_segments = parse_value(argument, value, "a number of road segments")?,
+            "--track-segments" => config.track_segments = parse_value(argument, value, "a number of road segments")?,
+            "--track" => config.track_file = Some(parse_value(argument, value, "a file path")?),
+            "--save-track" => config.save_track = Some(parse_value(argument, value, "a file path")?),
+            "--track-name" => config.track_name = Some(parse_value(argument, value, "a track name")?),
+            "--export-road" => config.export_road = Some(parse_value(argument, value, "a .json file path")?),
+            "--laps" => config.race_laps = parse_value(argument, value, "a positive number of laps")?,
+            "--vehicle" => config.vehicle_type = parse_value(argument, value, "a vehicle type number")?,
+            "--track-seed" => config.track_seed = Some(parse_value(argument, value, "a positive integer")?),
+            "--biomes" => config.track_biomes = parse_value::<String>(argument, value, "a comma separated list of biomes")?.split(',').map(|name| {name.trim().to_string()}).filter(|name| {!name.is_empty()}).collect(),
+            other => return Err(ConfigError::UnknownArgument(other.to_string()))
+        }
+        i += 2;
+    }
+
+    config.validate(&mode)?;
+    Ok((mode, config))
+}
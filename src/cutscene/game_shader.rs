@@ -8,13 +8,14 @@ pub struct GameShader {
     pub fog_distance:Arc<RwLock<f32>>,
     pub fog_color:Arc<RwLock<u32>>,
     pub sun_dir:Arc<RwLock<Vec3Df>>,
-    pub do_normals:Arc<AtomicBool>
-    
+    pub do_normals:Arc<AtomicBool>,
+    // colour of the time of day, multiplies the light baked in the meshes (block light included, the shader can't tell them apart)
+    pub sky_tint:Arc<RwLock<(u8,u8,u8)>>,
 }
 
 impl GameShader {
     pub fn new_default() -> Self {
-        Self {  sun_dir:Arc::new(RwLock::new(Vec3Df::all_ones().normalise())), activated: Arc::new(AtomicBool::new(true)), fog_distance: Arc::new(RwLock::new(1000.0)), fog_color: Arc::new(RwLock::new(rgb_to_argb((53, 81, 92)))), do_normals: Arc::new(AtomicBool::new(true)) }
+        Self {  sun_dir:Arc::new(RwLock::new(Vec3Df::all_ones().normalise())), activated: Arc::new(AtomicBool::new(true)), fog_distance: Arc::new(RwLock::new(1000.0)), fog_color: Arc::new(RwLock::new(rgb_to_argb((53, 81, 92)))), do_normals: Arc::new(AtomicBool::new(true)), sky_tint: Arc::new(RwLock::new((255, 255, 255))) }
     }
 }
 
@@ -32,7 +33,7 @@ impl ShaderData for GameShader {
             sun_dir,
             sun_dir_norm:1.0/sun_dir.norme(),
             do_normals:self.do_normals.load(Ordering::Relaxed),
-
+            sky_tint_f:rgbu_to_rgbf(self.sky_tint.read().unwrap().clone()),
         }
     }
     fn get_raw_frame_data(&self) -> Self::SFD {
@@ -47,6 +48,7 @@ impl ShaderData for GameShader {
             sun_dir,
             sun_dir_norm:1.0/sun_dir.norme(),
             do_normals:self.do_normals.load(Ordering::Relaxed),
+            sky_tint_f:rgbu_to_rgbf(self.sky_tint.read().unwrap().clone()),
         }
     }
 
@@ -60,7 +62,8 @@ pub struct GameShaderFrameData {
     pub fog_color_f:(f32,f32,f32),
     pub sun_dir:Vec3Df,
     pub sun_dir_norm:f32,
-    pub do_normals:bool
+    pub do_normals:bool,
+    pub sky_tint_f:(f32,f32,f32),
 }
 
 impl ShaderFrameData for GameShaderFrameData {
@@ -74,6 +77,7 @@ impl ShaderFrameData for GameShaderFrameData {
                     let coefficient = old_depth * self.inv_fog_distance;
                     let one_m_coef = 1.0 - coefficient;
                     let (fr, fg, fb) = rgbu_to_rgbf(argb_to_rgb(old_color));
+                    let (fr, fg, fb) = (fr * self.sky_tint_f.0, fg * self.sky_tint_f.1, fb * self.sky_tint_f.2);
                     if self.do_normals {
                         let normal = old_normal.to_le_bytes().map(|byte| {std::mem::transmute::<u8, i8>(byte)});
                         let normal_vec = Vec3Df::new(normal[0] as f32, normal[1] as f32, normal[2] as f32);
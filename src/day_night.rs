@@ -9,14 +9,35 @@ pub struct DayNight {
     night_light_dir:Vec3Df,
     current_tick:usize,
     half_time:usize,
+    // colour of full sky light for each part of the day, see GameShader::sky_tint
+    day_sky:(f32,f32,f32),
+    sunset_sky:(f32,f32,f32),
+    night_sky:(f32,f32,f32),
+}
+
+// how strong the sky light is compared to its colour
+const DAY_SKY_INTENSITY:f32 = 1.0;
+const SUNSET_SKY_INTENSITY:f32 = 0.75;
+const NIGHT_SKY_INTENSITY:f32 = 0.5;
+
+fn sky_from(color:(u8,u8,u8), intensity:f32) -> (f32,f32,f32) {
+    let color = collux_u8_a_f32(color);
+    (color.0 * intensity, color.1 * intensity, color.2 * intensity)
+}
+
+fn blend(first:(f32,f32,f32), second:(f32,f32,f32), first_factor:f32) -> (u8,u8,u8) {
+    let second_factor = 1.0 - first_factor;
+    collux_f32_a_u8((first_factor * first.0 + second_factor * second.0, first_factor * first.1 + second_factor * second.1, first_factor * first.2 + second_factor * second.2))
 }
 
 impl DayNight {
     pub fn new(daylight_color:(u8, u8, u8), sunset_color:(u8, u8, u8), night_color:(u8,u8,u8), noon_light_dir:Vec3Df, sunset_light_dir:Vec3Df, night_light_dir:Vec3Df,  half_time:usize) -> Self {
-        Self { daylight_color, sunset_color, night_color, noon_light_dir, sunset_light_dir, night_light_dir, current_tick:0, half_time  }
+        Self { daylight_color, sunset_color, night_color, noon_light_dir, sunset_light_dir, night_light_dir, current_tick:0, half_time, day_sky:sky_from((255,255,255), DAY_SKY_INTENSITY), sunset_sky:sky_from(sunset_color, SUNSET_SKY_INTENSITY), night_sky:sky_from(night_color, NIGHT_SKY_INTENSITY) }
     }
-    pub fn get_next_color(&mut self) -> ((u8,u8,u8), Vec3Df, bool) {
+    // (fog colour, sun direction, is it night, sky light colour)
+    pub fn get_next_color(&mut self) -> ((u8,u8,u8), Vec3Df, bool, (u8,u8,u8)) {
         let mut color = (0, 0, 0);
+        let mut sky = (0, 0, 0);
         let mut vector = Vec3Df::all_ones().normalise();
         let mut is_night = false;
         if self.current_tick < self.half_time/2 {
@@ -26,6 +47,7 @@ impl DayNight {
             let sunset = collux_u8_a_f32(self.sunset_color);
             color = collux_f32_a_u8((daylight_factor * daylight.0 + sunset_factor * sunset.0, daylight_factor * daylight.1 + sunset_factor * sunset.1, daylight_factor * daylight.2 + sunset_factor * sunset.2));
             vector = (self.noon_light_dir.normalise() * daylight_factor + self.sunset_light_dir.normalise() * sunset_factor).normalise();
+            sky = blend(self.day_sky, self.sunset_sky, daylight_factor);
         }
         else if self.current_tick < self.half_time {
             let daylight_factor = ((self.half_time/2 - (self.current_tick - self.half_time/2)) as f32)/(self.half_time as f32/2.0);
@@ -34,6 +56,7 @@ impl DayNight {
             let sunset = collux_u8_a_f32(self.night_color);
             color = collux_f32_a_u8((daylight_factor * daylight.0 + sunset_factor * sunset.0, daylight_factor * daylight.1 + sunset_factor * sunset.1, daylight_factor * daylight.2 + sunset_factor * sunset.2));
             vector = (self.sunset_light_dir.normalise() * daylight_factor + self.night_light_dir.normalise() * sunset_factor).normalise();
+            sky = blend(self.sunset_sky, self.night_sky, daylight_factor);
             is_night = true;
         }
         else if self.current_tick < self.half_time + self.half_time/2 {
@@ -43,6 +66,7 @@ impl DayNight {
             let sunset = collux_u8_a_f32(self.sunset_color);
             color = collux_f32_a_u8((daylight_factor * daylight.0 + sunset_factor * sunset.0, daylight_factor * daylight.1 + sunset_factor * sunset.1, daylight_factor * daylight.2 + sunset_factor * sunset.2));
             vector = (self.night_light_dir.normalise() * daylight_factor + self.sunset_light_dir.normalise() * (-sunset_factor)).normalise();
+            sky = blend(self.night_sky, self.sunset_sky, daylight_factor);
             is_night = true;
         }
         else {
@@ -52,12 +76,13 @@ impl DayNight {
             let sunset = collux_u8_a_f32(self.daylight_color);
             color = collux_f32_a_u8((daylight_factor * daylight.0 + sunset_factor * sunset.0, daylight_factor * daylight.1 + sunset_factor * sunset.1, daylight_factor * daylight.2 + sunset_factor * sunset.2));
             vector = (-self.sunset_light_dir.normalise() * daylight_factor + self.noon_light_dir.normalise() * sunset_factor).normalise();
+            sky = blend(self.sunset_sky, self.day_sky, daylight_factor);
             
         }
         self.current_tick += 1;
         if self.current_tick >= self.half_time*2{
             self.current_tick = 0;
         }
-        (color, vector, is_night)
+        (color, vector, is_night, sky)
     }
 }
\ No newline at end of file
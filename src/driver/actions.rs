@@ -32,6 +32,9 @@ impl Action {
     pub fn get_id(&self) -> usize {
         self.id
     }
+    pub fn get_started_at(&self) -> usize {
+        self.started_at
+    }
     pub fn needs_planning(&self) -> bool {
         match &self.kind {
             ActionKind::PathToPosition(pos, tolerance) => true,
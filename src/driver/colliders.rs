@@ -249,7 +249,7 @@ impl BoundingSphere {
             self.center - Vec3Df::new(0.0, 0.0, self.radius)
         ];
         for point in points {
-            if world.is_voxel_solid(get_voxel_pos(point)) {
+            if world.is_point_solid(point) {
                 return  true;
             }
         }
@@ -154,7 +154,8 @@ impl<ID:Identify> Component<ID> for Collider {
 #[derive(Clone, PartialEq, ToBytes, FromBytes)]
 pub struct MeshInfo {
     instance_id:Option<usize>,
-
+    // rendered there instead of the movement when set, see client::interpolation
+    pub shown:Option<(Vec3Df, Orientation)>,
 }
 
 #[derive(Clone)]
@@ -197,7 +198,7 @@ impl<ID:Identify> Component<ID> for MeshInfo {
     type CE = MeshEvent;
     type SC = StaticMeshInfo;
     fn from_static(static_comp:&Self::SC) -> Self {
-        Self { instance_id: None }
+        Self { instance_id: None, shown: None }
     }
 }
 
@@ -208,7 +209,7 @@ impl<ID:Identify> NewEntity<GameEntity,ID> for NewGameEntity<ID> {
         GameEntity {
             movement:self.movement,
             stats:self.stats,
-            mesh_info:MeshInfo { instance_id: None },
+            mesh_info:MeshInfo { instance_id: None, shown: None },
             collider:self.collider,
             actions:Actions::new(),
             director:self.director,
@@ -219,17 +220,18 @@ impl<ID:Identify> NewEntity<GameEntity,ID> for NewGameEntity<ID> {
 
 impl<'a, ID:Identify> RenderGameEntity<VectorinatorWrite<'a>, ID> for GameEntity {
     fn do_render_changes(rendering_data: &mut VectorinatorWrite<'a>,movement: &mut Movement,stats: &mut Stats,mesh_info: &mut MeshInfo,static_type: &StaticGameEntity<ID>) {
+        let (pos, orient) = mesh_info.shown.unwrap_or((movement.pos, movement.orient));
         match mesh_info.instance_id {
             Some(id) => {
                 let mut instance = rendering_data.meshes.instances[2].get_instance_mut(id);
-                instance.change_pos(movement.pos);
-                instance.change_orient(movement.orient);
+                instance.change_pos(pos);
+                instance.change_orient(orient);
             },
             None => {
                 if !rendering_data.meshes.does_mesh_exist(&static_type.mesh_info.mesh_id) {
                     rendering_data.meshes.add_mesh(static_type.mesh_info.mesh_data.clone());
                 }
-                mesh_info.instance_id = Some(rendering_data.meshes.add_instance(MeshInstance::new(movement.pos, movement.orient, static_type.mesh_info.mesh_id.clone(), true, false, false), 2))
+                mesh_info.instance_id = Some(rendering_data.meshes.add_instance(MeshInstance::new(pos, orient, static_type.mesh_info.mesh_id.clone(), true, false, false), 2))
             }
         }
     }
@@ -20,11 +20,18 @@ pub struct StatEvent<ID:Identify> {
     variant:StatEventVariant
 }
 
+impl<ID:Identify> StatEvent<ID> {
+    pub fn new(id:usize, source:Option<ID>, variant:StatEventVariant) -> Self {
+        Self { id, source, variant }
+    }
+}
+
 #[derive(Clone, ToBytes, FromBytes, PartialEq)]
 pub enum StatEventVariant {
     UpdateHealth(i32),
     UpdateDamage(i32),
-    UpdateStamina(i32)
+    UpdateStamina(i32),
+    UpdatePersonalVehicle(Option<usize>),
 }
 
 #[derive(Clone)]
@@ -49,6 +56,7 @@ impl<ID:Identify> ComponentEvent<Stats, ID> for StatEvent<ID> {
             StatEventVariant::UpdateDamage(new_dmg) => components[self.id].damage = new_dmg,
             StatEventVariant::UpdateHealth(new_health) => components[self.id].health = new_health,
             StatEventVariant::UpdateStamina(new_stam) => components[self.id].stamina = new_stam,
+            StatEventVariant::UpdatePersonalVehicle(vehicle) => components[self.id].personal_vehicle = vehicle,
         }
     }
 }
@@ -4,7 +4,7 @@ use engine_derive::GameEngine;
 use hord3::{defaults::default_rendering::vectorinator_binned::{Vectorinator, rendering_spaces::ViewportData, shaders::NoOpShader}, horde::{game_engine::{engine::{GameEngine, MovingObjectID}, entity::{Entity, EntityVec, MultiplayerEntity, Renderable, SimpleComponentEvent}, multiplayer::{GlobalComponent, GlobalEvent, HordeEventReport, HordeMultiModeChoice, HordeMultiplayer, HordeMultiplayerMode, Identify, MultiplayerEngine, MustSync}, static_type_id::HasStaticTypeID, world::{World, WorldComputeHandler, WorldEvent, WorldHandler, WorldOutHandler, WorldWriteHandler}}, geometry::{rotation::{Orientation, Rotation}, vec3d::{Vec3D, Vec3Df}}, rendering::camera::Camera, scheduler::IndividualTask, sound::{ARWWaves, WavesHandler}}};
 use to_from_bytes_derive::{FromBytes, ToBytes};
 
-use crate::{cutscene::{game_shader::GameShader, reverse_camera_coords::reverse_from_raster_to_worldpos}, driver::{Collider, ColliderEvent, ColliderEventVariant, GameEntity, GameEntityEvent, GameEntityVecRead, GameEntityVecWrite, MovementEvent, MovementEventVariant, actions::{Action, ActionKind, ActionSource, ActionTimer, ActionsEvent, ActionsUpdate}, colliders::AABB}, game_map::{GameMap, GameMapEvent, Voxel, VoxelLight, VoxelModel, VoxelType, get_voxel_pos, road::Road}, proxima_link::HordeProximaAIRequest, vehicle::{VehicleEntity, VehicleEntityEvent, VehicleEntityVecRead, VehicleEntityVecWrite, hull::HullUpdate, locomotion::{SurfaceData, SurfaceSubType, SurfaceType}, position::{VehiclePosEvent, VehiclePosUpdate}}};
+use crate::{cutscene::{game_shader::GameShader, reverse_camera_coords::reverse_from_raster_to_worldpos}, driver::{Collider, ColliderEvent, ColliderEventVariant, GameEntity, GameEntityEvent, GameEntityVecRead, GameEntityVecWrite, MovementEvent, MovementEventVariant, actions::{Action, ActionKind, ActionSource, ActionTimer, ActionsEvent, ActionsUpdate}, colliders::AABB}, game_map::{GameMap, GameMapEvent, Voxel, VoxelLight, VoxelModel, VoxelType, get_voxel_pos, road::Road, voxel_models::CustomModel}, proxima_link::HordeProximaAIRequest, vehicle::{VehicleEntity, VehicleEntityEvent, VehicleEntityVecRead, VehicleEntityVecWrite, hull::HullUpdate, locomotion::{SurfaceData, SurfaceSubType, SurfaceType}, position::{VehiclePosEvent, VehiclePosUpdate}}};
 
 
 #[derive(Clone, FromBytes, ToBytes, PartialEq, Debug)]
@@ -12,6 +12,7 @@ pub struct CoolVoxel {
     pub voxel_type:u16,
     pub orient:u8,
     pub light:VoxelLight,
+    pub sky:u8,
     //pub extra_voxel_data:Option<Vec<ExtraVoxelData>>,
 }
 
@@ -73,7 +74,7 @@ pub enum ExtraVoxelData {
 
 impl CoolVoxel {
     pub fn new(voxel_type:u16, orient:u8, light:VoxelLight, extra_voxel_data:Option<Vec<ExtraVoxelData>>) -> Self {
-        Self { voxel_type, orient, light, }//extra_voxel_data }
+        Self { voxel_type, orient, light, sky:0 }//extra_voxel_data }
     }
 }
 
@@ -88,11 +89,26 @@ pub struct CoolVoxelType {
     pub base_extra_voxel_data:Option<ExtraVoxelData>,
     pub surface_type:SurfaceType,
     pub surface_subtype:SurfaceSubType,
+    // texture of every side of the voxel model in EXPLORATION order, turned with the voxel orientation
+    pub face_textures:Option<Vec<usize>>,
+    // shape of the voxel when it isn't a full cube, see game_map::voxel_models
+    pub model:Option<CustomModel>,
 }
 
 impl CoolVoxelType {
     pub fn new(empty_sides:u8, texture:usize, light_passthrough:VoxelLight, is_light_source:Option<VoxelLight>, name:String, texture_path:Option<PathBuf>, base_extra_voxel_data:Option<ExtraVoxelData>, surface_type:SurfaceType, surface_subtype:SurfaceSubType) -> Self {
-        Self { empty_sides, texture, light_passthrough, is_light_source, name, texture_path:texture_path.map(|path| {path.to_string_lossy().to_string()}), base_extra_voxel_data, surface_type, surface_subtype }
+        Self { empty_sides, texture, light_passthrough, is_light_source, name, texture_path:texture_path.map(|path| {path.to_string_lossy().to_string()}), base_extra_voxel_data, surface_type, surface_subtype, face_textures:None, model:None }
+    }
+    pub fn with_face_textures(mut self, face_textures:[usize ; 6]) -> Self {
+        self.face_textures = Some(face_textures.to_vec());
+        self
+    }
+    // custom models let light through like partially empty voxels, so the faces next to them aren't left in the dark
+    pub fn with_model(mut self, model:CustomModel) -> Self {
+        self.empty_sides = model.sides_empty();
+        self.light_passthrough = VoxelLight::new(200, 255, 255, 255);
+        self.model = Some(model);
+        self
     }
 }
 
@@ -107,11 +123,23 @@ impl VoxelType for CoolVoxelType {
         0
     }
     fn kind_of_model(&self) -> crate::game_map::VoxelModel {
-        VoxelModel::WrappedTexture(self.texture)
+        if self.model.is_some() {
+            return VoxelModel::Custom
+        }
+        match &self.face_textures {
+            Some(textures) if textures.len() == 6 => VoxelModel::SpecifiedTexture([textures[0], textures[1], textures[2], textures[3], textures[4], textures[5]]),
+            _ => VoxelModel::WrappedTexture(self.texture)
+        }
     }
     fn light_passthrough(&self) -> VoxelLight {
         self.light_passthrough.clone()
     }
+    fn light_source(&self) -> Option<VoxelLight> {
+        self.is_light_source
+    }
+    fn custom_model(&self) -> Option<&CustomModel> {
+        self.model.as_ref()
+    }
 }
 
 impl Voxel for CoolVoxel {
@@ -125,6 +153,15 @@ impl Voxel for CoolVoxel {
     fn light_level(&self) -> crate::game_map::VoxelLight {
         self.light
     }
+    fn set_light_level(&mut self, light:crate::game_map::VoxelLight) {
+        self.light = light;
+    }
+    fn sky_light(&self) -> u8 {
+        self.sky
+    }
+    fn set_sky_light(&mut self, sky:u8) {
+        self.sky = sky;
+    }
 }
 
 fn get_push_to_next_integer_coords_in_dir(start:Vec3Df, dir:Vec3Df) -> Vec3Df {
@@ -499,8 +536,10 @@ fn after_main_tick<'a>(turn:EntityTurn, id:usize, first_ent:&GameEntityVecRead<'
 #[derive(Clone)]
 pub struct ExtraData {
     pub tick:Arc<AtomicUsize>,
-    pub waves:WavesHandler<CoolGameEngine>,
-    pub current_render_data:Arc<RwLock<(Camera, ViewportData)>>,
+    // None when running without audio (headless server)
+    pub waves:Option<WavesHandler<CoolGameEngine>>,
+    // None when nothing is rendered (headless server)
+    pub current_render_data:Option<Arc<RwLock<(Camera, ViewportData)>>>,
     pub payload_sender:Sender<HordeProximaAIRequest>,
 }
 
@@ -15,12 +15,30 @@ pub struct GameInputHandler {
     previous_keyboard:HashSet<Button>,
     throttle_inertia:HashMap<Button, f32>,
     on_car:bool,
+    // driver entity the server gave us, None until it answers
+    driver:Option<usize>,
+    // no actions are sent while frozen, like during the race countdown
+    frozen:bool,
+    // actions sent to our driver since the last take_sent_actions, for client side prediction
+    sent_actions:Vec<Action>,
 }
 
 
 impl GameInputHandler {
     pub fn new(current_mouse_pos:MouseState, sensitivity:f32, receiver:Receiver<WindowingEvent>) -> Self {
-        Self {throttle_inertia:HashMap::with_capacity(16),current_keyboard:HashSet::new(), previous_keyboard:HashSet::new(), last_mouse_pos: (0,0,0), current_mouse_pos, last_camera_used: Camera::new(Vec3Df::new(15.0, 50.0, -60.0), Orientation::zero()), sensitivity, outside_events:receiver, on_car:false }
+        Self {throttle_inertia:HashMap::with_capacity(16),current_keyboard:HashSet::new(), previous_keyboard:HashSet::new(), last_mouse_pos: (0,0,0), current_mouse_pos, last_camera_used: Camera::new(Vec3Df::new(15.0, 50.0, -60.0), Orientation::zero()), sensitivity, outside_events:receiver, on_car:false, driver:None, frozen:false, sent_actions:Vec::with_capacity(8) }
+    }
+    pub fn set_driver(&mut self, driver:Option<usize>) {
+        self.driver = driver;
+    }
+    pub fn set_frozen(&mut self, frozen:bool) {
+        self.frozen = frozen;
+    }
+    pub fn get_driver(&self) -> Option<usize> {
+        self.driver
+    }
+    pub fn take_sent_actions(&mut self) -> Vec<Action> {
+        self.sent_actions.drain(..).collect()
     }
     pub fn is_newly_pressed(&self, button:&Button) -> bool {
         self.current_keyboard.contains(button) && !self.previous_keyboard.contains(button)
@@ -48,36 +66,49 @@ impl GameInputHandler {
             *entry += 0.50;
             *entry = entry.clamp(0.0, 1.0);
         }
+        // nothing to drive until the server gave us a driver
+        if self.driver.is_none() || self.frozen {
+            self.throttle_inertia.clear();
+        }
+        let mut actions = Vec::with_capacity(2);
         for (button, throttle) in &mut self.throttle_inertia {
-            if *throttle >= 0.3 {
+            if *throttle >= 0.3 && let Some(driver) = self.driver {
                 if *button == Button::I {
                     other_input = true;
                     //println!("THROTTLING ON CLIENT");
-                    first_ent.tunnels.actions_out.send(GameEntityEvent::new(MustSync::Client, ActionsEvent::new(0, None, ActionsUpdate::AddAction(Action::new(0, tick, ActionTimer::Infinite, ActionKind::Throttle(4.5 * *throttle), ActionSource::Director).make_parallel()))));
+                    actions.push(Action::new(driver, tick, ActionTimer::Infinite, ActionKind::Throttle(4.5 * *throttle), ActionSource::Director).make_parallel());
                 }
                 else if *button == Button::K {
                     other_input = true;
-                    first_ent.tunnels.actions_out.send(GameEntityEvent::new(MustSync::Client, ActionsEvent::new(0, None, ActionsUpdate::AddAction(Action::new(0, tick, ActionTimer::Infinite, ActionKind::Throttle(-0.3 * *throttle), ActionSource::Director).make_parallel()))));
+                    actions.push(Action::new(driver, tick, ActionTimer::Infinite, ActionKind::Throttle(-0.3 * *throttle), ActionSource::Director).make_parallel());
                 }
                 if *button == Button::J {
-                    first_ent.tunnels.actions_out.send(GameEntityEvent::new(MustSync::Client, ActionsEvent::new(0, None, ActionsUpdate::AddAction(Action::new(0, tick, ActionTimer::Infinite, ActionKind::Turn(-0.02 * *throttle), ActionSource::Director).make_parallel()))));
+                    actions.push(Action::new(driver, tick, ActionTimer::Infinite, ActionKind::Turn(-0.02 * *throttle), ActionSource::Director).make_parallel());
                 }
                 else if *button == Button::L {
-                    first_ent.tunnels.actions_out.send(GameEntityEvent::new(MustSync::Client, ActionsEvent::new(0, None, ActionsUpdate::AddAction(Action::new(0, tick, ActionTimer::Infinite, ActionKind::Turn(0.02 * *throttle), ActionSource::Director).make_parallel()))));
+                    actions.push(Action::new(driver, tick, ActionTimer::Infinite, ActionKind::Turn(0.02 * *throttle), ActionSource::Director).make_parallel());
                 }
                 *throttle *= 0.8;
                 //dbg!(throttle);
             }
         }
+        if let Some(driver) = self.driver {
+            for action in actions {
+                first_ent.tunnels.actions_out.send(GameEntityEvent::new(MustSync::Client, ActionsEvent::new(driver, None, ActionsUpdate::AddAction(action.clone()))));
+                self.sent_actions.push(action);
+            }
+        }
         
 
-        if let Some(vehicle) = first_ent.stats[0].personal_vehicle && self.on_car {
+        if let Some(driver) = self.driver && let Some(vehicle) = first_ent.stats[driver].personal_vehicle && self.on_car {
             if self.current_keyboard.contains(&Button::V) {
                 self.on_car = false;
             }
+            // follows the vehicle where it is drawn
             let vehicle_pos = &second_ent.position[vehicle];
-            let vehicle_rotat = Rotation::from_orientation(vehicle_pos.orientation);
-            self.last_camera_used = Camera { pos: vehicle_rotat.rotate(Vec3Df::new(-5.0, 0.0, 4.0)) + vehicle_pos.pos, orient: vehicle_pos.orientation + Orientation::new(PI/2.0, -vehicle_pos.orientation.pitch, vehicle_pos.orientation.pitch + PI * 0.65), fov: 90.0 };
+            let (pos, orientation) = second_ent.mesh_info[vehicle].shown.unwrap_or((vehicle_pos.pos, vehicle_pos.orientation));
+            let vehicle_rotat = Rotation::from_orientation(orientation);
+            self.last_camera_used = Camera { pos: vehicle_rotat.rotate(Vec3Df::new(-5.0, 0.0, 4.0)) + pos, orient: orientation + Orientation::new(PI/2.0, -orientation.pitch, orientation.pitch + PI * 0.65), fov: 90.0 };
             self.last_camera_used.clone()
         }
         else {
@@ -0,0 +1,127 @@
+use std::fmt::Display;
+
+use hord3::horde::geometry::{rotation::Orientation, vec3d::Vec3Df};
+use to_from_bytes_derive::{FromBytes, ToBytes};
+
+use super::WorldChunkPos;
+use crate::{driver::{Movement, stats::Stats}, vehicle::position::VehiclePosition};
+
+// positions and orientations are compared to that much, speeds a bit finer
+// clients compute every entity again on their side, so these fields only have to be close (see design_doc.md)
+const POSITION_STEP:f32 = 1.0/8.0;
+const ORIENTATION_STEP:f32 = 1.0/64.0;
+const SPEED_STEP:f32 = 1.0/32.0;
+
+// Checksums of the server's state after one of its ticks, clients compare them with their own state once they applied that tick
+// entities are in id order, chunks are the ones edited since the previous checksums
+#[derive(Clone, ToBytes, FromBytes, PartialEq)]
+pub struct StateChecksums {
+    pub tick:usize,
+    pub vehicles:Vec<u64>,
+    pub movements:Vec<u64>,
+    pub stats:Vec<u64>,
+    pub chunks:Vec<(WorldChunkPos, u64)>,
+}
+
+// what a client wants the server to send again after a desync
+#[derive(Clone, ToBytes, FromBytes, PartialEq)]
+pub struct ResyncRequest {
+    pub vehicles:Vec<usize>,
+    pub drivers:Vec<usize>,
+    pub chunks:Vec<WorldChunkPos>,
+}
+
+impl ResyncRequest {
+    pub fn is_empty(&self) -> bool {
+        self.vehicles.is_empty() && self.drivers.is_empty() && self.chunks.is_empty()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ChecksumPart {
+    VehiclePosition(usize),
+    Movement(usize),
+    Stats(usize),
+    Chunk(WorldChunkPos),
+}
+
+impl Display for ChecksumPart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::VehiclePosition(id) => write!(f, "VehiclePosition of vehicle {}", id),
+            Self::Movement(id) => write!(f, "Movement of driver {}", id),
+            Self::Stats(id) => write!(f, "Stats of driver {}", id),
+            Self::Chunk(c_pos) => write!(f, "chunk ({}, {}, {})", c_pos.x, c_pos.y, c_pos.z),
+        }
+    }
+}
+
+// FNV-1a, the same on every platform and build unlike std's hasher
+pub struct Checksum {
+    hash:u64,
+}
+
+impl Checksum {
+    pub fn new() -> Self {
+        Self { hash:0xcbf29ce484222325 }
+    }
+    pub fn add_bytes(&mut self, bytes:&[u8]) {
+        for byte in bytes {
+            self.hash ^= *byte as u64;
+            self.hash = self.hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    pub fn add_u64(&mut self, value:u64) {
+        self.add_bytes(&value.to_le_bytes());
+    }
+    // exact value, for fields every side must agree on
+    pub fn add_f32(&mut self, value:f32) {
+        self.add_bytes(&value.to_bits().to_le_bytes());
+    }
+    // rounded to step, for fields computed by every side on its own
+    pub fn add_close_f32(&mut self, value:f32, step:f32) {
+        self.add_u64((value / step).round() as i64 as u64);
+    }
+    fn add_close_vec(&mut self, vec:Vec3Df, step:f32) {
+        self.add_close_f32(vec.x, step);
+        self.add_close_f32(vec.y, step);
+        self.add_close_f32(vec.z, step);
+    }
+    fn add_close_orientation(&mut self, orient:Orientation, step:f32) {
+        self.add_close_f32(orient.yaw, step);
+        self.add_close_f32(orient.pitch, step);
+        self.add_close_f32(orient.roll, step);
+    }
+    pub fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+pub fn vehicle_position_checksum(position:&VehiclePosition) -> u64 {
+    let mut checksum = Checksum::new();
+    checksum.add_close_vec(position.pos, POSITION_STEP);
+    checksum.add_close_vec(position.spd, SPEED_STEP);
+    checksum.add_close_orientation(position.orientation, ORIENTATION_STEP);
+    checksum.add_close_orientation(position.turn_spd, ORIENTATION_STEP);
+    checksum.finish()
+}
+
+pub fn movement_checksum(movement:&Movement) -> u64 {
+    let mut checksum = Checksum::new();
+    checksum.add_close_vec(movement.pos, POSITION_STEP);
+    checksum.add_close_vec(movement.speed, SPEED_STEP);
+    checksum.add_close_orientation(movement.orient, ORIENTATION_STEP);
+    checksum.finish()
+}
+
+pub fn stats_checksum(stats:&Stats) -> u64 {
+    let mut checksum = Checksum::new();
+    checksum.add_u64(stats.static_type_id as u64);
+    checksum.add_u64(stats.health as u64);
+    checksum.add_u64(stats.damage as u64);
+    checksum.add_u64(stats.stamina as u64);
+    checksum.add_f32(stats.ground_speed);
+    checksum.add_f32(stats.jump_height);
+    checksum.add_u64(stats.personal_vehicle.map(|vehicle| {vehicle as u64 + 1}).unwrap_or(0));
+    checksum.finish()
+}
@@ -0,0 +1,220 @@
+use std::mem::size_of;
+
+use to_from_bytes_derive::{FromBytes, ToBytes};
+
+use crate::game_map::{ChunkDims, MapChunk, Voxel, WorldChunkPos, WorldVoxelPos, storage::encode};
+
+// How the voxels of a chunk are sent over the network and written to disk
+// the smallest of the 3 is picked for each chunk
+#[derive(Clone, ToBytes, FromBytes, PartialEq)]
+pub enum CompressedVoxels<V:Voxel> {
+    // every voxel has the same type, count is the number of voxels in the chunk
+    Uniform(V, u32),
+    // palette of distinct voxels and runs of (palette index, run length) in chunk order
+    PaletteRLE(Vec<V>, Vec<(u16, u16)>),
+    // lots of distinct voxels (random lights for example), runs would only add overhead
+    Raw(Vec<V>),
+}
+
+#[derive(Clone, ToBytes, FromBytes, PartialEq)]
+pub struct CompressedChunk<V:Voxel> {
+    origin_worldpos:WorldVoxelPos,
+    chunk_coord:WorldChunkPos,
+    voxels:CompressedVoxels<V>,
+}
+
+const RUN_SIZE:usize = 2 * size_of::<u16>();
+
+impl<V:Voxel> CompressedVoxels<V> {
+    pub fn compress(voxels:&[V]) -> Self {
+        let mut palette:Vec<V> = Vec::with_capacity(8);
+        let mut runs:Vec<(u16, u16)> = Vec::with_capacity(32);
+        for voxel in voxels {
+            let index = match palette.iter().position(|known| {known == voxel}) {
+                Some(index) => index,
+                None => {
+                    if palette.len() >= u16::MAX as usize {
+                        return Self::Raw(voxels.to_vec());
+                    }
+                    palette.push(voxel.clone());
+                    palette.len() - 1
+                }
+            } as u16;
+            match runs.last_mut() {
+                Some((last_index, length)) if *last_index == index && *length < u16::MAX => *length += 1,
+                _ => runs.push((index, 1))
+            }
+            // past that point the palette version can't be smaller than the raw voxels anymore
+            if palette.len() * size_of::<V>() + runs.len() * RUN_SIZE >= voxels.len() * size_of::<V>() {
+                return Self::Raw(voxels.to_vec());
+            }
+        }
+        if palette.len() == 1 {
+            Self::Uniform(palette.pop().unwrap(), voxels.len() as u32)
+        }
+        else {
+            Self::PaletteRLE(palette, runs)
+        }
+    }
+    // runs straight from the indices of a compacted chunk, its palette already holds distinct voxels
+    fn from_palette(palette:&[V], indices:&[u8]) -> Self {
+        let mut runs:Vec<(u16, u16)> = Vec::with_capacity(32);
+        for index in indices {
+            match runs.last_mut() {
+                Some((last_index, length)) if *last_index == *index as u16 && *length < u16::MAX => *length += 1,
+                _ => runs.push((*index as u16, 1))
+            }
+        }
+        if palette.len() * size_of::<V>() + runs.len() * RUN_SIZE >= indices.len() * size_of::<V>() {
+            Self::Raw(indices.iter().map(|index| {palette[*index as usize].clone()}).collect())
+        }
+        else {
+            Self::PaletteRLE(palette.to_vec(), runs)
+        }
+    }
+    pub fn decompress(self) -> Vec<V> {
+        match self {
+            Self::Uniform(voxel, count) => vec![voxel ; count as usize],
+            Self::PaletteRLE(palette, runs) => {
+                let mut voxels = Vec::with_capacity(runs.iter().map(|(_, length)| {*length as usize}).sum());
+                for (index, length) in runs {
+                    for _ in 0..length {
+                        voxels.push(palette[index as usize].clone());
+                    }
+                }
+                voxels
+            },
+            Self::Raw(voxels) => voxels
+        }
+    }
+}
+
+impl<V:Voxel> CompressedChunk<V> {
+    pub fn get_chunk_coord(&self) -> WorldChunkPos {
+        self.chunk_coord
+    }
+    pub fn decompress(self) -> MapChunk<V> {
+        MapChunk::new(self.origin_worldpos, self.chunk_coord, self.voxels.decompress())
+    }
+}
+
+impl<V:Voxel> MapChunk<V> {
+    pub fn compress(&self, dims:&ChunkDims) -> CompressedChunk<V> {
+        let voxels = match &self.only_1_type {
+            // uniform chunks don't keep their voxels in memory
+            Some(voxel) => CompressedVoxels::Uniform(voxel.clone(), (dims.chunk_slice_area * dims.chunk_height) as u32),
+            None if !self.palette.is_empty() => CompressedVoxels::from_palette(&self.palette, &self.palette_indices),
+            None => CompressedVoxels::compress(&self.voxels)
+        };
+        CompressedChunk { origin_worldpos: self.origin_worldpos, chunk_coord: self.chunk_coord, voxels }
+    }
+}
+
+// every voxel of the chunk in order, what NewChunk used to send
+fn raw_voxels<V:Voxel>(chunk:&MapChunk<V>, dims:&ChunkDims) -> Vec<V> {
+    let mut voxels = Vec::with_capacity(dims.chunk_slice_area * dims.chunk_height);
+    for z in 0..dims.chunk_height_i {
+        for y in 0..dims.chunk_width_i {
+            for x in 0..dims.chunk_length_i {
+                voxels.push(chunk.get_voxel_data(WorldVoxelPos::new(x, y, z), dims).clone());
+            }
+        }
+    }
+    voxels
+}
+
+// Running totals of what chunk compression saves, printed by the server as chunks get sent
+#[derive(Clone, Default)]
+pub struct ChunkTransferStats {
+    chunks:usize,
+    raw_bytes:usize,
+    compressed_bytes:usize,
+}
+
+impl ChunkTransferStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // sizes once encoded, against the encoding of the raw voxels
+    pub fn add<V:Voxel>(&mut self, chunk:&MapChunk<V>, compressed:&CompressedChunk<V>, dims:&ChunkDims) {
+        self.chunks += 1;
+        self.raw_bytes += encode(&raw_voxels(chunk, dims)).len();
+        self.compressed_bytes += encode(compressed).len();
+    }
+    pub fn print(&self) {
+        if self.chunks > 0 {
+            println!(
+                "Chunk transfer : {} chunks, {} KB raw, {} KB compressed ({:.1}% saved)",
+                self.chunks,
+                self.raw_bytes/1024,
+                self.compressed_bytes/1024,
+                100.0 - (self.compressed_bytes as f64/self.raw_bytes as f64) * 100.0
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use hord3::horde::geometry::vec3d::Vec3Df;
+
+    use crate::{game_engine::CoolVoxel, game_map::{ChunkDims, GameMap, WorldChunkPos, WorldVoxelPos, road::{Road, RoadVoxels}, storage::encode}, voxel_registry::{VoxelRegistry, voxel_registry}};
+
+    use super::raw_voxels;
+
+    // the first chunks of a road, generated and lit like on the server
+    fn road_chunks() -> (GameMap<CoolVoxel, Road>, Vec<WorldChunkPos>) {
+        // other tests may have loaded it already
+        let _ = VoxelRegistry::load_global(PathBuf::from("voxels.json"));
+        let registry = voxel_registry();
+        let mut world = GameMap::new(100, ChunkDims::new(8, 8, 8), registry.get_voxel_types(), (40,40,40), 1, Road::new(WorldChunkPos::new(0,0,1), Vec3Df::new(1.0, 0.0, 0.0), 7, RoadVoxels::from_registry(registry)));
+        let dims = world.get_chunk_dims_vector_f();
+        let mut generated = Vec::new();
+        for _ in 0..8 {
+            world.generator.step_forwards(3.0, &dims);
+            let chunks = world.generator.get_chunks_to_generate(3.0, &world);
+            generated.extend(world.generate_chunks_with_generator_and_get_them(chunks).into_iter().map(|(c_pos, _)| {c_pos}));
+        }
+        (world, generated)
+    }
+
+    #[test]
+    fn road_chunks_are_smaller_compressed() {
+        let (world, generated) = road_chunks();
+        assert!(!generated.is_empty());
+        let dims = world.get_chunk_dims();
+        let (mut raw_bytes, mut compressed_bytes) = (0, 0);
+        for c_pos in &generated {
+            let chunk = world.get_chunk_at(*c_pos).unwrap();
+            let raw = raw_voxels(chunk, dims);
+            let compressed = chunk.compress(dims);
+            raw_bytes += encode(&raw).len();
+            compressed_bytes += encode(&compressed).len();
+            assert!(raw_voxels(&compressed.decompress(), dims) == raw);
+        }
+        println!("{} road chunks : {} bytes raw, {} bytes compressed ({:.1}% saved)", generated.len(), raw_bytes, compressed_bytes, 100.0 - (compressed_bytes as f64/raw_bytes as f64) * 100.0);
+        assert!(compressed_bytes < raw_bytes);
+    }
+
+    #[test]
+    fn compacted_road_chunks_read_the_same() {
+        let (world, generated) = road_chunks();
+        let dims = world.get_chunk_dims();
+        let mut compacted = 0;
+        for c_pos in &generated {
+            let chunk = world.get_chunk_at(*c_pos).unwrap();
+            if !chunk.is_compacted() {
+                continue
+            }
+            compacted += 1;
+            let mut expanded = chunk.clone();
+            let first = expanded.get_voxel_data(WorldVoxelPos::new(0, 0, 0), dims).clone();
+            *expanded.get_voxel_data_mut(WorldVoxelPos::new(0, 0, 0), dims) = first;
+            assert!(!expanded.is_compacted());
+            assert!(raw_voxels(&expanded, dims) == raw_voxels(chunk, dims));
+        }
+        assert!(compacted > 0);
+    }
+}
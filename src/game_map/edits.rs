@@ -0,0 +1,52 @@
+use std::fmt::Display;
+
+use to_from_bytes_derive::{FromBytes, ToBytes};
+
+use super::{Voxel, WorldVoxelPos};
+
+// Voxel edits coming from clients (tile editor) are only requests : the client shows them right away,
+// the server checks them (see server::voxel_edits) and either applies them with UpdateVoxelAt
+// or answers with a rejection that puts the server's voxel back on every client
+#[derive(Clone, ToBytes, FromBytes, PartialEq)]
+pub struct VoxelEditRequest<V:Voxel> {
+    // secret session token of the player asking for the edit, the server finds its driver from its id (see game_map::players)
+    pub token:u64,
+    // counted by each client, to match rejections with the edits they undo
+    pub edit_id:u32,
+    pub pos:WorldVoxelPos,
+    pub voxel:V,
+}
+
+#[derive(Clone, Copy, ToBytes, FromBytes, PartialEq, Debug)]
+pub enum EditRejection {
+    UnknownPlayer,
+    OutOfReach,
+    Protected,
+    RateLimited,
+    UnknownVoxelType,
+    ChunkNotLoaded,
+}
+
+impl Display for EditRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownPlayer => write!(f, "unknown player"),
+            Self::OutOfReach => write!(f, "too far from the player"),
+            Self::Protected => write!(f, "protected zone"),
+            Self::RateLimited => write!(f, "too many edits"),
+            Self::UnknownVoxelType => write!(f, "unknown voxel type"),
+            Self::ChunkNotLoaded => write!(f, "chunk not loaded on the server"),
+        }
+    }
+}
+
+#[derive(Clone, ToBytes, FromBytes, PartialEq)]
+pub struct VoxelEditRejected<V:Voxel> {
+    // driver entity of the player whose edit it was
+    pub player:usize,
+    pub edit_id:u32,
+    pub pos:WorldVoxelPos,
+    pub reason:EditRejection,
+    // what the server has at pos, None if the chunk isn't loaded there
+    pub voxel:Option<V>,
+}
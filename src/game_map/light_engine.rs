@@ -0,0 +1,274 @@
+use std::collections::{HashSet, VecDeque};
+
+use hord3::{defaults::default_rendering::vectorinator_binned::triangles::{collux_f32_a_u8, collux_u8_a_f32}, horde::geometry::vec3d::Vec3D};
+
+use super::{EXPLORATION, GameMap, Generator, Voxel, VoxelLight, VoxelType, WorldChunkPos, WorldVoxelPos};
+
+// Incremental flood fill lighting, run every time a voxel changes (GameMapEvent::UpdateVoxelAt) or a chunk arrives
+// every voxel keeps the brightest light reaching it, so that a light can be taken back out without recomputing the whole area :
+// - remove pass : everything that was darker than the removed light around it was lit through it, and goes dark
+// - add pass : the light sources and the lit voxels at the border of the darkened area spread again
+//
+// Block light comes from light sources (VoxelType::light_source) and is coloured
+// Sky light is a single level : full in every column open to the sky, going straight down without loss,
+// then spreading sideways under overhangs like block light. How bright it is depends on the time of day (GameShader::sky_tint)
+
+#[derive(Clone, Copy, PartialEq)]
+enum LightChannel {
+    Block,
+    Sky,
+}
+
+const DOWN:usize = 2;
+const FULL_SKY:u8 = 255;
+
+// same attenuation as LightSpread::calc_max_spread
+fn spread_through(light:VoxelLight, passthrough:VoxelLight) -> VoxelLight {
+    let passthrough_f = collux_u8_a_f32((passthrough.r, passthrough.g, passthrough.b));
+    let current = collux_u8_a_f32((light.r, light.g, light.b));
+    let (r, g, b) = collux_f32_a_u8((current.0 * passthrough_f.0, current.1 * passthrough_f.1, current.2 * passthrough_f.2));
+    VoxelLight { level: light.level - (255 - passthrough.level).min(light.level), r, g, b }
+}
+
+impl LightChannel {
+    fn get<V:Voxel>(&self, voxel:&V) -> VoxelLight {
+        match self {
+            Self::Block => voxel.light_level(),
+            Self::Sky => VoxelLight::new(voxel.sky_light(), 255, 255, 255)
+        }
+    }
+    fn set<V:Voxel>(&self, voxel:&mut V, light:VoxelLight) {
+        match self {
+            Self::Block => voxel.set_light_level(light),
+            Self::Sky => voxel.set_sky_light(light.level)
+        }
+    }
+    fn source<V:Voxel>(&self, voxel_types:&Vec<V::VT>, voxel:&V) -> Option<VoxelLight> {
+        match self {
+            Self::Block => voxel_types[voxel.voxel_id()].light_source(),
+            Self::Sky => None
+        }
+    }
+    fn spread(&self, light:VoxelLight, passthrough:VoxelLight, dir:usize) -> VoxelLight {
+        if *self == Self::Sky && dir == DOWN && light.level == FULL_SKY {
+            light
+        }
+        else {
+            spread_through(light, passthrough)
+        }
+    }
+}
+
+impl<V:Voxel, G:Generator<V>> GameMap<V, G> {
+    // previous is what was at pos before the change, the new voxel must already be in place
+    pub fn update_light_at(&mut self, pos:WorldVoxelPos, previous:&V) {
+        let mut changed = HashSet::with_capacity(64);
+        for channel in [LightChannel::Block, LightChannel::Sky] {
+            let mut to_spread = self.remove_light(channel, pos, channel.get(previous), &mut changed);
+            match self.get_voxel_at(pos).and_then(|voxel| {channel.source(&self.voxel_types, voxel)}) {
+                Some(source) => {
+                    self.set_light(channel, pos, source, &mut changed);
+                    to_spread.push_back(pos);
+                },
+                None => {
+                    // light around the voxel comes back in if it lets light through
+                    for dir in EXPLORATION {
+                        match self.get_voxel_at(pos + dir) {
+                            Some(voxel) if channel.get(voxel).level > 0 => to_spread.push_back(pos + dir),
+                            _ => ()
+                        }
+                    }
+                }
+            }
+            self.spread_light(channel, to_spread, &mut changed);
+        }
+        self.remesh_lit_chunks(changed, true);
+    }
+    // Lights a chunk that was just generated, loaded or received, and updates the chunks around it :
+    // light from the neighbours comes in, its own light sources and sky go out, and it shades the columns under it
+    pub fn light_new_chunk(&mut self, chunk_pos:WorldChunkPos) {
+        if !self.does_chunk_exist(chunk_pos) {
+            return
+        }
+        let mut changed = HashSet::with_capacity(256);
+        let origin = self.get_chunk_dims_vector().component_product(&chunk_pos);
+        let (length, width, height) = (self.dims.chunk_length_i, self.dims.chunk_width_i, self.dims.chunk_height_i);
+        let above = chunk_pos + Vec3D::new(0, 0, 1);
+        let open_sky = !self.does_chunk_exist(above);
+        let mut block_seeds = VecDeque::with_capacity(64);
+        let mut sky_seeds = VecDeque::with_capacity(64);
+
+        // uniform chunks in the open (all of the air above the ground) are lit in one go so they stay uniform
+        let lit_uniform = match self.chunks.get_mut(&chunk_pos) {
+            Some(chunk) => match &mut chunk.only_1_type {
+                Some(voxel) if open_sky && self.voxel_types[voxel.voxel_id()].light_passthrough().level > 0 && self.voxel_types[voxel.voxel_id()].light_source().is_none() => {
+                    if voxel.sky_light() != FULL_SKY {
+                        voxel.set_sky_light(FULL_SKY);
+                        changed.insert(origin);
+                    }
+                    true
+                },
+                _ => false
+            },
+            None => false
+        };
+
+        for x in 0..length {
+            for y in 0..width {
+                for z in 0..height {
+                    let on_border = x == 0 || y == 0 || z == 0 || x == length - 1 || y == width - 1 || z == height - 1;
+                    if lit_uniform && !on_border {
+                        continue
+                    }
+                    let pos = origin + Vec3D::new(x, y, z);
+                    let (source, passthrough) = match self.get_voxel_at(pos) {
+                        Some(voxel) => (self.voxel_types[voxel.voxel_id()].light_source(), self.voxel_types[voxel.voxel_id()].light_passthrough()),
+                        None => continue
+                    };
+                    match source {
+                        Some(light) => {
+                            self.set_light(LightChannel::Block, pos, light, &mut changed);
+                            block_seeds.push_back(pos);
+                        },
+                        None => ()
+                    }
+                    if lit_uniform || (z == height - 1 && open_sky && passthrough.level > 0) {
+                        self.set_light(LightChannel::Sky, pos, VoxelLight::new(FULL_SKY, 255, 255, 255), &mut changed);
+                        sky_seeds.push_back(pos);
+                    }
+                    if on_border {
+                        for dir in EXPLORATION {
+                            let next = pos + dir;
+                            if self.get_chunk_pos_i(next) == chunk_pos {
+                                continue
+                            }
+                            match self.get_voxel_at(next) {
+                                Some(voxel) => {
+                                    if voxel.light_level().level > 0 {
+                                        block_seeds.push_back(next);
+                                    }
+                                    if voxel.sky_light() > 0 {
+                                        sky_seeds.push_back(next);
+                                    }
+                                },
+                                None => ()
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        self.spread_light(LightChannel::Block, block_seeds, &mut changed);
+        self.spread_light(LightChannel::Sky, sky_seeds, &mut changed);
+
+        // columns under the chunk that were open to the sky before it arrived
+        if self.does_chunk_exist(chunk_pos - Vec3D::new(0, 0, 1)) {
+            for x in 0..length {
+                for y in 0..width {
+                    let bottom = origin + Vec3D::new(x, y, 0);
+                    let under = bottom - Vec3D::new(0, 0, 1);
+                    let bottom_sky = self.get_voxel_at(bottom).map(|voxel| {voxel.sky_light()}).unwrap_or(0);
+                    let under_sky = self.get_voxel_at(under).map(|voxel| {voxel.sky_light()}).unwrap_or(0);
+                    if under_sky == FULL_SKY && bottom_sky < FULL_SKY {
+                        let to_spread = self.remove_light(LightChannel::Sky, under, VoxelLight::new(FULL_SKY, 255, 255, 255), &mut changed);
+                        self.spread_light(LightChannel::Sky, to_spread, &mut changed);
+                    }
+                }
+            }
+        }
+        self.remesh_lit_chunks(changed, false);
+    }
+    fn set_light(&mut self, channel:LightChannel, pos:WorldVoxelPos, light:VoxelLight, changed:&mut HashSet<WorldVoxelPos>) {
+        match self.get_voxel_at(pos) {
+            Some(voxel) if channel.get(voxel) == light => return,
+            Some(_) => (),
+            None => return
+        }
+        match self.get_voxel_at_mut(pos) {
+            Some(voxel) => {
+                channel.set(voxel, light);
+                changed.insert(pos);
+            },
+            None => ()
+        }
+    }
+    // returns the voxels that must spread their light again afterwards
+    fn remove_light(&mut self, channel:LightChannel, start:WorldVoxelPos, start_light:VoxelLight, changed:&mut HashSet<WorldVoxelPos>) -> VecDeque<WorldVoxelPos> {
+        let mut to_spread = VecDeque::with_capacity(64);
+        let mut to_remove = VecDeque::with_capacity(64);
+        self.set_light(channel, start, VoxelLight::zero_light(), changed);
+        if start_light.level > 0 {
+            to_remove.push_back((start, start_light.level));
+        }
+        while let Some((pos, level)) = to_remove.pop_front() {
+            for (dir, offset) in EXPLORATION.iter().enumerate() {
+                let next = pos + *offset;
+                let (next_light, next_is_source) = match self.get_voxel_at(next) {
+                    Some(voxel) => (channel.get(voxel), channel.source(&self.voxel_types, voxel).is_some()),
+                    None => continue
+                };
+                if next_light.level == 0 {
+                    continue
+                }
+                // full sky goes down without loss, so the column under a removed full sky voxel was lit by it too
+                let lit_by_removed = next_light.level < level || (channel == LightChannel::Sky && dir == DOWN && level == FULL_SKY);
+                if lit_by_removed && !next_is_source {
+                    self.set_light(channel, next, VoxelLight::zero_light(), changed);
+                    to_remove.push_back((next, next_light.level));
+                }
+                else {
+                    to_spread.push_back(next);
+                }
+            }
+        }
+        to_spread
+    }
+    fn spread_light(&mut self, channel:LightChannel, mut to_spread:VecDeque<WorldVoxelPos>, changed:&mut HashSet<WorldVoxelPos>) {
+        while let Some(pos) = to_spread.pop_front() {
+            let light = match self.get_voxel_at(pos) {
+                Some(voxel) => channel.get(voxel),
+                None => continue
+            };
+            if light.level == 0 {
+                continue
+            }
+            for (dir, offset) in EXPLORATION.iter().enumerate() {
+                let next = pos + *offset;
+                let next_light = match self.get_voxel_at(next) {
+                    Some(voxel) => {
+                        let passthrough = self.voxel_types[voxel.voxel_id()].light_passthrough();
+                        if passthrough.level == 0 {
+                            continue
+                        }
+                        let spread = channel.spread(light, passthrough, dir);
+                        if spread.level <= channel.get(voxel).level {
+                            continue
+                        }
+                        spread
+                    },
+                    None => continue
+                };
+                self.set_light(channel, next, next_light, changed);
+                to_spread.push_back(next);
+            }
+        }
+    }
+    // faces take the light of the voxel in front of them, so chunks next to a changed voxel need a new mesh too
+    // edits go through the remesh fast track, new chunks don't
+    fn remesh_lit_chunks(&mut self, changed:HashSet<WorldVoxelPos>, fasttrack:bool) {
+        let mut chunks:HashSet<WorldChunkPos> = HashSet::with_capacity(8);
+        for pos in changed {
+            chunks.insert(self.get_chunk_pos_i(pos));
+            for dir in EXPLORATION {
+                chunks.insert(self.get_chunk_pos_i(pos + dir));
+            }
+        }
+        for chunk_pos in chunks {
+            if self.queue_remesh(chunk_pos) {
+                if fasttrack {
+                    self.remesh_fasttrack.push(chunk_pos);
+                }
+            }
+        }
+    }
+}
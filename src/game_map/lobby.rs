@@ -0,0 +1,73 @@
+use hord3::horde::game_engine::multiplayer::MustSync;
+use to_from_bytes_derive::{FromBytes, ToBytes};
+
+use crate::game_map::road::RoadBiome;
+
+// Lobby before the race, carried by SessionMessage::Lobby
+// like in players, clients put their secret session token in what they ask for themselves, the server only sends drivers back
+#[derive(Clone, ToBytes, FromBytes, PartialEq, Debug)]
+pub struct MatchSetup {
+    pub seed:u64,
+    // biomes the road goes through, every biome if empty
+    pub biomes:Vec<RoadBiome>,
+}
+
+impl MatchSetup {
+    pub fn biome_names(&self) -> String {
+        if self.biomes.is_empty() {
+            String::from("every biome")
+        }
+        else {
+            self.biomes.iter().map(|biome| {biome.get_name()}).collect::<Vec<&str>>().join(", ")
+        }
+    }
+}
+
+#[derive(Clone, ToBytes, FromBytes, PartialEq, Debug)]
+pub enum LobbyPhase {
+    // players pick their vehicle and get ready
+    Waiting,
+    // vehicles are held on the starting grid, the race starts in that many seconds
+    Countdown(usize),
+    Racing,
+}
+
+#[derive(Clone, ToBytes, FromBytes, PartialEq, Debug)]
+pub struct LobbyPlayer {
+    pub name:String,
+    pub driver:usize,
+    pub vehicle_type:usize,
+    pub ready:bool,
+    pub host:bool,
+}
+
+#[derive(Clone, ToBytes, FromBytes, PartialEq, Debug)]
+pub struct LobbyState {
+    pub phase:LobbyPhase,
+    pub players:Vec<LobbyPlayer>,
+    pub setup:MatchSetup,
+    // the road already started generating (saved world), the host can't change the track anymore
+    pub setup_locked:bool,
+    // vehicle types are 0..vehicle_types
+    pub vehicle_types:usize,
+}
+
+#[derive(Clone, ToBytes, FromBytes, PartialEq, Debug)]
+pub enum LobbyEvent {
+    // client to server, while waiting
+    PickVehicle {token:u64, vehicle_type:usize},
+    Ready {token:u64, ready:bool},
+    // client to server, only the host's is used
+    Setup {token:u64, setup:MatchSetup},
+    // server to clients whenever something changes, and every second
+    State(LobbyState),
+}
+
+impl LobbyEvent {
+    pub fn should_sync(&self) -> MustSync {
+        match self {
+            Self::PickVehicle { .. } | Self::Ready { .. } | Self::Setup { .. } => MustSync::Client,
+            Self::State(_) => MustSync::Server,
+        }
+    }
+}
@@ -0,0 +1,527 @@
+use std::{any::Any, collections::HashMap, sync::{Arc, LazyLock, Mutex, atomic::{AtomicUsize, Ordering}, mpmc::{self, Sender}}, thread};
+
+use to_from_bytes_derive::{FromBytes, ToBytes};
+use hord3::{defaults::default_rendering::vectorinator_binned::{meshes::{MeshLOD, MeshLODS, MeshLODType, MeshTriangles, TrianglePoint}, triangles::{collux_f32_a_u8, collux_u8_a_f32}}, horde::geometry::vec3d::{Vec3D, Vec3Df}};
+
+use crate::game_map::{ChunkDims, DIR_MASK, EXPLORATION, MapChunk, OPPOSITE, PERPENDICULAR, TRIS_INDICES_UVS, Voxel, VoxelLight, VoxelType, WorldChunkPos, multiply_corresponding_nonzero, orient_dir, voxel_models::{ModelTriangle, model_to_world}};
+
+// How the full detail mesh of a chunk is built
+// Naive : one quad per visible voxel face
+// Greedy : coplanar faces with the same texture and light are merged into bigger quads
+#[derive(Clone, Copy, Debug, ToBytes, FromBytes, PartialEq)]
+pub enum MeshingMode {
+    Naive,
+    Greedy,
+}
+
+// Coarser meshes built for every chunk on top of the full detail one, in blocks of factor^3 voxels
+// the renderer switches to them with distance (see the lod factor of chunk meshes in GameMap)
+const LOD_FACTORS:[i32 ; 3] = [2, 4, 8];
+
+// (texture, quarter turns of the texture, light) of a visible face
+type Face = (u32, u8, (u8,u8,u8));
+
+// Everything needed to build the mesh of a chunk without the GameMap, so it can run on the meshing threads
+pub struct ChunkMesher<V:Voxel> {
+    dims:ChunkDims,
+    voxel_types:Vec<V::VT>,
+    min_light_levels:(u8,u8,u8),
+    mode:MeshingMode,
+    // triangles of the custom model of every voxel type that has one
+    custom_triangles:Vec<Option<Vec<ModelTriangle>>>,
+}
+
+impl<V:Voxel> ChunkMesher<V> {
+    pub fn new(dims:ChunkDims, voxel_types:Vec<V::VT>, min_light_levels:(u8,u8,u8), mode:MeshingMode) -> Self {
+        let custom_triangles = voxel_types.iter().map(|voxel_type| {voxel_type.custom_model().map(|model| {model.triangles()})}).collect();
+        Self { dims, voxel_types, min_light_levels, mode, custom_triangles }
+    }
+    pub fn mesh_chunk(&self, chunk:&MapChunk<V>, around:[Option<&MapChunk<V>> ; 6]) -> MeshLODS {
+        let mut lods = Vec::with_capacity(1 + LOD_FACTORS.len());
+        let (effective_dirs, face_data) = self.get_face_data(chunk, around);
+        let mut full_detail = self.get_lod(&face_data, effective_dirs, self.get_grid(1), 1);
+        self.add_custom_models(&mut full_detail, chunk, around);
+        lods.push(MeshLODType::Mesh(Arc::new(full_detail)));
+        for factor in LOD_FACTORS {
+            if self.dims.chunk_length_i % factor == 0 && self.dims.chunk_width_i % factor == 0 && self.dims.chunk_height_i % factor == 0 {
+                let (coarse_dirs, coarse_faces) = self.get_downsampled_face_data(chunk, around, factor, &face_data);
+                lods.push(MeshLODType::Mesh(Arc::new(self.get_lod(&coarse_faces, coarse_dirs, self.get_grid(factor), factor))));
+            }
+        }
+        // coarsest first
+        lods.reverse();
+        MeshLODS::new(lods)
+    }
+    // (naive triangles, greedy triangles) of the full detail mesh of a chunk
+    pub fn count_triangles_per_mode(&self, chunk:&MapChunk<V>, around:[Option<&MapChunk<V>> ; 6]) -> (usize, usize) {
+        let (effective_dirs, face_data) = self.get_face_data(chunk, around);
+        // every face is a quad : 4 points, 2 triangles
+        (
+            self.get_lod_naive(&face_data, effective_dirs, self.get_grid(1), 1).x.len() / 2,
+            self.get_lod_greedy(&face_data, effective_dirs, self.get_grid(1), 1).x.len() / 2,
+        )
+    }
+    // colour of a face lit by the voxel in front of it : its block light, and its sky light as white
+    // the colour and strength of the time of day are applied by GameShader::sky_tint, so the meshes don't change with it
+    fn finished_light(&self, voxel_in_front:Option<&V>) -> (u8,u8,u8) {
+        let light = voxel_in_front.map(|voxel| {voxel.light_level()}).unwrap_or(VoxelLight::new(0, 0, 0, 0));
+        let level = (light.level.max(50) as f32) * 0.00392156862;
+        let converted_collux = collux_u8_a_f32((light.r, light.g, light.b));
+        let mut finished_collux = collux_f32_a_u8((converted_collux.0 * level, converted_collux.1 * level, converted_collux.2 * level));
+        let sky_level = voxel_in_front.map(|voxel| {voxel.sky_light()}).unwrap_or(0) as f32 * 0.00392156862;
+        let sky_white = (255.0 * sky_level) as u8;
+        let sky_collux = (sky_white, sky_white, sky_white);
+        finished_collux = (finished_collux.0.max(sky_collux.0), finished_collux.1.max(sky_collux.1), finished_collux.2.max(sky_collux.2));
+        (finished_collux.0.max(self.min_light_levels.0), finished_collux.1.max(self.min_light_levels.1), finished_collux.2.max(self.min_light_levels.2))
+    }
+    fn get_face_data_for_dir_first_pass(
+        &self,
+        (x,y,z):(i32,i32,i32),
+        (i,mask):(usize,u8),
+        chunk:&MapChunk<V>,
+        around:[Option<&MapChunk<V>> ; 6],
+        effective_dirs:&mut [bool ; 6]
+    ) -> Option<Face> {
+        match chunk.get_at_local(Vec3D::new(x, y, z), &self.dims){
+            Some(voxel) => if !&self.voxel_types[voxel.voxel_id()].is_completely_empty() && self.custom_triangles[voxel.voxel_id()].is_none() {
+                if chunk.is_empty_in_direction_local(Vec3D::new(x, y, z), &self.dims, around, &self.voxel_types, i) {
+                    let (full_texture, quarter_turns) = self.voxel_types[voxel.voxel_id()].texture_facing(i, voxel.orientation());
+                    let finished_collux = self.finished_light(chunk.get_voxel_in_dir(Vec3D::new(x, y, z), around, &self.dims, i));
+                    effective_dirs[i] = true;
+                    Some((full_texture as u32, quarter_turns, finished_collux))
+                }
+                else {
+                    None
+                }
+            }
+            else {
+                None
+            },
+            None => return None
+        }
+    }
+
+
+    // texture and light of every visible face of the chunk, also used as the base of the coarser LODs
+    fn get_face_data(&self, chunk:&MapChunk<V>, around:[Option<&MapChunk<V>> ; 6]) -> ([bool ; 6], Vec<[Option<Face> ; 6]>) {
+        let mut effective_dirs = [false ; 6];
+        if let Some(voxel) = chunk.only_1_type.clone() && self.voxel_types[voxel.voxel_id()].is_completely_empty() {
+            return (effective_dirs, vec![])
+        }
+        let mut face_data_vec = vec![[None ; 6] ; self.dims.chunk_height * self.dims.chunk_length * self.dims.chunk_width];
+        let mut kept_dirs = Vec::with_capacity(6);
+        if let Some(voxel) = chunk.only_1_type.clone() && !self.voxel_types[voxel.voxel_id()].is_completely_empty() {
+            for (i, ch) in around.iter().enumerate() {
+                if let Some(chunk2) = ch {
+                    if let Some(voxel) = chunk2.only_1_type.clone() && self.voxel_types[voxel.voxel_id()].is_completely_empty() {
+                        kept_dirs.push((i, DIR_MASK[i]));
+                    }
+                    else if let None = chunk2.only_1_type.clone() {
+                        kept_dirs.push((i, DIR_MASK[i]));
+                    }
+                }
+            }
+        }
+        else {
+            for i in 0..6 {
+                kept_dirs.push((i, DIR_MASK[i]));
+            }
+        }
+        for x in 0..self.dims.chunk_length_i {
+            for y in 0..self.dims.chunk_width_i {
+                for z in 0..self.dims.chunk_height_i {
+                    let index = x as usize + (y as usize * self.dims.chunk_length) + (z as usize * self.dims.chunk_slice_area);
+                    for (i, mask) in kept_dirs.iter() {
+                        face_data_vec[index][*i] = self.get_face_data_for_dir_first_pass((x,y,z), (*i, *mask), chunk, around, &mut effective_dirs);
+                    }
+                }
+            }
+        }
+        (effective_dirs, face_data_vec)
+    }
+    // Custom models (slopes, slabs, stairs...) are only in the full detail mesh, the coarser ones see them as full blocks
+    // their sides are hidden like the ones of cubes, the rest takes the light of the voxel itself
+    fn add_custom_models(&self, lod:&mut MeshLOD, chunk:&MapChunk<V>, around:[Option<&MapChunk<V>> ; 6]) {
+        if let Some(voxel) = &chunk.only_1_type && self.custom_triangles[voxel.voxel_id()].is_none() {
+            return
+        }
+        for x in 0..self.dims.chunk_length_i {
+            for y in 0..self.dims.chunk_width_i {
+                for z in 0..self.dims.chunk_height_i {
+                    let pos = Vec3D::new(x, y, z);
+                    let voxel = match chunk.get_at_local(pos, &self.dims) {
+                        Some(voxel) => voxel,
+                        None => continue
+                    };
+                    let triangles = match &self.custom_triangles[voxel.voxel_id()] {
+                        Some(triangles) => triangles,
+                        None => continue
+                    };
+                    let voxel_type = &self.voxel_types[voxel.voxel_id()];
+                    let orientation = voxel.orientation();
+                    let own_light = self.finished_light(Some(voxel));
+                    let center = Vec3Df::new(x as f32, y as f32, z as f32);
+                    for triangle in triangles {
+                        let (texture, light) = match triangle.side {
+                            Some(side) => {
+                                let dir = orient_dir(side, orientation);
+                                match chunk.get_voxel_in_dir(pos, around, &self.dims, dir) {
+                                    Some(other) if self.voxel_types[other.voxel_id()].empty_coming_from(OPPOSITE[dir] as u8, other.orientation()) => (voxel_type.texture_facing(dir, orientation).0, self.finished_light(Some(other))),
+                                    _ => continue
+                                }
+                            },
+                            None => (voxel_type.texture_facing(orient_dir(0, orientation), orientation).0, own_light)
+                        };
+                        // textures are laid flat on the side of the model the triangle is on, on the top otherwise
+                        let (u_axis, v_axis) = match triangle.side {
+                            Some(side) => PERPENDICULAR[side],
+                            None => PERPENDICULAR[0]
+                        };
+                        let uv = |point:Vec3Df| {(point.dot(&Vec3Df::new(u_axis.x as f32, u_axis.y as f32, u_axis.z as f32)), point.dot(&Vec3Df::new(v_axis.x as f32, v_axis.y as f32, v_axis.z as f32)))};
+                        let start_index = lod.x.len();
+                        lod.add_points(&triangle.points.map(|point| {model_to_world(point - Vec3Df::all_ones() * 0.5, orientation) + center}));
+                        let [p1, p2, p3] = [0, 1, 2].map(|corner| {
+                            let (u, v) = uv(triangle.points[corner]);
+                            TrianglePoint::new(start_index + corner, u, v, light.0, light.1, light.2)
+                        });
+                        lod.triangles.add_triangle(p1, p2, p3, texture as u32, 0);
+                    }
+                }
+            }
+        }
+    }
+    // (length, width, height) of the chunk in blocks of factor^3 voxels
+    fn get_grid(&self, factor:i32) -> (i32, i32, i32) {
+        (self.dims.chunk_length_i / factor, self.dims.chunk_width_i / factor, self.dims.chunk_height_i / factor)
+    }
+    fn get_lod(&self, face_data:&Vec<[Option<Face> ; 6]>, effective_dirs:[bool ; 6], grid:(i32, i32, i32), factor:i32) -> MeshLOD {
+        match self.mode {
+            MeshingMode::Naive => self.get_lod_naive(face_data, effective_dirs, grid, factor),
+            MeshingMode::Greedy => self.get_lod_greedy(face_data, effective_dirs, grid, factor),
+        }
+    }
+    // one quad per visible face, each cell of the grid being factor^3 voxels
+    fn get_lod_naive(&self, face_data:&Vec<[Option<Face> ; 6]>, effective_dirs:[bool ; 6], grid:(i32, i32, i32), factor:i32) -> MeshLOD {
+        let mut lod = MeshLOD::new(Vec::with_capacity(600), Vec::with_capacity(600), Vec::with_capacity(600), MeshTriangles::with_capacity(600));
+        if face_data.is_empty() {
+            return lod
+        }
+        let factor_f = factor as f32;
+        for x in 0..grid.0 {
+            for y in 0..grid.1 {
+                for z in 0..grid.2 {
+                    for i in 0..6 {
+                        if !effective_dirs[i] {
+                            continue
+                        }
+                        match face_data[grid_index(Vec3D::new(x, y, z), grid)][i] {
+                            Some(face) => add_face_quad(&mut lod, i, Vec3D::new(x as f32, y as f32, z as f32) * factor_f, Vec3Df::all_ones() * factor_f, (factor_f, factor_f), face),
+                            None => ()
+                        }
+                    }
+                }
+            }
+        }
+        lod
+    }
+    // For every direction, goes through the chunk slice by slice and merges faces with the same texture and light
+    // into rectangles : as wide as possible along the first perpendicular axis, then as long as possible along the second one
+    fn get_lod_greedy(&self, face_data:&Vec<[Option<Face> ; 6]>, effective_dirs:[bool ; 6], grid:(i32, i32, i32), factor:i32) -> MeshLOD {
+        let mut lod = MeshLOD::new(Vec::with_capacity(600), Vec::with_capacity(600), Vec::with_capacity(600), MeshTriangles::with_capacity(600));
+        if face_data.is_empty() {
+            return lod
+        }
+        let factor_f = factor as f32;
+        for i in 0..6 {
+            if !effective_dirs[i] {
+                continue
+            }
+            let (first_dir, second_dir) = PERPENDICULAR[i].clone();
+            let normal = Vec3D::new(1 - first_dir.x - second_dir.x, 1 - first_dir.y - second_dir.y, 1 - first_dir.z - second_dir.z);
+            let (first_length, second_length, normal_length) = (axis_length(first_dir, grid), axis_length(second_dir, grid), axis_length(normal, grid));
+            let mut merged = vec![false ; (first_length * second_length) as usize];
+            for layer in 0..normal_length {
+                merged.fill(false);
+                for second in 0..second_length {
+                    for first in 0..first_length {
+                        if merged[(first + second * first_length) as usize] {
+                            continue
+                        }
+                        let start = normal * layer + first_dir * first + second_dir * second;
+                        let face = match face_data[grid_index(start, grid)][i] {
+                            Some(face) => face,
+                            None => continue
+                        };
+                        let same = |first_offset:i32, second_offset:i32| {
+                            !merged[(first + first_offset + (second + second_offset) * first_length) as usize] && face_data[grid_index(start + first_dir * first_offset + second_dir * second_offset, grid)][i] == Some(face)
+                        };
+                        let mut width = 1;
+                        while first + width < first_length && same(width, 0) {
+                            width += 1;
+                        }
+                        let mut height = 1;
+                        while second + height < second_length && (0..width).all(|first_offset| {same(first_offset, height)}) {
+                            height += 1;
+                        }
+                        for second_offset in 0..height {
+                            for first_offset in 0..width {
+                                merged[(first + first_offset + (second + second_offset) * first_length) as usize] = true;
+                            }
+                        }
+                        let (u_s, v_s) = (width as f32 * factor_f, height as f32 * factor_f);
+                        let scaler = multiply_corresponding_nonzero(multiply_corresponding_nonzero(Vec3Df::all_ones() * factor_f, Vec3D::new(first_dir.x as f32, first_dir.y as f32, first_dir.z as f32) * (width as f32)), Vec3D::new(second_dir.x as f32, second_dir.y as f32, second_dir.z as f32) * (height as f32));
+                        add_face_quad(&mut lod, i, Vec3D::new(start.x as f32, start.y as f32, start.z as f32) * factor_f, scaler, (u_s, v_s), face);
+                    }
+                }
+            }
+        }
+        lod
+    }
+    // Chunk with every block of factor^3 voxels turned into one : solid if at least half of its voxels are,
+    // with the most common texture among them, None for empty blocks
+    fn downsample(&self, chunk:&MapChunk<V>, factor:i32) -> Vec<Option<u32>> {
+        let grid = self.get_grid(factor);
+        let block_count = (grid.0 * grid.1 * grid.2) as usize;
+        if let Some(voxel) = chunk.only_1_type.clone() {
+            let voxel_type = &self.voxel_types[voxel.voxel_id()];
+            let texture = if voxel_type.is_completely_empty() {None} else {Some(voxel_type.easy_texture() as u32)};
+            return vec![texture ; block_count]
+        }
+        let mut blocks = Vec::with_capacity(block_count);
+        let mut textures:Vec<(u32, i32)> = Vec::with_capacity(8);
+        for z in 0..grid.2 {
+            for y in 0..grid.1 {
+                for x in 0..grid.0 {
+                    textures.clear();
+                    let mut solid = 0;
+                    for dz in 0..factor {
+                        for dy in 0..factor {
+                            for dx in 0..factor {
+                                let voxel = chunk.get_at_local(Vec3D::new(x * factor + dx, y * factor + dy, z * factor + dz), &self.dims).unwrap();
+                                let voxel_type = &self.voxel_types[voxel.voxel_id()];
+                                if !voxel_type.is_completely_empty() {
+                                    solid += 1;
+                                    let texture = voxel_type.easy_texture() as u32;
+                                    match textures.iter_mut().find(|(known, _)| {*known == texture}) {
+                                        Some((_, count)) => *count += 1,
+                                        None => textures.push((texture, 1))
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    if solid * 2 >= factor * factor * factor {
+                        blocks.push(textures.iter().max_by_key(|(_, count)| {*count}).map(|(texture, _)| {*texture}));
+                    }
+                    else {
+                        blocks.push(None);
+                    }
+                }
+            }
+        }
+        blocks
+    }
+    // Light of a downsampled face : the light of the first full detail face of the block in the same direction,
+    // or of any of its faces if the block side is hidden at full detail
+    fn get_block_light(&self, face_data:&Vec<[Option<Face> ; 6]>, block_start:Vec3D<i32>, factor:i32, dir:usize) -> (u8,u8,u8) {
+        let mut fallback = None;
+        for dz in 0..factor {
+            for dy in 0..factor {
+                for dx in 0..factor {
+                    let faces = face_data[grid_index(block_start + Vec3D::new(dx, dy, dz), self.get_grid(1))];
+                    match faces[dir] {
+                        Some((_, _, light)) => return light,
+                        None => if fallback.is_none() {
+                            fallback = faces.iter().flatten().next().map(|(_, _, light)| {*light});
+                        }
+                    }
+                }
+            }
+        }
+        fallback.unwrap_or(self.min_light_levels)
+    }
+    // same as get_face_data but for the chunk downsampled by factor, a face is visible if the block next to it is empty
+    // blocks next to missing chunks are considered hidden, like at full detail
+    fn get_downsampled_face_data(&self, chunk:&MapChunk<V>, around:[Option<&MapChunk<V>> ; 6], factor:i32, face_data:&Vec<[Option<Face> ; 6]>) -> ([bool ; 6], Vec<[Option<Face> ; 6]>) {
+        let mut effective_dirs = [false ; 6];
+        if face_data.is_empty() {
+            return (effective_dirs, vec![])
+        }
+        let grid = self.get_grid(factor);
+        let blocks = self.downsample(chunk, factor);
+        let blocks_around = around.map(|other| {other.map(|other| {self.downsample(other, factor)})});
+        let mut coarse_faces = vec![[None ; 6] ; blocks.len()];
+        for x in 0..grid.0 {
+            for y in 0..grid.1 {
+                for z in 0..grid.2 {
+                    let pos = Vec3D::new(x, y, z);
+                    let index = grid_index(pos, grid);
+                    let texture = match blocks[index] {
+                        Some(texture) => texture,
+                        None => continue
+                    };
+                    for i in 0..6 {
+                        let next = pos + EXPLORATION[i];
+                        let next_empty = if next.in_origin_prism(grid.0, grid.1, grid.2) {
+                            blocks[grid_index(next, grid)].is_none()
+                        }
+                        else {
+                            match &blocks_around[i] {
+                                Some(other) => other[grid_index(Vec3D::new(next.x.rem_euclid(grid.0), next.y.rem_euclid(grid.1), next.z.rem_euclid(grid.2)), grid)].is_none(),
+                                None => false
+                            }
+                        };
+                        if next_empty {
+                            coarse_faces[index][i] = Some((texture, 0, self.get_block_light(face_data, pos * factor, factor, i)));
+                            effective_dirs[i] = true;
+                        }
+                    }
+                }
+            }
+        }
+        (effective_dirs, coarse_faces)
+    }
+}
+
+fn axis_length(axis:Vec3D<i32>, (length, width, height):(i32, i32, i32)) -> i32 {
+    if axis.x != 0 {
+        length
+    }
+    else if axis.y != 0 {
+        width
+    }
+    else {
+        height
+    }
+}
+
+fn grid_index(pos:Vec3D<i32>, (length, width, _):(i32, i32, i32)) -> usize {
+    (pos.x + pos.y * length + pos.z * length * width) as usize
+}
+
+// Adds the face of a voxel in direction dir, stretched by scaler, with its texture repeated (u_s, v_s) times
+// and turned by quarter_turns on the face
+fn add_face_quad(lod:&mut MeshLOD, dir:usize, pos:Vec3Df, scaler:Vec3Df, (u_s, v_s):(f32, f32), (full_texture, quarter_turns, finished_collux):Face) {
+    let start_index = lod.x.len();
+    let indices = TRIS_INDICES_UVS.1;
+    let uvs = TRIS_INDICES_UVS.2;
+    let mut points = TRIS_INDICES_UVS.0[dir];
+    points = points * scaler + pos + ((Vec3Df::all_ones() * 0.5).component_product(&scaler) - Vec3Df::all_ones() * 0.5);
+    lod.add_points(&points);
+    for triangle in [[0, 1, 2], [3, 4, 5]] {
+        let point = |corner:usize| {
+            let (u, v) = uvs[indices[corner]];
+            let (u, v) = match quarter_turns {
+                1 => (v * v_s, (1.0 - u) * u_s),
+                2 => ((1.0 - u) * u_s, (1.0 - v) * v_s),
+                3 => ((1.0 - v) * v_s, u * u_s),
+                _ => (u * u_s, v * v_s)
+            };
+            TrianglePoint::new(
+                indices[corner] + start_index,
+                u,
+                v,
+                finished_collux.0,
+                finished_collux.1,
+                finished_collux.2
+            )
+        };
+        lod.triangles.add_triangle(point(triangle[0]), point(triangle[1]), point(triangle[2]), full_texture, 0);
+    }
+}
+
+// Running totals of triangles with both meshing modes, printed by the server as road chunks get generated
+#[derive(Clone, Default)]
+pub struct MeshingComparison {
+    chunks:usize,
+    naive_triangles:usize,
+    greedy_triangles:usize,
+}
+
+impl MeshingComparison {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn add(&mut self, (naive_triangles, greedy_triangles):(usize, usize)) {
+        self.chunks += 1;
+        self.naive_triangles += naive_triangles;
+        self.greedy_triangles += greedy_triangles;
+    }
+    pub fn print(&self) {
+        if self.naive_triangles > 0 {
+            println!(
+                "Meshing : {} chunks, {} triangles naive, {} triangles greedy ({:.1}% fewer)",
+                self.chunks,
+                self.naive_triangles,
+                self.greedy_triangles,
+                100.0 - (self.greedy_triangles as f64/self.naive_triangles as f64) * 100.0
+            );
+        }
+    }
+}
+
+pub struct FinishedMesh {
+    pub chunk_pos:WorldChunkPos,
+    // MapChunk::mesh_version when the job was sent, the mesh is thrown away if the chunk changed since
+    pub version:u32,
+    pub lods:MeshLODS,
+}
+
+type MeshingJob = (usize, Box<dyn FnOnce() -> FinishedMesh + Send>);
+
+const MESHING_THREADS:usize = 4;
+
+// Meshing threads shared by every GameMap, results are sorted by map id so each map only gets its own
+// maps can share a mesh vec, so they each get an id from new_map_id
+pub struct MeshingPool {
+    job_sender:Sender<MeshingJob>,
+    finished:Arc<Mutex<HashMap<usize, Vec<FinishedMesh>>>>,
+    // last ChunkMesher of every map, see GameMap::get_mesher
+    meshers:Mutex<HashMap<usize, Arc<dyn Any + Send + Sync>>>,
+    next_map_id:AtomicUsize,
+}
+
+pub static MESHING_POOL:LazyLock<MeshingPool> = LazyLock::new(MeshingPool::new);
+
+impl MeshingPool {
+    fn new() -> Self {
+        let (job_sender, job_receiver) = mpmc::channel::<MeshingJob>();
+        let finished = Arc::new(Mutex::new(HashMap::with_capacity(4)));
+        for i in 0..MESHING_THREADS {
+            let job_receiver = job_receiver.clone();
+            let finished = finished.clone();
+            thread::Builder::new().name(format!("Chunk meshing {}", i)).spawn(move || {
+                while let Ok((map_id, job)) = job_receiver.recv() {
+                    let mesh = job();
+                    finished.lock().unwrap().entry(map_id).or_insert_with(|| {Vec::with_capacity(32)}).push(mesh);
+                }
+            }).unwrap();
+        }
+        Self { job_sender, finished, meshers:Mutex::new(HashMap::with_capacity(4)), next_map_id:AtomicUsize::new(0) }
+    }
+    pub fn new_map_id(&self) -> usize {
+        self.next_map_id.fetch_add(1, Ordering::Relaxed)
+    }
+    pub fn get_mesher<V:Voxel>(&self, map_id:usize) -> Option<Arc<ChunkMesher<V>>> {
+        self.meshers.lock().unwrap().get(&map_id).cloned().and_then(|mesher| {mesher.downcast().ok()})
+    }
+    pub fn set_mesher<V:Voxel>(&self, map_id:usize, mesher:Arc<ChunkMesher<V>>) {
+        self.meshers.lock().unwrap().insert(map_id, mesher);
+    }
+    pub fn submit<V:Voxel>(&self, map_id:usize, mesher:Arc<ChunkMesher<V>>, chunk:Arc<MapChunk<V>>, around:[Option<Arc<MapChunk<V>>> ; 6], version:u32) {
+        let chunk_pos = chunk.chunk_coord;
+        self.job_sender.send((map_id, Box::new(move || {
+            let lods = mesher.mesh_chunk(&chunk, around.each_ref().map(|around| {around.as_deref()}));
+            FinishedMesh { chunk_pos, version, lods }
+        }))).unwrap();
+    }
+    pub fn take_finished(&self, map_id:usize, budget:usize) -> Vec<FinishedMesh> {
+        match self.finished.lock().unwrap().get_mut(&map_id) {
+            Some(finished) => {
+                let amount = budget.min(finished.len());
+                finished.drain(..amount).collect()
+            },
+            None => Vec::new()
+        }
+    }
+}
@@ -1,9 +1,15 @@
-use std::{collections::{HashMap, VecDeque}, f32::consts::{PI, SQRT_2}, simd::{num::SimdFloat, Simd}, sync::{Arc, LazyLock}};
+use std::{collections::{HashMap, HashSet, VecDeque}, f32::consts::{PI, SQRT_2}, simd::{num::SimdFloat, Simd}, sync::{Arc, LazyLock}};
 
 use hord3::{defaults::default_rendering::vectorinator_binned::{Vectorinator, VectorinatorWrite, meshes::{Mesh, MeshID, MeshInstance, MeshLOD, MeshLODS, MeshLODType, MeshTriangles, TrianglePoint}, triangles::{collux_f32_a_u8, collux_one_simd_to_u8_level, collux_u8_a_f32, collux_u8_tuple_to_f32_simd}}, horde::{game_engine::{entity::Renderable, multiplayer::{Identify, MustSync}, world::{World, WorldEvent}}, geometry::{rotation::{Orientation, Rotation}, vec3d::{Vec3D, Vec3Df}}, rendering::RenderingBackend}, tests::engine_derive_test::TestRB};
 use to_from_bytes::{FromBytes, ToBytes};
 use to_from_bytes_derive::{FromBytes, ToBytes};
 use vec_sparse_grid::{SetGrid, SetGridUpdate};
+use chunk_compression::CompressedChunk;
+use mesher::{ChunkMesher, MESHING_POOL, MeshingMode};
+use voxel_models::{CustomModel, model_to_world, world_to_model};
+use edits::{VoxelEditRejected, VoxelEditRequest};
+use session::SessionMessage;
+use checksums::Checksum;
 
 use crate::{game_engine::CoolVoxel, game_map::road::Road};
 
@@ -13,6 +19,19 @@ pub mod raycaster;
 pub mod sparse_grid;
 pub mod vec_sparse_grid;
 pub mod road;
+pub mod storage;
+pub mod chunk_compression;
+pub mod mesher;
+pub mod light_engine;
+pub mod voxel_models;
+pub mod edits;
+pub mod players;
+pub mod vehicle_states;
+pub mod checksums;
+pub mod lobby;
+pub mod race;
+pub mod track;
+pub mod session;
 
 pub const VEC_LENGTH:usize = 4;
 pub const SET_CAPACITY:usize = 16;
@@ -75,11 +94,15 @@ impl VoxelLight {
     }
 }
 
-pub trait Voxel:Clone + Send + Sync + ToBytes + FromBytes {
+pub trait Voxel:Clone + PartialEq + Send + Sync + 'static + ToBytes + FromBytes {
     type VT:VoxelType;
     fn voxel_id(&self) -> usize;
     fn orientation(&self) -> u8;
     fn light_level(&self) -> VoxelLight;
+    fn set_light_level(&mut self, light:VoxelLight);
+    // how much of the sky reaches this voxel, 255 in the open, see light_engine
+    fn sky_light(&self) -> u8;
+    fn set_sky_light(&mut self, sky:u8);
     /*fn orientation_converted(&self) -> Orientation {
         // first 3 bits = which side of the voxel is the underside against
         // 000 => base
@@ -121,41 +144,35 @@ pub trait VoxelType:Clone + Send + Sync + ToBytes + FromBytes {
     fn vertices_taken(&self) -> u8;
     fn kind_of_model(&self) -> VoxelModel;
     fn light_passthrough(&self) -> VoxelLight;
+    // light given off by voxels of this type, spread by the light engine when they are placed
+    fn light_source(&self) -> Option<VoxelLight> {
+        None
+    }
+    // shape of voxels of this type when kind_of_model is VoxelModel::Custom
+    fn custom_model(&self) -> Option<&CustomModel> {
+        None
+    }
     fn empty_coming_from(&self, from:u8, orientation:u8) -> bool {
         let rotated_2_empty = self.empties_with_orientation(orientation);
         rotated_2_empty >> (from as u32) & 1 == 1
     }
     fn empties_with_orientation(&self, orientation:u8) -> u8 {
-        let rotat_1 = (orientation >> 3) & 0b00000011;
         let self_empty = self.sides_empty();
-        let mut rotated_1_empty:u8 = self_empty;
-        for (i, rotates) in ROTATES_ON_1.into_iter().enumerate() {
-            let target = LUT_ROTAT_1[rotat_1 as usize][i];
-            if target >= rotates {
-
-                rotated_1_empty |= self_empty & (1 << rotates) << (target - rotates)
-            }
-            else {
-                rotated_1_empty |= self_empty & (1 << rotates) >> (rotates - target)
-            }
-        }
-        let mut rotated_2_empty:u8 = 0;
-        let rotat_2 = orientation & 0b00000111;
-        for i in [0, 1, 2, 3, 4, 5] {
-            let target = LUT_ROTAT_2[rotat_2 as usize][i as usize];
-            if target >= i {
-
-                rotated_2_empty |= rotated_1_empty & (1 << i) << (target - i)
-            }
-            else {
-                rotated_2_empty |= rotated_1_empty & (1 << i) >> (i - target)
+        let mut rotated_empty:u8 = 0;
+        for i in 0..6 {
+            if (self_empty >> i) & 1 == 1 {
+                rotated_empty |= DIR_MASK[orient_dir(i, orientation)];
             }
         }
-        if self_empty != rotated_2_empty {
-            dbg!(self_empty, rotated_2_empty, rotated_1_empty);
-        }   
-        
-        rotated_2_empty
+        rotated_empty
+    }
+    // texture of the side of the voxel facing dir once oriented, and how many quarter turns it is rotated by on that side
+    fn texture_facing(&self, dir:usize, orientation:u8) -> (usize, u8) {
+        let texture = match self.kind_of_model() {
+            VoxelModel::SpecifiedTexture(textures) => textures[model_face(dir, orientation)],
+            _ => self.easy_texture()
+        };
+        (texture, face_quarter_turns(dir, orientation))
     }
     fn full_coming_from(&self, from:u8, orientation:u8) -> bool {
         !self.empty_coming_from(from, orientation)
@@ -201,6 +218,8 @@ const PERPENDICULAR:[(Vec3D<i32>, Vec3D<i32>) ; 6] = [
     (Vec3D::new(1, 0, 0), Vec3D::new(0, 0, 1)),
 ];
 
+const OPPOSITE:[usize ; 6] = [2, 3, 0, 1, 5, 4];
+
 // first 3 bits = which side of the voxel is the underside against
 // 000 => base
 // 001 => backside
@@ -212,26 +231,74 @@ const PERPENDICULAR:[(Vec3D<i32>, Vec3D<i32>) ; 6] = [
 // 00 => no rotation
 // 01 => pi/2
 // 10 => pi
-// 11 => -pi
-const ROTATES_ON_1:[u32 ; 4] = [
-    1, 3, 4, 5
-];
+// 11 => -pi/2
+// Where a direction of the voxel model ends up in the world : turned around the underside first, then the underside put against its side
+pub fn orient_vector(v:Vec3D<i32>, orientation:u8) -> Vec3D<i32> {
+    let mut turned = v;
+    for _ in 0..((orientation >> 3) & 0b00000011) {
+        turned = Vec3D::new(-turned.y, turned.x, turned.z);
+    }
+    let (x, y, z) = (turned.x, turned.y, turned.z);
+    match orientation & 0b00000111 {
+        1 => Vec3D::new(z, y, -x),
+        2 => Vec3D::new(x, -y, -z),
+        3 => Vec3D::new(-z, y, x),
+        4 => Vec3D::new(x, z, -y),
+        5 => Vec3D::new(x, -z, y),
+        _ => turned
+    }
+}
 
-const LUT_ROTAT_1:[[u32 ; 4] ; 4] = [
-    [1, 3, 4, 5],
-    [3, 4, 5, 1],
-    [4, 5, 1, 3],
-    [5, 1, 3, 4]
-];
+pub fn orient_dir(dir:usize, orientation:u8) -> usize {
+    let oriented = orient_vector(EXPLORATION[dir], orientation);
+    EXPLORATION.iter().position(|explo| {*explo == oriented}).unwrap()
+}
 
-const LUT_ROTAT_2:[[u32 ; 6]; 6] = [
-    [0, 1, 2, 3, 4, 5],
-    [1, 2, 3, 0, 4, 5],
-    [2, 3, 0, 1, 4, 5],
-    [3, 0, 1, 2, 4, 5],
-    [4, 1, 5, 3, 2, 0],
-    [5, 1, 4, 3, 0, 2],
-];
+// which side of the voxel model faces dir once oriented
+pub fn model_face(dir:usize, orientation:u8) -> usize {
+    (0..6).find(|model_dir| {orient_dir(*model_dir, orientation) == dir}).unwrap()
+}
+
+// quarter turns of the texture on the side facing dir, from where the first in-face axis of the model side ends up
+pub fn face_quarter_turns(dir:usize, orientation:u8) -> u8 {
+    let turned = orient_vector(PERPENDICULAR[model_face(dir, orientation)].0, orientation);
+    let (first, second) = PERPENDICULAR[dir];
+    if turned == first {
+        0
+    }
+    else if turned == second {
+        1
+    }
+    else if turned == first * -1 {
+        2
+    }
+    else {
+        3
+    }
+}
+
+// orientation putting the underside of a voxel against the side in direction underside_dir, turned quarter_turns times around it
+pub fn orientation_from(underside_dir:usize, quarter_turns:u8) -> u8 {
+    let underside = match underside_dir {
+        2 => 0,
+        3 => 1,
+        0 => 2,
+        1 => 3,
+        4 => 4,
+        _ => 5
+    };
+    underside | ((quarter_turns & 0b00000011) << 3)
+}
+
+// quarter turns around z bringing the x axis closest to v
+pub fn quarter_turns_towards(v:Vec3Df) -> u8 {
+    ((v.y.atan2(v.x) / (PI/2.0)).round() as i32).rem_euclid(4) as u8
+}
+
+// direction of EXPLORATION closest to v
+pub fn closest_dir(v:Vec3Df) -> usize {
+    (0..6).max_by(|a, b| {v.dot(&get_float_pos(EXPLORATION[*a])).total_cmp(&v.dot(&get_float_pos(EXPLORATION[*b])))}).unwrap()
+}
 
 const EMPTY_VOXEL:u8 = 0b00111111;
 
@@ -239,19 +306,37 @@ const EMPTY_VOXEL:u8 = 0b00111111;
 pub struct MapChunk<V:Voxel> {
     voxels:Vec<V>,
     only_1_type:Option<V>,
+    // chunks that aren't being edited keep every distinct voxel once and its index for each position instead of voxels, see compact
+    palette:Vec<V>,
+    palette_indices:Vec<u8>,
     origin_worldpos:WorldVoxelPos,
     chunk_coord:WorldChunkPos,
     mesh_id:Option<usize>,
     mesh_updated:bool,
     mesh_instance:Option<usize>,
+    // bumped every time the chunk needs a new mesh, to recognize outdated meshes coming back from the meshing threads
+    mesh_version:u32,
+    queued_version:Option<u32>,
 }
 
 #[derive(Clone, ToBytes, FromBytes, PartialEq)]
 pub enum GameMapEvent<V:Voxel, G:Generator<V>> {
     UpdateVoxelAt(WorldVoxelPos, V),
     UpdateSetGrid(SetGridUpdate),
-    NewChunk(WorldChunkPos, MapChunk<V>),
-    UpdateGenerator(G)
+    NewChunk(WorldChunkPos, CompressedChunk<V>),
+    UpdateGenerator(G),
+    // server dropped these chunks from memory, clients drop them too
+    UnloadChunks(Vec<WorldChunkPos>),
+    // a client wants chunks it dropped itself (view distance) back, answered with NewChunk
+    RequestChunks(Vec<WorldChunkPos>),
+    // hash of the server's voxel registry, sent regularly so clients can check they use the same one
+    RegistryHash(u64),
+    // edit asked by a client, shown right away on that client and checked by the server, see edits
+    RequestVoxelEdit(VoxelEditRequest<V>),
+    // the server refused an edit, every client goes back to the server's voxel
+    VoxelEditRejected(VoxelEditRejected<V>),
+    // players, lobby, race, vehicle states and checksums, handled by the server and client loops, see session
+    Session(SessionMessage),
 }
 
 impl<ID:Identify, V:Voxel, G:Generator<V>> WorldEvent<GameMap<V, G>, ID> for GameMapEvent<V, G> {
@@ -260,19 +345,55 @@ impl<ID:Identify, V:Voxel, G:Generator<V>> WorldEvent<GameMap<V, G>, ID> for Gam
     }
     fn should_sync(&self) -> MustSync {
         match self {
-            Self::NewChunk(_, _) => MustSync::Server,
+            Self::UpdateVoxelAt(_, _) | Self::NewChunk(_, _) | Self::UpdateGenerator(_) | Self::UnloadChunks(_) | Self::RegistryHash(_) | Self::VoxelEditRejected(_) => MustSync::Server,
+            Self::RequestChunks(_) | Self::RequestVoxelEdit(_) => MustSync::Client,
+            Self::Session(message) => message.should_sync(),
             _ => MustSync::Both
         }
     }
     fn apply_event(self, world:&mut GameMap<V, G>) {
+        if world.recording {
+            match &self {
+                GameMapEvent::UpdateVoxelAt(_, _) | GameMapEvent::NewChunk(_, _) | GameMapEvent::UnloadChunks(_) => world.recorded_events.push(self.clone()),
+                _ => ()
+            }
+        }
         match self {
-            GameMapEvent::UpdateVoxelAt(pos, new_voxel) => {world.get_voxel_at_mut(pos).and_then(|vox| { *vox = new_voxel; None::<()>}); world.modified_this_pos_signal_remesh(pos);},
+            GameMapEvent::UpdateVoxelAt(pos, new_voxel) => world.replace_voxel_at(pos, new_voxel),
             GameMapEvent::UpdateSetGrid(set_grid_update) => world.set_grid.apply_update::<VEC_LENGTH, SET_CAPACITY>(set_grid_update),
+            // chunks sent for players far away aren't kept, only remembered so they can be asked for once in view
+            GameMapEvent::NewChunk(chunk_pos, _) if world.is_out_of_view(chunk_pos) => world.chunks_out_of_view.push(chunk_pos),
             GameMapEvent::NewChunk(chunk_pos, chunk) => {
-                world.chunks.insert(chunk_pos, chunk);
+                world.insert_chunk(chunk_pos, chunk.decompress());
+                world.light_new_chunk(chunk_pos);
+                world.compact_chunk(chunk_pos);
                 world.modified_this_pos_signal_remesh(world.get_chunk_dims_vector().component_product(&chunk_pos));
             },
-            GameMapEvent::UpdateGenerator(generator) => world.generator = generator
+            // the server keeps stepping its own road, an older copy coming back would undo it
+            GameMapEvent::UpdateGenerator(generator) => if !world.authoritative {
+                world.generator = generator;
+            },
+            GameMapEvent::UnloadChunks(chunks) => {world.unload_chunks(&chunks);},
+            GameMapEvent::RequestChunks(chunks) => world.chunk_requests.extend(chunks),
+            GameMapEvent::RegistryHash(hash) => if hash != world.registry_hash {
+                world.registry_mismatch = Some(hash);
+            },
+            GameMapEvent::RequestVoxelEdit(request) => if world.authoritative {
+                world.edit_requests.push(request);
+            }
+            else {
+                world.replace_voxel_at(request.pos, request.voxel);
+            },
+            GameMapEvent::VoxelEditRejected(rejected) => if !world.authoritative {
+                match &rejected.voxel {
+                    Some(voxel) => world.replace_voxel_at(rejected.pos, voxel.clone()),
+                    None => ()
+                }
+                world.edit_rejections.push(rejected);
+            },
+            GameMapEvent::Session(message) => if message.is_kept_by(world.authoritative) {
+                world.session_messages.push(message);
+            },
         }
     }
 }
@@ -285,72 +406,101 @@ impl<V:Voxel, ID:Identify, G:Generator<V>> World<ID> for GameMap<V, G> {
     }
 }
 
+// at most that many chunks of a map are waiting on the meshing threads at once
+const MESHES_IN_FLIGHT_BUDGET:usize = 64;
+// at most that many finished meshes get sent to the renderer per frame
+const MESH_UPLOAD_BUDGET:usize = 16;
+// how fast chunk meshes switch to their downsampled LODs with distance, see mesher::LOD_FACTORS
+const CHUNK_LOD_FACTOR:f32 = 0.025;
+
 impl<'a, V:Voxel, G:Generator<V>> Renderable<VectorinatorWrite<'a>> for GameMap<V, G> {
     fn do_render_changes(&mut self, render_data:&mut VectorinatorWrite<'a>) {
+        let mesh_vec = self.mesh_vec;
+        for (mesh_id, instance) in self.unloaded_meshes.drain(..) {
+            render_data.meshes.instances[mesh_vec].get_instance_mut(instance).change_visibility(false);
+            self.free_mesh_slots.push((mesh_id, instance));
+        }
         if !self.rendering_up_to_date {
-            let mut iterator = if self.remesh_fasttrack.len() > 0 {
-                self.remesh_fasttrack.clone()
+            let dims_f = self.get_chunk_dims_vector_f();
+            let camera_pos = render_data.camera.pos;
+            self.remesh_queue.retain(|pos| {self.chunks.get(pos).is_some_and(|chunk| {!chunk.mesh_updated && chunk.queued_version != Some(chunk.mesh_version)})});
+            // edited chunks first, then closest to the camera first
+            let mut to_mesh:Vec<(bool, f32, WorldChunkPos)> = self.remesh_queue.iter().map(|pos| {
+                let center = (get_float_pos(*pos) + Vec3Df::all_ones() * 0.5).component_product(&dims_f);
+                (!self.remesh_fasttrack.contains(pos), center.dist(&camera_pos), *pos)
+            }).collect();
+            to_mesh.sort_by(|a, b| {a.0.cmp(&b.0).then(a.1.total_cmp(&b.1))});
+            let can_send = MESHES_IN_FLIGHT_BUDGET.saturating_sub(self.meshes_in_flight).min(to_mesh.len());
+            if can_send > 0 {
+                let mesher = self.get_mesher();
+                // every chunk is copied once per batch, jobs that need it as a neighbour share that copy
+                let mut copies = HashMap::with_capacity(can_send * 2);
+                for (_, _, pos) in &to_mesh[..can_send] {
+                    let around = EXPLORATION.map(|dir| {shared_copy(&self.chunks, *pos + dir, &mut copies)});
+                    let chunk = shared_copy(&self.chunks, *pos, &mut copies).unwrap();
+                    MESHING_POOL.submit(self.map_id, mesher.clone(), chunk, around, self.chunks[pos].mesh_version);
+                    let chunk = self.chunks.get_mut(pos).unwrap();
+                    chunk.queued_version = Some(chunk.mesh_version);
+                    self.remesh_queue.remove(pos);
+                    self.meshes_in_flight += 1;
+                }
+                self.remesh_fasttrack.retain(|pos| {!to_mesh[..can_send].iter().any(|(_, _, sent)| {sent == pos})});
             }
-            else {
-                self.chunks.keys().map(|vector| {vector.clone()}).collect::<Vec<Vec3D<i32>>>()
-            };
-            self.remesh_fasttrack.clear();
-            //let mut test_chunk_tris = 0;
-            //let mut test_chunk_id = 0;
-            let mesh_vec = self.mesh_vec;
-            for pos in iterator {
-                let around = self.get_chunks_around(pos);
-                let chunk = self.chunks.get(&pos).unwrap();
-                if chunk.mesh_updated == false || self.forced_rerender {
-                    let mut lods = Vec::new();
-                    if self.dims.chunk_height_i == self.dims.chunk_length_i && self.dims.chunk_length_i == self.dims.chunk_width_i && self.dims.chunk_height_i >= 8 {
-                        let (first_lod, effective_dirs, mut face_data) = self.get_lod_with_1_step(chunk, around);
-                        lods.push(MeshLODType::Mesh(Arc::new(first_lod)));
-                        const STEPS:[i32 ; 3] = [2, 4, 8];
-                        for step in STEPS {
-                            lods.push(MeshLODType::Mesh(Arc::new( self.get_lod_with_step(chunk, around, ((step/2) as usize, step), effective_dirs, &mut face_data))));
-                        }
-                    }
-                    else {
-                        lods.push(MeshLODType::Mesh(Arc::new( self.get_lod_without_step(chunk, around))));
-                    }
-                    lods.reverse();
-                    
-                    let size = self.dims.chunk_length_f*SQRT_2;
-                    let height = self.dims.chunk_height_f;
-                    let mut chunk = self.get_chunk_at_mut(pos).unwrap();
-                    match chunk.mesh_id {
-                        Some(id) => {
-                            render_data.meshes.set_mesh(&MeshID::Referenced(id), Mesh::new(MeshLODS::new(lods), format!("Chunk {} {} {} {}", pos.x, pos.y, pos.z, mesh_vec), size).with_lod_factor(0.025));
-                        },
-                        None => {
-                            /*let mut local_cool = false;
-                            if lod.triangles.len() > test_chunk_tris {
-                                test_chunk_tris = lod.triangles.len();
-                                local_cool = true;
-                            }*/
-                            let id = render_data.meshes.add_mesh(Mesh::new(MeshLODS::new(lods), format!("Chunk {} {} {} {}", pos.x, pos.y, pos.z, mesh_vec), size).with_lod_factor(0.025));
-                            /*if local_cool {
-                                test_chunk_id = id;
-                            }*/
-                            
-                            chunk.mesh_id = Some(id);   
-                        }
+            let still_waiting = self.remesh_queue.len();
+
+            let size = self.dims.chunk_length_f*SQRT_2;
+            let height = self.dims.chunk_height_f;
+            let finished = MESHING_POOL.take_finished(self.map_id, MESH_UPLOAD_BUDGET);
+            let uploaded_any = !finished.is_empty();
+            for mesh in finished {
+                self.meshes_in_flight = self.meshes_in_flight.saturating_sub(1);
+                let pos = mesh.chunk_pos;
+                // chunks can get unloaded or modified while their mesh is being built, a new one gets sent in the second case
+                match self.chunks.get(&pos) {
+                    Some(chunk) if chunk.mesh_version == mesh.version => (),
+                    _ => continue
+                }
+                if self.chunks[&pos].mesh_id.is_none() && let Some((mesh_id, instance)) = self.free_mesh_slots.pop() {
+                    // reuse the mesh and instance of an unloaded chunk instead of growing the renderer's vectors
+                    render_data.meshes.instances[mesh_vec].get_instance_mut(instance).change_pos(get_float_pos(pos) * height);
+                    let chunk = self.get_chunk_at_mut(pos).unwrap();
+                    chunk.mesh_id = Some(mesh_id);
+                    chunk.mesh_instance = Some(instance);
+                }
+                let mut chunk = self.get_chunk_at_mut(pos).unwrap();
+                match chunk.mesh_id {
+                    Some(id) => {
+                        render_data.meshes.set_mesh(&MeshID::Referenced(id), Mesh::new(mesh.lods, format!("Chunk {} {} {} {}", pos.x, pos.y, pos.z, mesh_vec), size).with_lod_factor(CHUNK_LOD_FACTOR));
+                    },
+                    None => {
+                        let id = render_data.meshes.add_mesh(Mesh::new(mesh.lods, format!("Chunk {} {} {} {}", pos.x, pos.y, pos.z, mesh_vec), size).with_lod_factor(CHUNK_LOD_FACTOR));
+                        chunk.mesh_id = Some(id);   
                     }
-                    match chunk.mesh_instance {
-                        Some(index) => render_data.meshes.instances[mesh_vec].get_instance_mut(index).change_visibility(true),
-                        None => {
-                            chunk.mesh_instance = Some(render_data.meshes.add_instance(MeshInstance::new(get_float_pos(pos) * height, Orientation::zero(), MeshID::Referenced(chunk.mesh_id.unwrap()), true, false, false), mesh_vec))
-                        }
+                }
+                match chunk.mesh_instance {
+                    Some(index) => render_data.meshes.instances[mesh_vec].get_instance_mut(index).change_visibility(true),
+                    None => {
+                        chunk.mesh_instance = Some(render_data.meshes.add_instance(MeshInstance::new(get_float_pos(pos) * height, Orientation::zero(), MeshID::Referenced(chunk.mesh_id.unwrap()), true, false, false), mesh_vec))
                     }
-                    chunk.mesh_updated = true;
                 }
+                chunk.mesh_updated = true;
+            }
+            if uploaded_any {
+                render_data.meshes.change_buffer_size_for_instance_vec(self.mesh_vec, 1);
             }
-            // render_data.meshes.add_instance(MeshInstance::new(Vec3Df::new(5.0, 5.0, 5.0), Orientation::zero(), MeshID::Referenced(test_chunk_id), true, false, true), 3);
-            render_data.meshes.change_buffer_size_for_instance_vec(self.mesh_vec, 1);
-            self.rendering_up_to_date = true;
-            self.forced_rerender = false;
-            // YAHOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOOo
+            self.rendering_up_to_date = still_waiting == 0 && self.meshes_in_flight == 0;
+        }
+    }
+}
+
+// copy of the chunk at pos behind an Arc, made the first time a meshing job of the batch needs it
+fn shared_copy<V:Voxel>(chunks:&HashMap<WorldChunkPos, MapChunk<V>>, pos:WorldChunkPos, copies:&mut HashMap<WorldChunkPos, Arc<MapChunk<V>>>) -> Option<Arc<MapChunk<V>>> {
+    match copies.get(&pos) {
+        Some(copy) => Some(copy.clone()),
+        None => {
+            let copy = Arc::new(chunks.get(&pos)?.clone());
+            copies.insert(pos, copy.clone());
+            Some(copy)
         }
     }
 }
@@ -445,12 +595,15 @@ impl<V:Voxel> MapChunk<V> {
     pub fn new(orig_worldpos:WorldVoxelPos, chunk_pos:WorldChunkPos, data:Vec<V>) -> Self {
         let mut only_one = Some(data[0].clone());
         for voxel in &data {
-            if let Some(v) = only_one.clone() && v.voxel_id() != voxel.voxel_id() {
+            // lights are part of the voxel, so only chunks of identical voxels can drop them
+            if let Some(v) = only_one.clone() && v != *voxel {
                 only_one = None;
                 break;
             } 
         }
-        Self { voxels:data, origin_worldpos:orig_worldpos, chunk_coord:chunk_pos, mesh_id: None, mesh_updated: false, mesh_instance: None, only_1_type:only_one }
+        // voxels of uniform chunks are never read, only_1_type is used instead
+        let voxels = if only_one.is_some() {Vec::new()} else {data};
+        Self { voxels, palette:Vec::new(), palette_indices:Vec::new(), origin_worldpos:orig_worldpos, chunk_coord:chunk_pos, mesh_id: None, mesh_updated: false, mesh_instance: None, mesh_version:0, queued_version:None, only_1_type:only_one }
     }
     pub fn get_at_local(&self, pos:WorldVoxelPos, dims:&ChunkDims) -> Option<&V> {
         if pos.in_origin_prism(dims.chunk_length_i, dims.chunk_width_i, dims.chunk_height_i) {
@@ -461,10 +614,24 @@ impl<V:Voxel> MapChunk<V> {
         }
     }
     fn get_voxel_data(&self, pos:WorldVoxelPos, dims:&ChunkDims) -> &V {
-        self.only_1_type.as_ref().unwrap_or_else(|| {&self.voxels[pos.x as usize + (pos.y as usize * dims.chunk_length) + (pos.z as usize * dims.chunk_slice_area)]}) 
+        let index = pos.x as usize + (pos.y as usize * dims.chunk_length) + (pos.z as usize * dims.chunk_slice_area);
+        match &self.only_1_type {
+            Some(voxel) => voxel,
+            None if !self.palette.is_empty() => &self.palette[self.palette_indices[index] as usize],
+            None => &self.voxels[index]
+        }
     }
     fn get_voxel_data_mut(&mut self, pos:WorldVoxelPos, dims:&ChunkDims) -> &mut V {
-        self.only_1_type.as_mut().unwrap_or_else(|| {&mut self.voxels[pos.x as usize + (pos.y as usize * dims.chunk_length) + (pos.z as usize * dims.chunk_slice_area)]})
+        // writing into a uniform or compacted chunk must only change that one voxel, so it gets its own voxels back
+        if let Some(voxel) = self.only_1_type.take() {
+            self.voxels = vec![voxel ; dims.chunk_slice_area * dims.chunk_height];
+        }
+        if !self.palette.is_empty() {
+            self.voxels = self.palette_indices.iter().map(|index| {self.palette[*index as usize].clone()}).collect();
+            self.palette = Vec::new();
+            self.palette_indices = Vec::new();
+        }
+        &mut self.voxels[pos.x as usize + (pos.y as usize * dims.chunk_length) + (pos.z as usize * dims.chunk_slice_area)]
     }
     fn get_at_worldpos(&self, pos:WorldVoxelPos, dims:&ChunkDims) -> Option<&V> {
         self.get_at_local(pos - self.origin_worldpos, dims)
@@ -492,8 +659,39 @@ impl<V:Voxel> MapChunk<V> {
             }
         }
     }
+    // switches to a palette of up to 256 voxels when it takes less room than the voxels themselves
+    // lights are part of the voxels, so this is done once the chunk is lit
+    pub fn compact(&mut self) {
+        if self.only_1_type.is_some() || !self.palette.is_empty() {
+            return
+        }
+        let mut palette:Vec<V> = Vec::with_capacity(16);
+        let mut indices = Vec::with_capacity(self.voxels.len());
+        for voxel in &self.voxels {
+            let index = match palette.iter().position(|known| {known == voxel}) {
+                Some(index) => index,
+                None => {
+                    if palette.len() > u8::MAX as usize {
+                        return
+                    }
+                    palette.push(voxel.clone());
+                    palette.len() - 1
+                }
+            };
+            indices.push(index as u8);
+        }
+        if palette.len() * size_of::<V>() + indices.len() < self.voxels.len() * size_of::<V>() {
+            self.palette = palette;
+            self.palette_indices = indices;
+            self.voxels = Vec::new();
+        }
+    }
+    pub fn is_compacted(&self) -> bool {
+        !self.palette.is_empty()
+    }
     fn mark_for_remesh(&mut self) {
         self.mesh_updated = false;
+        self.mesh_version = self.mesh_version.wrapping_add(1);
     }
     fn get_at_worldpos_mut(&mut self, pos:WorldVoxelPos, dims:&ChunkDims) -> Option<&mut V> {
         self.get_at_local_mut(pos - self.origin_worldpos, dims)
@@ -514,7 +712,7 @@ impl<V:Voxel> MapChunk<V> {
                 let mut self_empty = voxel_types[voxel.voxel_id()].empties_with_orientation(voxel.orientation());
                 for (i, dir) in EXPLORATION.iter().enumerate() {
                     match voxels_around[i] {
-                        Some(vox_a) => self_empty |= ((voxel_types[vox_a.voxel_id()].empty_coming_from(i as u8, vox_a.orientation()) as u8) << i),
+                        Some(vox_a) => self_empty |= ((voxel_types[vox_a.voxel_id()].empty_coming_from(OPPOSITE[i] as u8, vox_a.orientation()) as u8) << i),
                         None => ()
                     } 
                 }
@@ -531,7 +729,8 @@ impl<V:Voxel> MapChunk<V> {
                 let mut self_empty = voxel_types[voxel.voxel_id()].empty_coming_from(direction as u8, voxel.orientation());
                 if !self_empty {
                     match self.get_voxel_in_dir(pos, chunks_around, dims, direction) {
-                        Some(vox_a) => self_empty || voxel_types[vox_a.voxel_id()].empty_coming_from(direction as u8, vox_a.orientation()),
+                        // the side of the other voxel touching this one
+                        Some(vox_a) => self_empty || voxel_types[vox_a.voxel_id()].empty_coming_from(OPPOSITE[direction] as u8, vox_a.orientation()),
                         None => self_empty
                     }
                 }   
@@ -577,10 +776,40 @@ pub struct GameMap<V:Voxel, G:Generator<V>> {
     dims:ChunkDims,
     voxel_types:Vec<V::VT>,
     mesh_vec:usize,
+    // key of this map's mesher and finished meshes in MESHING_POOL, clones share it
+    map_id:usize,
     rendering_up_to_date:bool,
-    forced_rerender:bool,
     min_light_levels:(u8,u8,u8),
     remesh_fasttrack:Vec<WorldChunkPos>,
+    // chunks waiting for a new mesh, the only ones looked at when rendering
+    remesh_queue:HashSet<WorldChunkPos>,
+    // the mesher kept by MESHING_POOL doesn't match the light or mode anymore
+    mesher_outdated:bool,
+    meshes_in_flight:usize,
+    // chunks edited since the last save, only kept when the map is saved, see storage::RegionStorage::save_dirty
+    saving:bool,
+    dirty_chunks:HashSet<WorldChunkPos>,
+    // (mesh id, instance index) of unloaded chunks, hidden on the next render then reused for new chunks
+    unloaded_meshes:Vec<(usize, usize)>,
+    free_mesh_slots:Vec<(usize, usize)>,
+    chunk_requests:Vec<WorldChunkPos>,
+    // (camera position, view distance in chunks) of a client, see set_view_limit
+    view_limit:Option<(Vec3Df, f32)>,
+    chunks_out_of_view:Vec<WorldChunkPos>,
+    meshing_mode:MeshingMode,
+    // hash of the voxel registry voxel_types come from, and the server's one if it's different
+    registry_hash:u64,
+    registry_mismatch:Option<u64>,
+    // the server's map, that checks voxel edits from clients instead of applying them
+    authoritative:bool,
+    edit_requests:Vec<VoxelEditRequest<V>>,
+    edit_rejections:Vec<VoxelEditRejected<V>>,
+    session_messages:Vec<SessionMessage>,
+    // chunks edited since the last checksums, only kept on the server
+    edited_chunks:HashSet<WorldChunkPos>,
+    // events that changed the chunks, kept while a replay is recorded, see replay
+    recording:bool,
+    recorded_events:Vec<GameMapEvent<V, G>>,
     pub set_grid:SetGrid,
     pub generator:G
 }
@@ -613,546 +842,153 @@ fn multiply_corresponding_nonzero(mut scaler:Vec3Df, dir:Vec3Df) -> Vec3Df {
 
 impl<V:Voxel, G:Generator<V>> GameMap<V, G> {
     pub fn new(expected_chunks:usize, dims:ChunkDims, voxel_types:Vec<V::VT>, min_light_levels:(u8,u8,u8), mesh_vec:usize, generator:G) -> Self {
-        Self { chunks: HashMap::with_capacity(expected_chunks), dims, voxel_types, forced_rerender:false, min_light_levels, mesh_vec, rendering_up_to_date: false, remesh_fasttrack:Vec::with_capacity(16), set_grid:SetGrid::new(5.0, Vec3D::all_ones() * -15, Vec3D::all_ones() * 15), generator }
-    }
-    fn get_lod_without_step(&self, chunk:&MapChunk<V>, around:[Option<&MapChunk<V>> ; 6]) -> MeshLOD {
-        let mut x = Vec::with_capacity(600);
-        let mut y = Vec::with_capacity(600);
-        let mut z = Vec::with_capacity(600);
-        let mut triangles = MeshTriangles::with_capacity(600);
-        let mut lod = MeshLOD::new(x, y, z, triangles);
-        for x in 0..self.dims.chunk_length_i {
-            for y in 0..self.dims.chunk_width_i {
-                for z in 0..self.dims.chunk_height_i {
-
-                    let voxel = chunk.get_at_local(Vec3D::new(x, y, z), &self.dims).unwrap();
-                    if !&self.voxel_types[voxel.voxel_id()].is_completely_empty() {
-                        let empty_dirs = chunk.get_empty_directions_local(Vec3D::new(x, y, z), &self.dims, around, &self.voxel_types);
-                        let lights = chunk.get_lights_local(Vec3D::new(x, y, z), &self.dims, around);
-                        //dbg!(Vec3D::new(x, y, z));
-                        for (i, mask) in DIR_MASK.iter().enumerate() {
-                            /*if empty_dirs != 0 {
-                                dbg!(empty_dirs);
-                            }*/
-                            if empty_dirs & mask  == *mask { //if a direction is empty (hint:it's a me, Mario)
-                                let mut points = TRIS_INDICES_UVS.0[i];
-                                let indices = TRIS_INDICES_UVS.1;
-                                let uvs = TRIS_INDICES_UVS.2;
-                                let start_index = lod.x.len();
-                                let mut level = (lights[i].level.max(50) as f32) / 255.0;
-                                let mut converted_collux = collux_u8_a_f32((lights[i].r, lights[i].g, lights[i].b));
-                                let mut finished_collux = collux_f32_a_u8((converted_collux.0 * level, converted_collux.1 * level, converted_collux.2 * level));
-                                finished_collux = (finished_collux.0.max(self.min_light_levels.0), finished_collux.1.max(self.min_light_levels.1), finished_collux.2.max(self.min_light_levels.2));
-                                points = points + Vec3D::new(x as f32, y as f32, z as f32); 
-                                lod.add_points(&points);
-                                lod.triangles.add_triangle(
-                                    TrianglePoint::new(
-                                        indices[0] + start_index,
-                                        uvs[indices[0]].0,
-                                        uvs[indices[0]].1,
-                                        finished_collux.0,
-                                        finished_collux.1,
-                                        finished_collux.2
-                                    ),
-                                    TrianglePoint::new(
-                                        indices[1] + start_index,
-                                        uvs[indices[1]].0,
-                                        uvs[indices[1]].1,
-                                        finished_collux.0,
-                                        finished_collux.1,
-                                        finished_collux.2
-                                    ),
-                                    TrianglePoint::new(
-                                        indices[2] + start_index,
-                                        uvs[indices[2]].0,
-                                        uvs[indices[2]].1,
-                                        finished_collux.0,
-                                        finished_collux.1,
-                                        finished_collux.2
-                                    ),
-                                    self.voxel_types[voxel.voxel_id()].easy_texture() as u32, 
-                                    0
-                                );
-                                lod.triangles.add_triangle(
-                                    TrianglePoint::new(
-                                        indices[3] + start_index,
-                                        uvs[indices[3]].0,
-                                        uvs[indices[3]].1,
-                                        finished_collux.0,
-                                        finished_collux.1,
-                                        finished_collux.2
-                                    ),
-                                    TrianglePoint::new(
-                                        indices[4] + start_index,
-                                        uvs[indices[4]].0,
-                                        uvs[indices[4]].1,
-                                        finished_collux.0,
-                                        finished_collux.1,
-                                        finished_collux.2
-                                    ),
-                                    TrianglePoint::new(
-                                        indices[5] + start_index,
-                                        uvs[indices[5]].0,
-                                        uvs[indices[5]].1,
-                                        finished_collux.0,
-                                        finished_collux.1,
-                                        finished_collux.2
-                                    ),
-                                    self.voxel_types[voxel.voxel_id()].easy_texture() as u32, 
-                                    0
-                                );
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        lod
-    }
-
-    fn get_face_data_for_dir_first_pass(
-        &self,
-        (x,y,z):(i32,i32,i32),
-        (i,mask):(usize,u8),
-        chunk:&MapChunk<V>,
-        around:[Option<&MapChunk<V>> ; 6],
-        taken_dirs:&mut Vec<u8>,
-        effective_dirs:&mut [bool ; 6]
-    ) -> Option<(u32, (u8,u8,u8))> {
-        match chunk.get_at_local(Vec3D::new(x, y, z), &self.dims){
-            Some(voxel) => if !&self.voxel_types[voxel.voxel_id()].is_completely_empty() {
-                if chunk.is_empty_in_direction_local(Vec3D::new(x, y, z), &self.dims, around, &self.voxel_types, i) {
-                    let full_texture = self.voxel_types[voxel.voxel_id()].easy_texture() as u32;
-                    let light = chunk.get_light_in_dir_local(Vec3D::new(x, y, z), &self.dims, around, i);
-                    let mut level = (light.level.max(50) as f32) * 0.00392156862;
-                    let mut converted_collux = collux_u8_a_f32((light.r, light.g, light.b));
-                    let mut finished_collux = collux_f32_a_u8((converted_collux.0 * level, converted_collux.1 * level, converted_collux.2 * level));
-                    finished_collux = (finished_collux.0.max(self.min_light_levels.0), finished_collux.1.max(self.min_light_levels.1), finished_collux.2.max(self.min_light_levels.2));
-                    effective_dirs[i] = true;
-                    Some((full_texture, finished_collux))
-                }
-                else {
-                    None
-                }
-            }
-            else {
-                None
-            },
-            None => return None
-        }
+        Self { chunks: HashMap::with_capacity(expected_chunks), dims, voxel_types, min_light_levels, mesh_vec, map_id:MESHING_POOL.new_map_id(), rendering_up_to_date: false, remesh_fasttrack:Vec::with_capacity(16), remesh_queue:HashSet::with_capacity(expected_chunks), mesher_outdated:true, meshes_in_flight:0, saving:false, dirty_chunks:HashSet::with_capacity(16), unloaded_meshes:Vec::new(), free_mesh_slots:Vec::new(), chunk_requests:Vec::new(), view_limit:None, chunks_out_of_view:Vec::new(), meshing_mode:MeshingMode::Greedy, registry_hash:0, registry_mismatch:None, authoritative:false, edit_requests:Vec::new(), edit_rejections:Vec::new(), session_messages:Vec::new(), edited_chunks:HashSet::new(), recording:false, recorded_events:Vec::new(), set_grid:SetGrid::new(5.0, Vec3D::all_ones() * -15, Vec3D::all_ones() * 15), generator }
     }
-
-
-    fn get_face_data_for_dir(
-        &self,
-        (x,y,z):(i32,i32,i32),
-        (i,mask):(usize,u8),
-        (prev_step,step):(usize, i32),
-        face_data:&mut Vec<[Option<(u32, (u8,u8,u8))> ; 6]>
-    ) -> Option<(u32, (u8,u8,u8))> {
-            for dx in (x..x+step).step_by(prev_step) {
-                for dy in (y..y+step).step_by(prev_step) {
-                    for dz in (z..z+step).step_by(prev_step) {
-                        if Vec3D::new(dx, dy, dz).in_origin_prism(self.dims.chunk_length_i, self.dims.chunk_width_i, self.dims.chunk_height_i) {
-                            if let Some(data) = face_data[dx as usize + (dy as usize * self.dims.chunk_length) + (dz as usize * self.dims.chunk_slice_area)][i] {
-                                return Some(data)
-                            }
-                        }
-                        else {
-                            return None
-                        }
-                        
-                        
-                    }
-                }
-            }
-            None
-        
+    pub fn does_chunk_exist(&self, chunk:WorldChunkPos) -> bool {
+        self.chunks.contains_key(&chunk)
     }
-    fn aggregation_start_step_1(&self, chunk:&MapChunk<V>, around:[Option<&MapChunk<V>> ; 6], start:(i32,i32,i32), dir:usize, mask:u8, taken_dirs:&mut Vec<u8>, effective_dirs:&mut [bool ; 6], face_data:&mut Vec<[Option<(u32, (u8,u8,u8))> ; 6]>) -> Option<(u32, (u8,u8,u8))> {
-        let position = Vec3D::new(start.0, start.1, start.2);
-        let value = self.get_face_data_for_dir_first_pass((position.x, position.y, position.z), (dir, mask), chunk, around, taken_dirs, effective_dirs);
-        let index = (position.x as usize + (position.y as usize * self.dims.chunk_length) + (position.z as usize * self.dims.chunk_slice_area));
-        taken_dirs[index] |= mask;
-        face_data[index][dir] = value;
-        value
+    pub fn set_min_light_levels(&mut self, mins:(u8,u8,u8)) {
+        self.min_light_levels = mins;
+        self.mesher_outdated = true;
     }
-
-    fn aggregation_start_step_n(&self, (prev_step, step):(usize,i32), start:(i32,i32,i32), dir:usize, mask:u8, taken_dirs:&mut Vec<u8>, face_data:&mut Vec<[Option<(u32, (u8,u8,u8))> ; 6]>) -> Option<(u32, (u8,u8,u8))> {
-        let position = Vec3D::new(start.0, start.1, start.2);
-        let value = self.get_face_data_for_dir((position.x, position.y, position.z), (dir, mask), (prev_step, step), face_data);
-        let index = (position.x as usize + (position.y as usize * self.dims.chunk_length) + (position.z as usize * self.dims.chunk_slice_area));
-        taken_dirs[index] |= mask;
-        face_data[index][dir] = value;
-        value
+    pub fn with_meshing_mode(mut self, mode:MeshingMode) -> Self {
+        self.meshing_mode = mode;
+        self
     }
-
-    fn is_area_all_same_in_dir_step_1(&self, chunk:&MapChunk<V>, around:[Option<&MapChunk<V>> ; 6], start:(i32,i32,i32), dir:usize, mask:u8, (area, area_start):((i32, i32), (i32,i32)), taken_dirs:&mut Vec<u8>, mut same:Option<(u32, (u8,u8,u8))>, effective_dirs:&mut [bool ; 6], face_data:&mut Vec<[Option<(u32, (u8,u8,u8))> ; 6]>) -> Option<(u32, (u8,u8,u8))> {
-        
-        let (mut first_dir, mut second_dir) = PERPENDICULAR[dir].clone();
-        for first in area_start.0..area.0 {
-            for second in area_start.1..area.1 {
-                let position = Vec3D::new(start.0, start.1, start.2) + first_dir * first + second_dir * second;
-                let value = self.get_face_data_for_dir_first_pass((position.x, position.y, position.z), (dir, mask), chunk, around, taken_dirs, effective_dirs);
-                if same.is_none() {
-                    same = value
-                }
-                else if same != value {
-                    return None;
-                }
-            }
-        }
-        for first in area_start.0..area.0 {
-            for second in area_start.1..area.1 {
-                let position = Vec3D::new(start.0, start.1, start.2) + first_dir * first + second_dir * second;
-                let index = (position.x as usize + (position.y as usize * self.dims.chunk_length) + (position.z as usize * self.dims.chunk_slice_area));
-                taken_dirs[index] |= mask;
-                face_data[index][dir] = same;
-
-            }
+    pub fn with_registry_hash(mut self, hash:u64) -> Self {
+        self.registry_hash = hash;
+        self
+    }
+    pub fn get_registry_hash(&self) -> u64 {
+        self.registry_hash
+    }
+    pub fn get_registry_mismatch(&self) -> Option<u64> {
+        self.registry_mismatch
+    }
+    pub fn with_authority(mut self, authoritative:bool) -> Self {
+        self.authoritative = authoritative;
+        self
+    }
+    // on once the map has storage attached, see mark_dirty
+    pub fn set_saving(&mut self, saving:bool) {
+        self.saving = saving;
+        if !saving {
+            self.dirty_chunks.clear();
         }
-        same
     }
-
-    fn is_area_all_same_in_dir(&self, chunk:&MapChunk<V>, around:[Option<&MapChunk<V>> ; 6], (prev_step, step):(usize,i32), start:(i32,i32,i32), dir:usize, mask:u8, (area, area_start):((i32, i32), (i32,i32)), scaler:&Vec3Df, taken_dirs:&mut Vec<u8>, mut same:Option<(u32, (u8,u8,u8))>, face_data:&mut Vec<[Option<(u32, (u8,u8,u8))> ; 6]>) -> Option<(u32, (u8,u8,u8))> {
-        
-        let (mut first_dir, mut second_dir) = PERPENDICULAR[dir].clone();
-        first_dir *= step;
-        second_dir *= step;
-        for first in area_start.0..area.0 {
-            for second in area_start.1..area.1 {
-                let position = Vec3D::new(start.0, start.1, start.2) + first_dir * first + second_dir * second;
-                let value = self.get_face_data_for_dir((position.x, position.y, position.z), (dir, mask), (prev_step, step), face_data);
-                if same.is_none() {
-                    same = value
-                }
-                else if same != value {
-                    return None;
+    pub fn take_edit_requests(&mut self) -> Vec<VoxelEditRequest<V>> {
+        self.edit_requests.drain(..).collect()
+    }
+    pub fn take_edit_rejections(&mut self) -> Vec<VoxelEditRejected<V>> {
+        self.edit_rejections.drain(..).collect()
+    }
+    // see SessionMessage::is_kept_by for which side keeps what
+    pub fn take_session_messages(&mut self) -> Vec<SessionMessage> {
+        self.session_messages.drain(..).collect()
+    }
+    pub fn take_edited_chunks(&mut self) -> Vec<WorldChunkPos> {
+        self.edited_chunks.drain().collect()
+    }
+    pub fn set_recording(&mut self, recording:bool) {
+        self.recording = recording;
+        self.recorded_events.clear();
+    }
+    pub fn take_recorded_events(&mut self) -> Vec<GameMapEvent<V, G>> {
+        self.recorded_events.drain(..).collect()
+    }
+    // type and orientation of every voxel, light is left out as every side computes it
+    pub fn chunk_checksum(&self, chunk_pos:WorldChunkPos) -> Option<u64> {
+        let chunk = self.chunks.get(&chunk_pos)?;
+        let mut checksum = Checksum::new();
+        for z in 0..self.dims.chunk_height_i {
+            for y in 0..self.dims.chunk_width_i {
+                for x in 0..self.dims.chunk_length_i {
+                    let voxel = chunk.get_voxel_data(Vec3D::new(x, y, z), &self.dims);
+                    checksum.add_u64(voxel.voxel_id() as u64);
+                    checksum.add_bytes(&[voxel.orientation()]);
                 }
             }
         }
-        for first in area_start.0..area.0 {
-            for second in area_start.1..area.1 {
-                let position = Vec3D::new(start.0, start.1, start.2) + first_dir * first + second_dir * second;
-                let index = (position.x as usize + (position.y as usize * self.dims.chunk_length) + (position.z as usize * self.dims.chunk_slice_area));
-                taken_dirs[index] |= mask;
-                face_data[index][dir] = same;
-            }
+        Some(checksum.finish())
+    }
+    // sets the voxel and updates everything that depends on it (meshes, saving, light)
+    pub fn replace_voxel_at(&mut self, pos:WorldVoxelPos, new_voxel:V) {
+        let previous = self.get_voxel_at_mut(pos).map(|vox| {std::mem::replace(vox, new_voxel)});
+        self.modified_this_pos_signal_remesh(pos);
+        self.mark_dirty(self.get_chunk_pos_i(pos));
+        if self.authoritative {
+            self.edited_chunks.insert(self.get_chunk_pos_i(pos));
         }
-        same
-    }
-    
-    fn get_lod_with_1_step(&self, chunk:&MapChunk<V>, around:[Option<&MapChunk<V>> ; 6]) -> (MeshLOD, [bool ; 6], Vec<[Option<(u32, (u8,u8,u8))> ; 6]>) {
-        if let Some(voxel) = chunk.only_1_type.clone() && self.voxel_types[voxel.voxel_id()].is_completely_empty() {
-            (MeshLOD::new(Vec::new(), Vec::new(), Vec::new(), MeshTriangles::with_capacity(0)), [false ; 6], vec![])
-        }
-        else {
-            let mut x = Vec::with_capacity(600);
-            let mut y = Vec::with_capacity(600);
-            let mut z = Vec::with_capacity(600);
-            let mut triangles = MeshTriangles::with_capacity(600);
-            let mut lod = MeshLOD::new(x, y, z, triangles);
-            let mut taken_dirs = vec![0 ; self.dims.chunk_height * self.dims.chunk_length * self.dims.chunk_width];
-            let mut kept_dirs = Vec::with_capacity(6);
-            let mut effective_dirs = [false ; 6];
-            let mut face_data_vec = vec![[None ; 6] ; self.dims.chunk_height * self.dims.chunk_length * self.dims.chunk_width];
-            let scaler = Vec3Df::all_ones();
-            if let Some(voxel) = chunk.only_1_type.clone() && !self.voxel_types[voxel.voxel_id()].is_completely_empty() {
-                for (i, ch) in around.iter().enumerate() {
-                    if let Some(chunk2) = ch {
-                        if let Some(voxel) = chunk2.only_1_type.clone() && self.voxel_types[voxel.voxel_id()].is_completely_empty() {
-                            kept_dirs.push((i, DIR_MASK[i]));
-                            effective_dirs[i] = true;
-                        }
-                        else if let None = chunk2.only_1_type.clone() {
-                            kept_dirs.push((i, DIR_MASK[i]));
-                            effective_dirs[i] = true;
-                        }
-                    }
-                }
-            }
-            else {
-                for (i, ch) in around.iter().enumerate() {
-                    kept_dirs.push((i, DIR_MASK[i]));
-                }
-            }
-            
-            println!("Started {} {} {}", chunk.chunk_coord.x, chunk.chunk_coord.y, chunk.chunk_coord.z);
-            for x in 0..self.dims.chunk_length_i {
-                for y in 0..self.dims.chunk_width_i {
-                    for z in 0..self.dims.chunk_height_i {
-                        for (i, mask) in kept_dirs.iter() {
-                            if taken_dirs[(x as usize + (y as usize * self.dims.chunk_length) + (z as usize * self.dims.chunk_slice_area))] & *mask == *mask {
-                                continue
-                            }
-                            else {
-                                let mut first_dir_greed = 1;
-                                let mut second_dir_greed = 1;
-                                let face_data = self.aggregation_start_step_1(chunk, around, (x,y,z), *i, *mask, &mut taken_dirs, &mut effective_dirs, &mut face_data_vec);
-
-                                while (first_dir_greed + 1) < self.dims.chunk_height_i && (second_dir_greed + 1) < self.dims.chunk_height_i && face_data.is_some() {
-                                    
-                                    //println!("{} {} {}", first_dir_greed, second_dir_greed, step);
-                                    let mut first_test_face = self.is_area_all_same_in_dir_step_1(chunk, around, (x,y,z), *i, *mask, ((first_dir_greed + 1, second_dir_greed), (first_dir_greed, 0)), &mut taken_dirs, face_data, &mut effective_dirs, &mut face_data_vec);
-                                    if first_test_face.is_some() {
-                                        first_dir_greed += 1;
-                                    }
-
-                                    let mut second_test_face = self.is_area_all_same_in_dir_step_1(chunk, around, (x,y,z), *i, *mask, ((first_dir_greed, second_dir_greed + 1), (0, second_dir_greed)), &mut taken_dirs, face_data, &mut effective_dirs, &mut face_data_vec);
-                                    if second_test_face.is_some() {
-                                        second_dir_greed += 1;
-                                    }
-                                    if first_test_face.is_none() && second_test_face.is_none() {
-                                        break;
-                                    }
-                                }
-                                match face_data {
-                                    Some((full_texture, finished_collux)) => {
-                                        let u_s = first_dir_greed as f32;
-                                        let v_s = second_dir_greed as f32;
-                                        let (mut f_dir, mut s_dir) = PERPENDICULAR[*i].clone();
-                                        let scaler = multiply_corresponding_nonzero(multiply_corresponding_nonzero(scaler, Vec3D::new(f_dir.x as f32, f_dir.y as f32, f_dir.z as f32) * u_s), Vec3D::new(s_dir.x as f32, s_dir.y as f32, s_dir.z as f32) * v_s);
-
-                                        let start_index = lod.x.len();
-                                        let indices = TRIS_INDICES_UVS.1;
-                                        let uvs = TRIS_INDICES_UVS.2;
-                                        let mut points = TRIS_INDICES_UVS.0[*i];
-                                        points = points * scaler + (Vec3D::new(x as f32, y as f32, z as f32)) + ((Vec3Df::all_ones() * 0.5).component_product(&scaler) - Vec3Df::all_ones() * 0.5);
-                                        lod.add_points(&points);
-                                        lod.triangles.add_triangle(
-                                            TrianglePoint::new(
-                                                indices[0] + start_index,
-                                                uvs[indices[0]].0 * u_s,
-                                                uvs[indices[0]].1 * v_s,
-                                                finished_collux.0,
-                                                finished_collux.1,
-                                                finished_collux.2
-                                            ),
-                                            TrianglePoint::new(
-                                                indices[1] + start_index,
-                                                uvs[indices[1]].0 * u_s,
-                                                uvs[indices[1]].1 * v_s,
-                                                finished_collux.0,
-                                                finished_collux.1,
-                                                finished_collux.2
-                                            ),
-                                            TrianglePoint::new(
-                                                indices[2] + start_index,
-                                                uvs[indices[2]].0 * u_s,
-                                                uvs[indices[2]].1 * v_s,
-                                                finished_collux.0,
-                                                finished_collux.1,
-                                                finished_collux.2
-                                            ),
-                                            full_texture, 
-                                            0
-                                        );
-                                        lod.triangles.add_triangle(
-                                            TrianglePoint::new(
-                                                indices[3] + start_index,
-                                                uvs[indices[3]].0 * u_s,
-                                                uvs[indices[3]].1 * v_s,
-                                                finished_collux.0,
-                                                finished_collux.1,
-                                                finished_collux.2
-                                            ),
-                                            TrianglePoint::new(
-                                                indices[4] + start_index,
-                                                uvs[indices[4]].0 * u_s,
-                                                uvs[indices[4]].1 * v_s,
-                                                finished_collux.0,
-                                                finished_collux.1,
-                                                finished_collux.2
-                                            ),
-                                            TrianglePoint::new(
-                                                indices[5] + start_index,
-                                                uvs[indices[5]].0 * u_s,
-                                                uvs[indices[5]].1 * v_s,
-                                                finished_collux.0,
-                                                finished_collux.1,
-                                                finished_collux.2
-                                            ),
-                                            full_texture, 
-                                            0
-                                        );
-                                    },
-                                    None => ()
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-            (lod, effective_dirs, face_data_vec)
+        match previous {
+            Some(previous) => self.update_light_at(pos, &previous),
+            None => ()
         }
-        
     }
-    fn get_lod_with_step(&self, chunk:&MapChunk<V>, around:[Option<&MapChunk<V>> ; 6], (prev_step, step):(usize, i32), effective_dirs:[bool ; 6], vec_face_data:&mut Vec<[Option<(u32, (u8,u8,u8))> ; 6]>) -> MeshLOD {
-        if let Some(voxel) = chunk.only_1_type.clone() && self.voxel_types[voxel.voxel_id()].is_completely_empty() {
-            MeshLOD::new(Vec::new(), Vec::new(), Vec::new(), MeshTriangles::with_capacity(0))
+    pub fn get_meshing_mode(&self) -> MeshingMode {
+        self.meshing_mode
+    }
+    pub fn set_meshing_mode(&mut self, mode:MeshingMode) {
+        if self.meshing_mode != mode {
+            self.meshing_mode = mode;
+            self.mesher_outdated = true;
+            self.force_rerender();
         }
-        else {
-            let mut x = Vec::with_capacity(600);
-            let mut y = Vec::with_capacity(600);
-            let mut z = Vec::with_capacity(600);
-            let mut triangles = MeshTriangles::with_capacity(600);
-            let mut lod = MeshLOD::new(x, y, z, triangles);
-            let scaler = Vec3D::new(step as f32, step as f32, step as f32);
-            let float_step = step as f32;
-            let mut taken_dirs = vec![0 ; self.dims.chunk_height * self.dims.chunk_length * self.dims.chunk_width];
-            let mut kept_dirs = Vec::with_capacity(6);
-            if let Some(voxel) = chunk.only_1_type.clone() && !self.voxel_types[voxel.voxel_id()].is_completely_empty() {
-                for (i, ch) in around.iter().enumerate() {
-                    if let Some(chunk2) = ch {
-                        if let Some(voxel) = chunk2.only_1_type.clone() && self.voxel_types[voxel.voxel_id()].is_completely_empty() {
-                            kept_dirs.push((i, DIR_MASK[i]));
-                        }
-                        else if let None = chunk2.only_1_type.clone() {
-                            kept_dirs.push((i, DIR_MASK[i]));
-                        }
-                    }
-                }
-            }
-            else {
-                for (i, ch) in around.iter().enumerate() {
-                    if effective_dirs[i] {
-                        kept_dirs.push((i, DIR_MASK[i]));
-                    }
-                }
-            }
-            
-            println!("Started {} {} {}", chunk.chunk_coord.x, chunk.chunk_coord.y, chunk.chunk_coord.z);
-            for x in (0..self.dims.chunk_length_i).step_by(step as usize) {
-                for y in (0..self.dims.chunk_width_i).step_by(step as usize) {
-                    for z in (0..self.dims.chunk_height_i).step_by(step as usize) {
-                        for (i, mask) in kept_dirs.iter() {
-                            if taken_dirs[(x as usize + (y as usize * self.dims.chunk_length) + (z as usize * self.dims.chunk_slice_area))] & *mask == *mask {
-                                continue
-                            }
-                            else {
-                                let mut first_dir_greed = 1;
-                                let mut second_dir_greed = 1;
-                                let face_data = self.aggregation_start_step_n((prev_step, step), (x,y,z), *i, *mask, &mut taken_dirs, vec_face_data);
-
-                                while (first_dir_greed + 1) * step < self.dims.chunk_height_i && (second_dir_greed + 1) * step < self.dims.chunk_height_i && face_data.is_some() {
-                                    
-                                    //println!("{} {} {}", first_dir_greed, second_dir_greed, step);
-                                    let mut first_test_face = self.is_area_all_same_in_dir(chunk, around, (prev_step, step), (x,y,z), *i, *mask, ((first_dir_greed + 1, second_dir_greed), (first_dir_greed, 0)), &scaler, &mut taken_dirs, face_data, vec_face_data);
-                                    if first_test_face.is_some() {
-                                        first_dir_greed += 1;
-                                    }
-
-                                    let mut second_test_face = self.is_area_all_same_in_dir(chunk, around, (prev_step, step), (x,y,z), *i, *mask, ((first_dir_greed, second_dir_greed + 1), (0, second_dir_greed)), &scaler, &mut taken_dirs, face_data, vec_face_data);
-                                    if second_test_face.is_some() {
-                                        second_dir_greed += 1;
-                                    }
-                                    if first_test_face.is_none() && second_test_face.is_none() {
-                                        break;
-                                    }
-                                }
-                                match face_data {
-                                    Some((full_texture, finished_collux)) => {
-                                        let u_s = first_dir_greed as f32;
-                                        let v_s = second_dir_greed as f32;
-                                        let (mut f_dir, mut s_dir) = PERPENDICULAR[*i].clone();
-                                        let scaler = multiply_corresponding_nonzero(multiply_corresponding_nonzero(scaler, Vec3D::new(f_dir.x as f32, f_dir.y as f32, f_dir.z as f32) * u_s), Vec3D::new(s_dir.x as f32, s_dir.y as f32, s_dir.z as f32) * v_s);
-
-                                        let start_index = lod.x.len();
-                                        let indices = TRIS_INDICES_UVS.1;
-                                        let uvs = TRIS_INDICES_UVS.2;
-                                        let mut points = TRIS_INDICES_UVS.0[*i];
-                                        points = points * scaler + (Vec3D::new(x as f32, y as f32, z as f32)) + ((Vec3Df::all_ones() * 0.5).component_product(&scaler) - Vec3Df::all_ones() * 0.5);
-                                        lod.add_points(&points);
-                                        lod.triangles.add_triangle(
-                                            TrianglePoint::new(
-                                                indices[0] + start_index,
-                                                uvs[indices[0]].0 * float_step * u_s,
-                                                uvs[indices[0]].1 * float_step * v_s,
-                                                finished_collux.0,
-                                                finished_collux.1,
-                                                finished_collux.2
-                                            ),
-                                            TrianglePoint::new(
-                                                indices[1] + start_index,
-                                                uvs[indices[1]].0 * float_step * u_s,
-                                                uvs[indices[1]].1 * float_step * v_s,
-                                                finished_collux.0,
-                                                finished_collux.1,
-                                                finished_collux.2
-                                            ),
-                                            TrianglePoint::new(
-                                                indices[2] + start_index,
-                                                uvs[indices[2]].0 * float_step * u_s,
-                                                uvs[indices[2]].1 * float_step * v_s,
-                                                finished_collux.0,
-                                                finished_collux.1,
-                                                finished_collux.2
-                                            ),
-                                            full_texture, 
-                                            0
-                                        );
-                                        lod.triangles.add_triangle(
-                                            TrianglePoint::new(
-                                                indices[3] + start_index,
-                                                uvs[indices[3]].0 * float_step * u_s,
-                                                uvs[indices[3]].1 * float_step * v_s,
-                                                finished_collux.0,
-                                                finished_collux.1,
-                                                finished_collux.2
-                                            ),
-                                            TrianglePoint::new(
-                                                indices[4] + start_index,
-                                                uvs[indices[4]].0 * float_step * u_s,
-                                                uvs[indices[4]].1 * float_step * v_s,
-                                                finished_collux.0,
-                                                finished_collux.1,
-                                                finished_collux.2
-                                            ),
-                                            TrianglePoint::new(
-                                                indices[5] + start_index,
-                                                uvs[indices[5]].0 * float_step * u_s,
-                                                uvs[indices[5]].1 * float_step * v_s,
-                                                finished_collux.0,
-                                                finished_collux.1,
-                                                finished_collux.2
-                                            ),
-                                            full_texture, 
-                                            0
-                                        );
-                                    },
-                                    None => ()
-                                }
-                            }
-                        }
-                    }
-                }
+    }
+    // (naive triangles, greedy triangles) for the chunk as it is right now, None if it isn't loaded
+    pub fn count_triangles_per_mode(&self, chunk_pos:WorldChunkPos) -> Option<(usize, usize)> {
+        let mesher = match MESHING_POOL.get_mesher(self.map_id) {
+            Some(mesher) if !self.mesher_outdated => mesher,
+            _ => Arc::new(self.new_mesher())
+        };
+        self.get_chunk_at(chunk_pos).map(|chunk| {mesher.count_triangles_per_mode(chunk, self.get_chunks_around(chunk_pos))})
+    }
+    fn new_mesher(&self) -> ChunkMesher<V> {
+        ChunkMesher::new(self.dims.clone(), self.voxel_types.clone(), self.min_light_levels, self.meshing_mode)
+    }
+    // building a mesher copies the voxel types and their models, so it's only done when they would mesh differently
+    fn get_mesher(&mut self) -> Arc<ChunkMesher<V>> {
+        match MESHING_POOL.get_mesher(self.map_id) {
+            Some(mesher) if !self.mesher_outdated => mesher,
+            _ => {
+                let mesher = Arc::new(self.new_mesher());
+                MESHING_POOL.set_mesher(self.map_id, mesher.clone());
+                self.mesher_outdated = false;
+                mesher
             }
-            lod
         }
-        
     }
-    pub fn does_chunk_exist(&self, chunk:WorldChunkPos) -> bool {
-        self.chunks.contains_key(&chunk)
+    pub fn force_rerender(&mut self) {
+        self.rendering_up_to_date = false;
+        for (pos, chunk) in self.chunks.iter_mut() {
+            chunk.mark_for_remesh();
+            self.remesh_queue.insert(*pos);
+        }
     }
-    pub fn set_min_light_levels(&mut self, mins:(u8,u8,u8)) {
-        self.min_light_levels = mins;
+    // the chunk gets a new mesh on one of the next renders
+    fn queue_remesh(&mut self, chunk_pos:WorldChunkPos) -> bool {
+        match self.chunks.get_mut(&chunk_pos) {
+            Some(chunk) => {
+                chunk.mark_for_remesh();
+                self.remesh_queue.insert(chunk_pos);
+                self.rendering_up_to_date = false;
+                true
+            },
+            None => false
+        }
     }
-    pub fn force_rerender(&mut self) {
+    // new chunks have no mesh yet
+    fn insert_chunk(&mut self, chunk_pos:WorldChunkPos, chunk:MapChunk<V>) {
+        self.chunks.insert(chunk_pos, chunk);
+        self.remesh_queue.insert(chunk_pos);
         self.rendering_up_to_date = false;
-        self.forced_rerender = true;
     }
     pub fn modified_this_pos_signal_remesh(&mut self, pos:WorldVoxelPos) {
-        let mut must_re_render = false;
-        let dims = self.dims.clone();
-        let mut add_to_fasttrack = Vec::with_capacity(7);
-        self.get_chunk_at_mut(self.get_chunk_pos_i(pos)).and_then(|chunk| {chunk.mark_for_remesh(); add_to_fasttrack.push(get_chunk_pos_i(&dims, pos)); must_re_render = true; Some(1_i32)});
-        for dir in EXPLORATION {
-            self.get_chunk_at_mut(self.get_chunk_pos_i(pos + dir)).and_then(|chunk| {chunk.mark_for_remesh(); add_to_fasttrack.push(get_chunk_pos_i(&dims, pos + dir)); must_re_render = true; Some(1_i32)});
-        }
-        for add in add_to_fasttrack {
-            self.remesh_fasttrack.push(add);
+        for dir in std::iter::once(Vec3D::zero()).chain(EXPLORATION) {
+            let chunk_pos = self.get_chunk_pos_i(pos + dir);
+            if self.queue_remesh(chunk_pos) {
+                self.remesh_fasttrack.push(chunk_pos);
+            }
         }
-        self.rendering_up_to_date = !must_re_render;
     }
     pub fn generate_chunk<F:FnMut(Vec3D<i32>) -> V>(&self, chunk_pos:WorldChunkPos, func:&mut F) -> MapChunk<V> {
         let mut chunk_data = Vec::with_capacity(self.dims.chunk_slice_area * self.dims.chunk_height);
@@ -1168,29 +1004,130 @@ impl<V:Voxel, G:Generator<V>> GameMap<V, G> {
         chunk
     }
     pub fn get_all_chunk_pos(&self) -> Vec<Vec3D<i32>> {
-        self.chunks.clone().keys().map(|vector| {vector.clone()}).collect::<Vec<Vec3D<i32>>>()
+        self.chunks.keys().cloned().collect()
     }
     pub fn generate_chunks<F:FnMut(Vec3D<i32>) -> V>(&mut self, start:WorldChunkPos, end:WorldChunkPos, func:&mut F) {
         self.set_grid = SetGrid::new(4.0, start * self.dims.chunk_length_i, end * self.dims.chunk_length_i);
         for xc in start.x..end.x {
             for yc in start.y..end.y {
                 for zc in start.z..end.z {
-                    self.chunks.insert(Vec3D::new(xc, yc, zc), self.generate_chunk(Vec3D::new(xc, yc, zc), func));
+                    let chunk = self.generate_chunk(Vec3D::new(xc, yc, zc), func);
+                    self.insert_chunk(Vec3D::new(xc, yc, zc), chunk);
+                }
+            }
+        }
+        // from the top down, so that the sky reaches lower chunks through the ones above
+        for zc in (start.z..end.z).rev() {
+            for xc in start.x..end.x {
+                for yc in start.y..end.y {
+                    self.light_new_chunk(Vec3D::new(xc, yc, zc));
+                }
+            }
+        }
+        // lighting a chunk can change the ones around it, so they are only compacted once everything is lit
+        for xc in start.x..end.x {
+            for yc in start.y..end.y {
+                for zc in start.z..end.z {
+                    self.compact_chunk(Vec3D::new(xc, yc, zc));
                 }
             }
         }
     }
+    pub fn get_chunk_dims(&self) -> &ChunkDims {
+        &self.dims
+    }
     pub fn generate_chunks_with_generator_and_get_them(&mut self, chunks:Vec<WorldChunkPos>) -> Vec<(WorldChunkPos, MapChunk<V>)> {
         let mut out = Vec::with_capacity(chunks.len());
-        for c_pos in chunks {
-            let chunk = self.generate_chunk(c_pos, &mut |pos| {
+        for c_pos in &chunks {
+            let chunk = self.generate_chunk(*c_pos, &mut |pos| {
                 self.generator.generate(pos)
             });
-            self.chunks.insert(c_pos, chunk.clone());
-            out.push((c_pos, chunk));
+            self.insert_chunk(*c_pos, chunk);
+        }
+        let mut by_height = chunks.clone();
+        by_height.sort_by_key(|c_pos| {-c_pos.z});
+        for c_pos in by_height {
+            self.light_new_chunk(c_pos);
+        }
+        for c_pos in chunks {
+            self.compact_chunk(c_pos);
+            out.push((c_pos, self.chunks[&c_pos].clone()));
         }
         out
     }
+    // for chunks that come from disk, they don't need to be saved again
+    pub fn insert_loaded_chunk(&mut self, chunk_pos:WorldChunkPos, chunk:MapChunk<V>) {
+        self.insert_chunk(chunk_pos, chunk);
+        self.light_new_chunk(chunk_pos);
+        self.compact_chunk(chunk_pos);
+        self.modified_this_pos_signal_remesh(self.get_chunk_dims_vector().component_product(&chunk_pos));
+    }
+    // chunks stay compacted until something is written into them, see MapChunk::compact
+    pub fn compact_chunk(&mut self, chunk_pos:WorldChunkPos) {
+        match self.chunks.get_mut(&chunk_pos) {
+            Some(chunk) => chunk.compact(),
+            None => ()
+        }
+    }
+    // only edits make a chunk dirty, light is computed again when a chunk is loaded
+    // and chunks that were never saved are found by save_dirty without being marked
+    pub fn mark_dirty(&mut self, chunk_pos:WorldChunkPos) {
+        if self.saving && self.chunks.contains_key(&chunk_pos) {
+            self.dirty_chunks.insert(chunk_pos);
+        }
+    }
+    pub fn unload_chunks(&mut self, positions:&[WorldChunkPos]) -> Vec<(WorldChunkPos, MapChunk<V>)> {
+        let mut removed = Vec::with_capacity(positions.len());
+        for pos in positions {
+            self.remesh_queue.remove(pos);
+            match self.chunks.remove(pos) {
+                Some(chunk) => {
+                    if let Some(mesh_id) = chunk.mesh_id && let Some(instance) = chunk.mesh_instance {
+                        self.unloaded_meshes.push((mesh_id, instance));
+                        self.rendering_up_to_date = false;
+                    }
+                    removed.push((*pos, chunk));
+                },
+                None => ()
+            }
+        }
+        removed
+    }
+    // chunks whose center is farther than view_distance (in chunks, horizontally) from every anchor (world positions)
+    pub fn get_chunks_farther_than(&self, anchors:&[Vec3Df], view_distance:f32) -> Vec<WorldChunkPos> {
+        let dims = self.get_chunk_dims_vector_f();
+        let anchors_c:Vec<Vec3Df> = anchors.iter().map(|anchor| {Vec3Df::new(anchor.x/dims.x, anchor.y/dims.y, 0.0)}).collect();
+        let mut far = Vec::with_capacity(16);
+        for pos in self.chunks.keys() {
+            let center = Vec3Df::new(pos.x as f32 + 0.5, pos.y as f32 + 0.5, 0.0);
+            if anchors_c.iter().all(|anchor| {center.dist(anchor) > view_distance}) {
+                far.push(*pos);
+            }
+        }
+        far
+    }
+    pub fn take_chunk_requests(&mut self) -> Vec<WorldChunkPos> {
+        self.chunk_requests.drain(..).collect()
+    }
+    // new chunks farther than view_distance (in chunks, horizontally) from the anchor are skipped, see take_chunks_out_of_view
+    pub fn set_view_limit(&mut self, limit:Option<(Vec3Df, f32)>) {
+        self.view_limit = limit;
+    }
+    fn is_out_of_view(&self, chunk_pos:WorldChunkPos) -> bool {
+        match self.view_limit {
+            Some((anchor, view_distance)) => {
+                let dims = self.get_chunk_dims_vector_f();
+                Vec3Df::new(chunk_pos.x as f32 + 0.5, chunk_pos.y as f32 + 0.5, 0.0).dist(&Vec3Df::new(anchor.x/dims.x, anchor.y/dims.y, 0.0)) > view_distance
+            },
+            None => false
+        }
+    }
+    pub fn take_chunks_out_of_view(&mut self) -> Vec<WorldChunkPos> {
+        self.chunks_out_of_view.drain(..).collect()
+    }
+    pub fn take_dirty_chunks(&mut self) -> HashSet<WorldChunkPos> {
+        self.dirty_chunks.drain().collect()
+    }
     pub fn make_meshes_invisible<'a>(&mut self, write: &mut VectorinatorWrite<'a>) {
         write.meshes.change_visibility_of_all_instances_of_vec(self.mesh_vec, false);
     }
@@ -1281,32 +1218,83 @@ impl<V:Voxel, G:Generator<V>> GameMap<V, G> {
     pub fn full_collision(&self, pos:Vec3Df, speed_nudge:Vec3Df) -> Option<Collision<V>> {
         self.generator.full_collision(pos, speed_nudge).or_else(|| {
             match self.get_voxel_at(get_voxel_pos(pos)) {
-                Some(voxel) => Some(Collision { surface_normal: Vec3Df::new(0.0, 0.0, 1.0), minimum_nudge: get_minimum_nudge(pos, speed_nudge, self), voxel: voxel.clone(), position:pos }),
+                Some(voxel) => match self.get_custom_model_push(pos) {
+                    Some((push, normal)) => Some(Collision { surface_normal: normal, minimum_nudge: push, voxel: voxel.clone(), position:pos }),
+                    None => Some(Collision { surface_normal: self.get_surface_normal(voxel), minimum_nudge: get_minimum_nudge(pos, speed_nudge, self), voxel: voxel.clone(), position:pos })
+                },
                 None => None
             }
         })
     }
+    // partially empty voxels are built up from their underside so they push away from it, whichever way they are oriented
+    pub fn get_surface_normal(&self, voxel:&V) -> Vec3Df {
+        let voxel_type = &self.voxel_types[voxel.voxel_id()];
+        if voxel_type.sides_empty() & EMPTY_VOXEL != 0 && !voxel_type.is_completely_empty() {
+            let up = orient_vector(EXPLORATION[0], voxel.orientation());
+            Vec3Df::new(up.x as f32, up.y as f32, up.z as f32)
+        }
+        else {
+            Vec3Df::new(0.0, 0.0, 1.0)
+        }
+    }
     pub fn simple_collision(&self, pos:Vec3Df) -> bool {
-        self.generator.simple_collision(pos) || self.is_voxel_solid(get_voxel_pos(pos))
+        self.generator.simple_collision(pos) || self.is_point_solid(pos)
+    }
+    // full voxels are solid everywhere in them, custom models only under the top of their shape
+    pub fn is_point_solid(&self, pos:Vec3Df) -> bool {
+        match self.get_voxel_at(get_voxel_pos(pos)) {
+            Some(voxel) => {
+                let voxel_type = &self.voxel_types[voxel.voxel_id()];
+                match voxel_type.custom_model() {
+                    Some(model) => model.is_solid_at(get_model_pos(pos, voxel.orientation())),
+                    None => !voxel_type.is_completely_empty()
+                }
+            },
+            None => false
+        }
+    }
+    // (how far to push pos out of the custom model it is in, along the top of the model, normal of the top there)
+    pub fn get_custom_model_push(&self, pos:Vec3Df) -> Option<(Vec3Df, Vec3Df)> {
+        let voxel = self.get_voxel_at(get_voxel_pos(pos))?;
+        let model = self.voxel_types[voxel.voxel_id()].custom_model()?;
+        let model_pos = get_model_pos(pos, voxel.orientation());
+        let depth = model.height_at(model_pos.x, model_pos.y) - model_pos.z;
+        if depth >= 0.0 {
+            Some((model_to_world(Vec3Df::new(0.0, 0.0, depth), voxel.orientation()), model_to_world(model.normal_at(model_pos.x, model_pos.y), voxel.orientation())))
+        }
+        else {
+            None
+        }
     }
 }
 
 
+// position of pos inside the voxel it is in, in the space of the model of the voxel (0 to 1 on every axis)
+pub fn get_model_pos(pos:Vec3Df, orientation:u8) -> Vec3Df {
+    let centered = pos - get_float_pos(get_voxel_pos(pos)) - Vec3Df::all_ones() * 0.5;
+    world_to_model(centered, orientation) + Vec3Df::all_ones() * 0.5
+}
+
 pub fn get_minimum_nudge<V:Voxel, G:Generator<V>>(end:Vec3Df, nudge:Vec3Df, world:&GameMap<V, G>) -> Vec3Df {
     let xy_zeroed_out = end + Vec3Df::new(0.0, 0.0, nudge.z);
-    if world.is_voxel_solid(get_voxel_pos(xy_zeroed_out)) {
+    // slopes, slabs and stairs push up along their top instead of stepping a whole voxel
+    match world.get_custom_model_push(xy_zeroed_out) {
+        Some((push, _)) => return Vec3Df::new(0.0, 0.0, nudge.z) + push,
+        None => ()
+    }
+    if world.is_point_solid(xy_zeroed_out) {
         let stepped_z_nudge = end + Vec3Df::new(0.0, 0.0, nudge.z + 1.0);
-        if world.is_voxel_solid(get_voxel_pos(stepped_z_nudge)) {
+        if world.is_point_solid(stepped_z_nudge) {
             let x_zeroed = end + Vec3Df::new(0.0, nudge.y, nudge.z);
             let y_zeroed = end + Vec3Df::new(nudge.x, 0.0, nudge.z);
             let z_zeroed = end + Vec3Df::new(nudge.x, nudge.y, 0.0);
-            if !world.is_voxel_solid(get_voxel_pos(x_zeroed)) {
+            if !world.is_point_solid(x_zeroed) {
                 Vec3Df::new(0.0, nudge.y, nudge.z)
             }
-            else if !world.is_voxel_solid(get_voxel_pos(y_zeroed)) {
+            else if !world.is_point_solid(y_zeroed) {
                 Vec3Df::new(nudge.x, 0.0, nudge.z)
             }
-            else if !world.is_voxel_solid(get_voxel_pos(z_zeroed)) {
+            else if !world.is_point_solid(z_zeroed) {
                 Vec3Df::new(nudge.x, nudge.y, 0.0)
             }
             else {
@@ -0,0 +1,43 @@
+use hord3::horde::game_engine::multiplayer::MustSync;
+use to_from_bytes_derive::{FromBytes, ToBytes};
+
+// Players joining and leaving the server, carried by SessionMessage::Player
+// events don't say which client sent them, so every client picks a random secret session token
+// and puts it in everything it asks for itself, the server checks it against the player's public id (player_id of the token)
+// the server only ever sends ids, so no client learns the token of another one
+#[derive(Clone, ToBytes, FromBytes, PartialEq, Debug)]
+pub enum PlayerEvent {
+    // client to server, sent again every second until the client gets its entities
+    Join {id:u64, name:String},
+    // client to server every second with the token, players that stay silent for too long have left
+    Heartbeat(u64),
+    // client to server with the token when quitting
+    Leave(u64),
+    // server to clients, the driver entity and vehicle of a player
+    Assigned {id:u64, driver:usize, vehicle:usize},
+    // server to clients, no room left for that player
+    Refused(u64),
+    // server to clients, the player left and its entities went back to the server
+    Left {id:u64, driver:usize, vehicle:usize},
+}
+
+impl PlayerEvent {
+    pub fn should_sync(&self) -> MustSync {
+        match self {
+            Self::Join { .. } | Self::Heartbeat(_) | Self::Leave(_) => MustSync::Client,
+            Self::Assigned { .. } | Self::Refused(_) | Self::Left { .. } => MustSync::Server,
+        }
+    }
+}
+
+// public id of the player holding that secret token, the same on every platform and build
+// the mixing is a bijection, xoring its input back in makes finding the token from the id a brute force search
+pub fn player_id(token:u64) -> u64 {
+    let mut mixed = token;
+    for _ in 0..4 {
+        mixed = (mixed ^ (mixed >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        mixed = (mixed ^ (mixed >> 27)).wrapping_mul(0x94d049bb133111eb);
+        mixed ^= mixed >> 31;
+    }
+    mixed ^ token
+}
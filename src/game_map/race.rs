@@ -0,0 +1,54 @@
+use hord3::horde::game_engine::multiplayer::MustSync;
+use to_from_bytes_derive::{FromBytes, ToBytes};
+
+// Race progress computed by the server, carried by SessionMessage::Race, see server::race
+#[derive(Clone, ToBytes, FromBytes, PartialEq, Debug)]
+pub struct RacePosition {
+    pub name:String,
+    pub driver:usize,
+    pub vehicle:usize,
+    // laps done, checkpoints passed in the current lap
+    pub lap:usize,
+    pub checkpoint:usize,
+    // in voxels along the road since the start line, all laps included
+    pub distance:f32,
+    pub finish_time_ms:Option<u64>,
+}
+
+#[derive(Clone, ToBytes, FromBytes, PartialEq, Debug)]
+pub struct RaceStandings {
+    pub tick:usize,
+    // 0 when the race has no finish
+    pub laps:usize,
+    pub checkpoints_per_lap:usize,
+    // first place first
+    pub positions:Vec<RacePosition>,
+}
+
+#[derive(Clone, ToBytes, FromBytes, PartialEq, Debug)]
+pub struct LeaderboardEntry {
+    pub place:usize,
+    pub name:String,
+    pub driver:usize,
+    pub time_ms:u64,
+}
+
+#[derive(Clone, ToBytes, FromBytes, PartialEq, Debug)]
+pub enum RaceEvent {
+    // a few times per second while racing
+    Standings(RaceStandings),
+    // every checkpoint a racer passes, lap and checkpoint counted from 1
+    Checkpoint {driver:usize, lap:usize, checkpoint:usize, time_ms:u64},
+    // the finishers so far every time one more finishes, complete once every racer still connected finished
+    Leaderboard {entries:Vec<LeaderboardEntry>, complete:bool},
+}
+
+impl RaceEvent {
+    pub fn should_sync(&self) -> MustSync {
+        MustSync::Server
+    }
+}
+
+pub fn format_race_time(time_ms:u64) -> String {
+    format!("{}:{:02}.{:03}", time_ms / 60000, (time_ms / 1000) % 60, time_ms % 1000)
+}
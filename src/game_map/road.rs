@@ -1,12 +1,66 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, f32::consts::FRAC_PI_2, ops::Range};
 
-use hord3::horde::geometry::{plane::{EquationPlane, VectorPlane}, vec3d::{Vec3D, Vec3Df}};
+use hord3::horde::geometry::{plane::{EquationPlane, VectorPlane}, rotation::Orientation, vec3d::{Vec3D, Vec3Df}};
 use to_from_bytes_derive::{FromBytes, ToBytes};
 
-use crate::{game_engine::CoolVoxel, game_map::{Collision, GameMap, Generator, VoxelLight, WorldChunkPos, WorldVoxelPos, get_float_pos, get_voxel_pos}};
+use crate::{game_engine::CoolVoxel, game_map::{Collision, GameMap, Generator, VoxelLight, WorldChunkPos, WorldVoxelPos, get_float_pos, get_voxel_pos, orientation_from, quarter_turns_towards}, voxel_registry::VoxelRegistry};
 
 const BIOME_PROBABILITY_CHANGE:f32 = 0.0033;
 
+// desert jumps : a row of slopes across the road every JUMP_SPACING voxels
+const JUMP_SPACING:f32 = 64.0;
+const JUMP_WIDTH:f32 = 4.0;
+// in voxels, between the city curbs (half slabs on both sides of the road), ravine walls start RAVINE_MARGIN voxels farther
+pub const DEFAULT_ROAD_WIDTH:f32 = 12.0;
+const RAVINE_MARGIN:f32 = 9.0;
+// pos is in a segment up to that many voxels beside its road
+const SEGMENT_MARGIN:f32 = 24.0;
+// players can't edit voxels that close to the driving surface (see server::voxel_edits)
+const PROTECTED_MARGIN:f32 = 1.0;
+const PROTECTED_DEPTH:f32 = 3.0;
+const PROTECTED_HEIGHT:f32 = 3.0;
+// starting grid : GRID_COLUMNS vehicles side by side, rows GRID_ROW_SPACING voxels apart going back from the road start
+const GRID_COLUMNS:usize = 3;
+const GRID_COLUMN_SPACING:f32 = 4.0;
+const GRID_ROW_SPACING:f32 = 7.0;
+const GRID_HEIGHT:f32 = 1.5;
+// closed tracks : four straights each followed by a left quarter turn, flat so that the road meets its start again
+// lengths in chunks like the steps of step_forwards, straights are at least two segments so that the starting grid is on one
+const TRACK_SEGMENT_STEPS:f32 = 3.0;
+const TRACK_STRAIGHT_MIN:f32 = 6.0;
+// sections of a road made from sections are split in segments at most that long
+const SECTION_SEGMENT_MAX_STEPS:f32 = 6.0;
+
+// voxel types placed by the biomes, looked up once in the registry by their string id
+#[derive(Clone, ToBytes, FromBytes, Debug, PartialEq)]
+pub struct RoadVoxels {
+    air:u16,
+    sand:u16,
+    flowers:u16,
+    grassy_ground:u16,
+    ground:u16,
+    rock:u16,
+    snow:u16,
+    sand_slope:u16,
+    rock_half_slab:u16,
+}
+
+impl RoadVoxels {
+    pub fn from_registry(registry:&VoxelRegistry) -> Self {
+        Self {
+            air:registry.id("air"),
+            sand:registry.id("sand"),
+            flowers:registry.id("flowers"),
+            grassy_ground:registry.id("grassy_ground"),
+            ground:registry.id("ground"),
+            rock:registry.id("rock"),
+            snow:registry.id("snow"),
+            sand_slope:registry.id("sand_slope"),
+            rock_half_slab:registry.id("rock_half_slab"),
+        }
+    }
+}
+
 #[derive(Clone, ToBytes, FromBytes, Debug, PartialEq)]
 pub struct Road {
     start:WorldChunkPos,
@@ -17,7 +71,22 @@ pub struct Road {
     center_road_plane:EquationPlane,
     segments:Vec<RoadSegment>,
     current_biome:RoadBiome,
+    // in voxels, and in radians (positive lowers the right side of the road)
+    current_width:f32,
+    current_banking:f32,
+    // biomes the road can switch to, never empty
+    biomes:Vec<RoadBiome>,
     biome_probability:f32,
+    seed:u64,
+    // state of the road's own RNG, so that two roads with the same seed generate the same segments
+    rng_state:u64,
+    voxels:RoadVoxels,
+    // finite roads have every segment planned up front, closed tracks asked for that many segments, None for an endless road
+    track_length:Option<usize>,
+    // the last segment of a finite road leads back to its first one
+    loops:bool,
+    // segments of a closed track whose chunks were generated
+    built:usize,
 }
 
 impl Generator<CoolVoxel> for Road {
@@ -45,7 +114,7 @@ impl Generator<CoolVoxel> for Road {
         if let Some(dist) = closest_distance && dist < 0.0 && let Some(seg) = closest_segment {
             let segment = &self.segments[seg];
             let normal = segment.road_plane.get_normal();
-            Some(Collision { surface_normal: normal, minimum_nudge:normal * dist.abs(), voxel: CoolVoxel::new(4, 0, VoxelLight::max_light(), None), position:pos })
+            Some(Collision { surface_normal: normal, minimum_nudge:normal * dist.abs(), voxel: CoolVoxel::new(self.voxels.ground, 0, VoxelLight::max_light(), None), position:pos })
         }
         else {
             None
@@ -54,13 +123,195 @@ impl Generator<CoolVoxel> for Road {
 }
 
 impl Road {
-    pub fn new(start:WorldChunkPos, start_dir:Vec3Df) -> Self {
+    pub fn new(start:WorldChunkPos, start_dir:Vec3Df, seed:u64, voxels:RoadVoxels) -> Self {
         let perp = start_dir.cross(&Vec3D::new(0.0, 0.0, 1.0));
         let current_head_c = get_float_pos(start) + Vec3Df::new(-0.5, -0.5, 0.25);
-        Self { biome_probability:0.0, current_biome:RoadBiome::City,start, current_head_c, current_direction_c: start_dir, road_plane: VectorPlane::new(perp, start_dir, current_head_c).to_equation_plane(), center_road_plane:EquationPlane::new(Vec3Df::all_ones(), 0.0), segments:Vec::with_capacity(32) }
+        Self { biome_probability:0.0, current_biome:RoadBiome::City, biomes:ALL_BIOMES.to_vec(), start, current_head_c, current_direction_c: start_dir, road_plane: VectorPlane::new(perp, start_dir, current_head_c).to_equation_plane(), center_road_plane:EquationPlane::new(Vec3Df::all_ones(), 0.0), segments:Vec::with_capacity(32), seed, rng_state:seed, voxels, current_width:DEFAULT_ROAD_WIDTH, current_banking:0.0, track_length:None, loops:false, built:0 }
+    }
+    // an empty list keeps every biome
+    pub fn with_biomes(mut self, biomes:Vec<RoadBiome>) -> Self {
+        if !biomes.is_empty() {
+            if !biomes.contains(&self.current_biome) {
+                self.current_biome = biomes[0].clone();
+            }
+            self.biomes = biomes;
+        }
+        self
+    }
+    // voxel ids of this registry, for roads made with another one
+    pub fn with_voxels(mut self, voxels:RoadVoxels) -> Self {
+        self.voxels = voxels;
+        self
+    }
+    // plans a circuit of about segment_count segments coming back to the start of the road, see step_forwards
+    // straights start along an axis so that city ones stay axis aligned, corners take the corner_segments of their biome
+    pub fn into_closed_track(mut self, segment_count:usize, chunk_dims:&Vec3Df) -> Self {
+        let mut rng = fastrand::Rng::with_seed(self.rng_state);
+        let heading = self.current_direction_c.y.atan2(self.current_direction_c.x);
+        let start_heading = (heading / FRAC_PI_2).round() * FRAC_PI_2;
+        // every side is a straight and the corner after it, the biome changes between sides
+        let mut biome = self.current_biome.clone();
+        let mut sides = Vec::with_capacity(4);
+        for side in 0..4 {
+            if side > 0 && rng.bool() {
+                biome = rng.choice(self.biomes.clone()).unwrap();
+            }
+            sides.push(biome.clone());
+        }
+        let corners:Vec<usize> = sides.iter().map(|biome| {biome.corner_segments()}).collect();
+        // a corner ends as far forwards as to the left of where it starts
+        let offsets:Vec<f32> = corners.iter().map(|corner| {(0..*corner).map(|j| {((j as f32 + 0.5) * FRAC_PI_2 / *corner as f32).cos() * TRACK_SEGMENT_STEPS}).sum()}).collect();
+        let straight_total = segment_count.saturating_sub(corners.iter().sum::<usize>()) as f32 * TRACK_SEGMENT_STEPS;
+        // opposite straights differ by what the corners move the road along them
+        let third_diff = offsets[0] + offsets[3] - offsets[1] - offsets[2];
+        let fourth_diff = offsets[0] + offsets[1] - offsets[2] - offsets[3];
+        let first_two = straight_total * 0.5 - offsets[0] + offsets[2];
+        let first = (first_two * (0.35 + rng.f32() * 0.3)).max(TRACK_STRAIGHT_MIN).max(TRACK_STRAIGHT_MIN - third_diff);
+        let second = (first_two - first).max(TRACK_STRAIGHT_MIN).max(TRACK_STRAIGHT_MIN - fourth_diff);
+        let straights = [first, second, first + third_diff, second + fourth_diff];
+        // (heading, steps, biome) of every segment
+        let mut plan = Vec::with_capacity(segment_count + 8);
+        let mut first_pieces = 0;
+        for side in 0..4 {
+            let heading = start_heading + side as f32 * FRAC_PI_2;
+            let pieces = ((straights[side] / TRACK_SEGMENT_STEPS).round() as usize).max(1);
+            if side == 0 {
+                first_pieces = pieces;
+            }
+            for _ in 0..pieces {
+                plan.push((heading, straights[side] / pieces as f32, sides[side].clone()));
+            }
+            for j in 0..corners[side] {
+                plan.push((heading + (j as f32 + 0.5) * FRAC_PI_2 / corners[side] as f32, TRACK_SEGMENT_STEPS, sides[side].clone()));
+            }
+        }
+        // the start line is in the middle of the first straight, the starting grid behind it is on the straight too
+        plan.rotate_left(first_pieces / 2);
+        self.segments = Vec::with_capacity(plan.len());
+        for (heading, steps, biome) in plan {
+            self.turn_to(biome, Vec3Df::new(heading.cos(), heading.sin(), 0.0), chunk_dims);
+            self.push_segment(steps, chunk_dims);
+        }
+        self.rng_state = rng.get_seed();
+        self.track_length = Some(segment_count);
+        self.loops = true;
+        self.built = 0;
+        self.load_segment(0, chunk_dims);
+        println!("[Road] Planned a closed track of {} segments, {:.0} voxels long, through {}", self.segments.len(), self.length_of(self.segments.len()), sides.iter().map(|biome| {biome.get_name()}).collect::<Vec<&str>>().join(", "));
+        self
+    }
+    // a road going straight from the start of every section to the start of the next one, see game_map::track
+    // a loop comes back from its last section to its first one, otherwise the road ends at the start of its last section
+    // a loop needs at least 3 sections and an open road 2, and no section can start where the one before it does
+    pub fn from_sections(sections:&[RoadSection], loops:bool, seed:u64, voxels:RoadVoxels, chunk_dims:&Vec3Df) -> Result<Self, String> {
+        let needed = if loops {3} else {2};
+        if sections.len() < needed {
+            return Err(format!("a {} road needs at least {} sections, got {}", if loops {"closed"} else {"open"}, needed, sections.len()));
+        }
+        let count = if loops {sections.len()} else {sections.len() - 1};
+        for i in 0..count {
+            if sections[(i + 1) % sections.len()].start == sections[i].start {
+                return Err(format!("sections {} and {} start at the same position", i, (i + 1) % sections.len()));
+            }
+        }
+        let to_chunks = |pos:Vec3Df| {Vec3Df::new(pos.x / chunk_dims.x, pos.y / chunk_dims.y, pos.z / chunk_dims.z)};
+        let first_direction = (sections[1].start - sections[0].start).normalise();
+        let mut road = Road::new(get_voxel_pos(to_chunks(sections[0].start)), first_direction, seed, voxels);
+        road.segments = Vec::with_capacity(sections.len() * 2);
+        road.biomes = Vec::with_capacity(4);
+        for i in 0..count {
+            let (from, to) = (&sections[i], &sections[(i + 1) % sections.len()]);
+            let along = to_chunks(to.start) - to_chunks(from.start);
+            let steps = along.dot(&along).sqrt();
+            let pieces = (steps / SECTION_SEGMENT_MAX_STEPS).ceil().max(1.0) as usize;
+            road.current_head_c = to_chunks(from.start);
+            road.current_width = from.width;
+            road.current_banking = from.banking;
+            for _ in 0..pieces {
+                road.turn_to(from.biome.clone(), along.normalise(), chunk_dims);
+                road.push_segment(steps / pieces as f32, chunk_dims);
+            }
+            if !road.biomes.contains(&from.biome) {
+                road.biomes.push(from.biome.clone());
+            }
+        }
+        road.track_length = Some(road.segments.len());
+        road.loops = loops;
+        road.built = 0;
+        road.load_segment(0, chunk_dims);
+        Ok(road)
+    }
+    // one section per segment, from_sections makes the same segments again
+    // a road that doesn't loop gets one more section where it ends
+    pub fn get_sections(&self) -> Vec<RoadSection> {
+        let mut sections:Vec<RoadSection> = self.segments.iter().map(|segment| {RoadSection { start:segment.head, biome:segment.segment_biome.clone(), width:segment.width, banking:segment.banking }}).collect();
+        if !self.is_closed() && let Some(last) = self.segments.last() {
+            sections.push(RoadSection { start:last.head + last.direction * last.length, biome:last.segment_biome.clone(), width:last.width, banking:last.banking });
+        }
+        sections
+    }
+    // the same road with none of its chunks generated yet, what a track is saved as
+    pub fn as_unbuilt(&self, chunk_dims:&Vec3Df) -> Self {
+        let mut road = self.clone();
+        if road.is_finite() {
+            road.built = 0;
+            road.load_segment(0, chunk_dims);
+        }
+        road
+    }
+    // picks the seed and biomes of a road that didn't start generating, false once it did
+    // a closed track is planned again with them
+    pub fn set_track(&mut self, seed:u64, biomes:Vec<RoadBiome>, chunk_dims:&Vec3Df) -> bool {
+        if self.has_started() {
+            return false;
+        }
+        let road = Road::new(self.start, self.current_direction_c, seed, self.voxels.clone()).with_biomes(biomes);
+        *self = match self.track_length {
+            Some(segment_count) => road.into_closed_track(segment_count, chunk_dims),
+            None => road
+        };
+        true
+    }
+    // every segment is planned, the road never grows
+    pub fn is_finite(&self) -> bool {
+        self.track_length.is_some()
+    }
+    pub fn is_closed(&self) -> bool {
+        self.is_finite() && self.loops
+    }
+    pub fn has_started(&self) -> bool {
+        match self.track_length {
+            Some(_) => self.built > 0,
+            None => !self.segments.is_empty()
+        }
+    }
+    // the server generates the road before letting vehicles drive, then only grows endless roads as they get close to the end
+    pub fn initial_generation_done(&self) -> bool {
+        match self.track_length {
+            Some(_) => self.built >= self.segments.len(),
+            None => self.segments.len() >= 100
+        }
+    }
+    pub fn get_seed(&self) -> u64 {
+        self.seed
+    }
+    pub fn get_biomes(&self) -> &Vec<RoadBiome> {
+        &self.biomes
+    }
+    // in chunk coordinates
+    pub fn get_head(&self) -> Vec3Df {
+        self.current_head_c
+    }
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
     }
     pub fn get_chunks_to_generate(&self, steps:f32, world:&GameMap<CoolVoxel, Self>) -> Vec<WorldChunkPos> {
         let mut chunks = HashSet::with_capacity(16);
+        // the segment of a finite road being built has its own length
+        let steps = match self.track_length {
+            Some(_) => self.segments[self.built % self.segments.len()].length / world.get_chunk_dims_vector_f().x,
+            None => steps
+        };
         let mut i = 0.3;
         while i <= steps {
             let at = self.current_head_c + self.current_direction_c * i;
@@ -76,7 +327,25 @@ impl Road {
         }
         chunks.drain().collect()
     }
+    // endless roads get a new segment, finite ones move on to the next of their planned segments until they are back to the first
     pub fn step_forwards(&mut self, steps:f32, chunk_dims:&Vec3Df) {
+        match self.track_length {
+            Some(_) => if self.built < self.segments.len() {
+                self.built += 1;
+                self.load_segment(self.built % self.segments.len(), chunk_dims);
+            },
+            None => {
+                self.push_segment(steps, chunk_dims);
+                let mut rng = fastrand::Rng::with_seed(self.rng_state);
+                let biome = self.get_next_biome(&mut rng);
+                let direction = biome.get_next_direction(self.current_direction_c, &mut rng);
+                self.rng_state = rng.get_seed();
+                self.turn_to(biome, direction, chunk_dims);
+            }
+        }
+    }
+    // a segment from the head with the current planes, then the head moves to its end
+    fn push_segment(&mut self, steps:f32, chunk_dims:&Vec3Df) {
         let perp = self.current_direction_c.cross(&Vec3D::new(0.0, 0.0, 1.0));
         let normal = self.current_direction_c.cross(&perp);
         self.segments.push(
@@ -87,21 +356,33 @@ impl Road {
                 road_plane: self.road_plane.clone(),
                 center_road_plane: self.center_road_plane.clone(),
                 road_slice_plane: VectorPlane::new(perp, normal, self.current_head_c.component_product(chunk_dims)).to_equation_plane(),
-                segment_biome:self.current_biome.clone()
+                segment_biome:self.current_biome.clone(),
+                width:self.current_width,
+                banking:self.current_banking,
             }
         );
-        self.current_biome = self.get_next_biome();
         self.current_head_c += self.current_direction_c * steps;
-        self.current_direction_c = self.current_biome.get_next_direction(self.current_direction_c);
+    }
+    fn turn_to(&mut self, biome:RoadBiome, direction:Vec3Df, chunk_dims:&Vec3Df) {
+        self.current_biome = biome;
+        self.current_direction_c = direction;
         let perp = self.current_direction_c.cross(&Vec3D::new(0.0, 0.0, 1.0));
-        let normal = self.current_direction_c.cross(&perp);
+        // banking turns the road around its direction
+        let perp = if self.current_banking != 0.0 {
+            let normal = self.current_direction_c.cross(&perp);
+            perp * self.current_banking.cos() + normal * self.current_banking.sin()
+        }
+        else {
+            perp
+        };
         self.road_plane = VectorPlane::new(perp, self.current_direction_c, self.current_head_c.component_product(chunk_dims)).to_equation_plane();
         self.center_road_plane = VectorPlane::new(Vec3D::new(0.0, 0.0, 1.0), self.current_direction_c, self.current_head_c.component_product(chunk_dims)).to_equation_plane();
     }
-    pub fn position_within_last(&self, pos:Vec3Df) -> bool {
+    // segment whose road is the closest under pos, only looking at the segments in range
+    pub fn closest_segment_in(&self, pos:Vec3Df, range:Range<usize>) -> Option<usize> {
         let mut closest_segment = None;
         let mut closest_distance = None;
-        for i in 0..self.segments.len() {
+        for i in range.start.min(self.segments.len())..range.end.min(self.segments.len()) {
             match self.segments[i].distance_to_road_if_in_segment(pos) {
                 Some(distance) => if distance < closest_distance.unwrap_or(f32::INFINITY) {
                     closest_distance = Some(distance);
@@ -110,18 +391,73 @@ impl Road {
                 None => ()
             }
         }
-        if let Some(closest) = closest_segment && self.segments.len() - closest < 10 {
+        closest_segment
+    }
+    pub fn closest_segment(&self, pos:Vec3Df) -> Option<usize> {
+        self.closest_segment_in(pos, 0..self.segments.len())
+    }
+    // in voxels along the road from its start, pos being in that segment
+    pub fn distance_along(&self, segment:usize, pos:Vec3Df) -> f32 {
+        self.segments[..segment].iter().map(|segment| {segment.length}).sum::<f32>() + self.segments[segment].distance_within(pos)
+    }
+    // length of the first count segments
+    pub fn length_of(&self, count:usize) -> f32 {
+        self.segments.iter().take(count).map(|segment| {segment.length}).sum()
+    }
+    // a finite road never grows
+    pub fn position_within_last(&self, pos:Vec3Df) -> bool {
+        if self.track_length.is_none() && let Some(closest) = self.closest_segment(pos) && self.segments.len() - closest < 10 {
             true
         }
         else {
             false
         }
     }
-    fn get_next_biome(&mut self) -> RoadBiome {
-        let rand = fastrand::f32();
+    // count spots behind the start of the first segment, facing along it, front row first
+    // empty until the first segment is generated
+    pub fn starting_grid(&self, count:usize) -> Vec<(Vec3Df, Orientation)> {
+        let first = match self.segments.first() {
+            Some(first) => first,
+            None => return Vec::new()
+        };
+        let forwards = Vec3Df::new(first.direction.x, first.direction.y, 0.0).normalise();
+        let side = forwards.cross(&Vec3D::new(0.0, 0.0, 1.0));
+        let orient = Orientation::new(forwards.y.atan2(forwards.x), 0.0, 0.0);
+        let mut spots = Vec::with_capacity(count);
+        for slot in 0..count {
+            let (row, column) = (slot / GRID_COLUMNS, slot % GRID_COLUMNS);
+            // the front row is one row into the segment so that nobody starts off the road
+            let along = first.head + forwards * (GRID_ROW_SPACING * (1.0 - row as f32));
+            let across = side * ((column as f32 - (GRID_COLUMNS - 1) as f32 * 0.5) * GRID_COLUMN_SPACING);
+            let on_road = along + across;
+            // straight above the road plane, whatever its slope
+            let height = first.distance_to_road(on_road);
+            spots.push((on_road + Vec3Df::new(0.0, 0.0, GRID_HEIGHT - height), orient));
+        }
+        spots
+    }
+    pub fn is_protected(&self, pos:Vec3Df) -> bool {
+        self.segments.iter().any(|segment| {segment.is_on_road_surface(pos)})
+    }
+    fn on_jump(&self, pos:Vec3Df) -> bool {
+        pos.dot(&self.current_direction_c).rem_euclid(JUMP_SPACING) < 1.0 && self.center_road_plane.signed_distance(&pos).abs() <= JUMP_WIDTH
+    }
+    // back to where the road was when that planned segment was pushed
+    fn load_segment(&mut self, index:usize, chunk_dims:&Vec3Df) {
+        let segment = &self.segments[index];
+        self.current_head_c = Vec3Df::new(segment.head.x / chunk_dims.x, segment.head.y / chunk_dims.y, segment.head.z / chunk_dims.z);
+        self.current_direction_c = segment.direction;
+        self.road_plane = segment.road_plane.clone();
+        self.center_road_plane = segment.center_road_plane.clone();
+        self.current_biome = segment.segment_biome.clone();
+        self.current_width = segment.width;
+        self.current_banking = segment.banking;
+    }
+    fn get_next_biome(&mut self, rng:&mut fastrand::Rng) -> RoadBiome {
+        let rand = rng.f32();
         if rand < self.biome_probability {
             self.biome_probability = 0.0;
-            fastrand::choice(ALL_BIOMES).unwrap()
+            rng.choice(self.biomes.clone()).unwrap()
         }
         else {
             self.biome_probability += BIOME_PROBABILITY_CHANGE;
@@ -140,87 +476,114 @@ pub enum RoadBiome {
 
 impl RoadBiome {
     pub fn generate(&self, float_pos:Vec3Df, road:&Road) -> CoolVoxel {
+        let voxels = &road.voxels;
         match self {
             Self::Desert => {
                 let dist = road.road_plane.signed_distance(&float_pos);
-                if dist > -1.2 {
-                    CoolVoxel::new(0, 0, VoxelLight::max_light(), None)
+                if dist > -1.2 && dist <= -0.2 && road.on_jump(float_pos) {
+                    CoolVoxel::new(voxels.sand_slope, orientation_from(2, quarter_turns_towards(road.current_direction_c)), VoxelLight::zero_light(), None)
+                }
+                else if dist > -1.2 {
+                    CoolVoxel::new(voxels.air, 0, VoxelLight::zero_light(), None)
                 }
                 else {
-                    CoolVoxel::new(1, 0, VoxelLight::max_light(), None)
+                    CoolVoxel::new(voxels.sand, 0, VoxelLight::zero_light(), None)
                 }
             },
             Self::Plains => {
                 let dist = road.road_plane.signed_distance(&float_pos);
                 if dist > -1.2 {
-                    CoolVoxel::new(0, 0, VoxelLight::max_light(), None)
+                    CoolVoxel::new(voxels.air, 0, VoxelLight::zero_light(), None)
                 }
                 else {
                     let center_dist = road.center_road_plane.signed_distance(&float_pos);
                     if center_dist.abs() <= 1.5 {
-                        CoolVoxel::new(3, 0, VoxelLight::max_light(), None)
+                        CoolVoxel::new(voxels.grassy_ground, 0, VoxelLight::zero_light(), None)
                     }
                     else {
-                        CoolVoxel::new(2, 0, VoxelLight::max_light(), None)
+                        CoolVoxel::new(voxels.flowers, 0, VoxelLight::zero_light(), None)
                     }
                 }
             },
             Self::Ravine => {
-                const RAVINE_WIDTH:f32 = 15.0;
+                let ravine_width = road.current_width * 0.5 + RAVINE_MARGIN;
                 let center_dist = road.center_road_plane.signed_distance(&float_pos);
                 let dist = road.road_plane.signed_distance(&float_pos);
-                if center_dist.abs() <= RAVINE_WIDTH {
+                if center_dist.abs() <= ravine_width {
                     if dist > -1.2 {
-                        CoolVoxel::new(0, 0, VoxelLight::max_light(), None)
+                        CoolVoxel::new(voxels.air, 0, VoxelLight::zero_light(), None)
                     }
                     else {
                         let center_dist = road.center_road_plane.signed_distance(&float_pos);
                         if center_dist.abs() <= 1.5 {
-                            CoolVoxel::new(3, 0, VoxelLight::max_light(), None)
+                            CoolVoxel::new(voxels.grassy_ground, 0, VoxelLight::zero_light(), None)
                         }
                         else {
-                            CoolVoxel::new(2, 0, VoxelLight::max_light(), None)
+                            CoolVoxel::new(voxels.flowers, 0, VoxelLight::zero_light(), None)
                         }
                     }
                 }
-                else if center_dist.abs() >= RAVINE_WIDTH + dist/RAVINE_WIDTH * 5.0 {
-                    CoolVoxel::new(4, 0, VoxelLight::max_light(), None)
+                else if center_dist.abs() >= ravine_width + dist/ravine_width * 5.0 {
+                    CoolVoxel::new(voxels.ground, 0, VoxelLight::zero_light(), None)
                 }
                 else {
-                    CoolVoxel::new(0, 0, VoxelLight::max_light(), None)
+                    CoolVoxel::new(voxels.air, 0, VoxelLight::zero_light(), None)
                 }
             },
             Self::City => {
                 let dist = road.road_plane.signed_distance(&float_pos);
-                if dist > -1.2 {
-                    CoolVoxel::new(0, 0, VoxelLight::max_light(), None)
+                let center_dist = road.center_road_plane.signed_distance(&float_pos);
+                let curb_distance = road.current_width * 0.5;
+                if dist > -1.2 && dist <= -0.2 && center_dist.abs() >= curb_distance && center_dist.abs() < curb_distance + 1.0 {
+                    CoolVoxel::new(voxels.rock_half_slab, 0, VoxelLight::zero_light(), None)
+                }
+                else if dist > -1.2 {
+                    CoolVoxel::new(voxels.air, 0, VoxelLight::zero_light(), None)
                 }
                 else {
-                    let center_dist = road.center_road_plane.signed_distance(&float_pos);
                     if center_dist.abs() <= 1.5 {
-                        CoolVoxel::new(6, 0, VoxelLight::max_light(), None)
+                        CoolVoxel::new(voxels.snow, 0, VoxelLight::zero_light(), None)
                     }
                     else {
-                        CoolVoxel::new(5, 0, VoxelLight::max_light(), None)
+                        CoolVoxel::new(voxels.rock, 0, VoxelLight::zero_light(), None)
                     }
                 }
             }
         }
     }
-    pub fn get_next_direction(&self, mut current_direction_c:Vec3Df) -> Vec3Df {
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Self::Desert => "desert",
+            Self::Plains => "plains",
+            Self::City => "city",
+            Self::Ravine => "ravine",
+        }
+    }
+    pub fn from_name(name:&str) -> Option<Self> {
+        ALL_BIOMES.into_iter().find(|biome| {biome.get_name() == name.trim().to_lowercase()})
+    }
+    // segments of a quarter turn on a closed track, each turning by the same angle, cities turn at once
+    pub fn corner_segments(&self) -> usize {
+        match self {
+            Self::Desert => 4,
+            Self::Plains | Self::Ravine => 6,
+            Self::City => 0,
+        }
+    }
+    pub fn get_next_direction(&self, mut current_direction_c:Vec3Df, rng:&mut fastrand::Rng) -> Vec3Df {
         match self {
             Self::Desert => {
-                current_direction_c.x += (fastrand::f32() - 0.5) * 0.1;
-                current_direction_c.y += (fastrand::f32() - 0.5) * 0.1;
-                current_direction_c.z += (fastrand::f32() - 0.5) * 0.1;
+                current_direction_c.x += (rng.f32() - 0.5) * 0.1;
+                current_direction_c.y += (rng.f32() - 0.5) * 0.1;
+                current_direction_c.z += (rng.f32() - 0.5) * 0.1;
                 current_direction_c.z = current_direction_c.z.clamp(-0.1, 0.1);
                 current_direction_c = current_direction_c.normalise();
                 current_direction_c
             },
             Self::Plains | Self::Ravine => {
-                current_direction_c.x += (fastrand::f32() - 0.5) * 0.01;
-                current_direction_c.y += (fastrand::f32() - 0.5) * 0.01;
-                current_direction_c.z += (fastrand::f32() - 0.5) * 0.01;
+                current_direction_c.x += (rng.f32() - 0.5) * 0.01;
+                current_direction_c.y += (rng.f32() - 0.5) * 0.01;
+                current_direction_c.z += (rng.f32() - 0.5) * 0.01;
                 current_direction_c.z = current_direction_c.z.clamp(-0.05, 0.05);
                 current_direction_c = current_direction_c.normalise();
                 current_direction_c
@@ -256,14 +619,36 @@ pub struct RoadSegment {
     road_plane:EquationPlane,
     center_road_plane:EquationPlane,
     road_slice_plane:EquationPlane,
-    segment_biome:RoadBiome
+    segment_biome:RoadBiome,
+    width:f32,
+    banking:f32,
+}
 
+// what a finite road is made from, see Road::from_sections
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoadSection {
+    // in voxels
+    pub start:Vec3Df,
+    pub biome:RoadBiome,
+    // in voxels, and in radians (positive lowers the right side of the road)
+    pub width:f32,
+    pub banking:f32,
 }
 
 impl RoadSegment {
     pub fn in_segment(&self, pos:Vec3Df) -> bool {
         let back_dist = self.road_slice_plane.signed_distance(&pos);
-        self.center_road_plane.signed_distance(&pos).abs() < 30.0 && self.road_plane.signed_distance(&pos).abs() <= 10.0 && back_dist >= 0.0 && back_dist <= self.length
+        self.center_road_plane.signed_distance(&pos).abs() < self.width * 0.5 + SEGMENT_MARGIN && self.road_plane.signed_distance(&pos).abs() <= 10.0 && back_dist >= 0.0 && back_dist <= self.length
+    }
+    // the road itself, what's just under it and the space right above it
+    pub fn is_on_road_surface(&self, pos:Vec3Df) -> bool {
+        let back_dist = self.road_slice_plane.signed_distance(&pos);
+        let height = self.road_plane.signed_distance(&pos);
+        back_dist >= 0.0 && back_dist <= self.length && self.center_road_plane.signed_distance(&pos).abs() <= self.width * 0.5 + PROTECTED_MARGIN && height >= -PROTECTED_DEPTH && height <= PROTECTED_HEIGHT
+    }
+    // how far into the segment pos is, along its direction
+    pub fn distance_within(&self, pos:Vec3Df) -> f32 {
+        self.road_slice_plane.signed_distance(&pos).clamp(0.0, self.length)
     }
     pub fn distance_to_road(&self, pos:Vec3Df) -> f32 {
         self.road_plane.signed_distance(&pos)
@@ -276,4 +661,59 @@ impl RoadSegment {
             None
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use hord3::horde::geometry::vec3d::Vec3Df;
+
+    use crate::{game_map::{WorldChunkPos, road::{Road, RoadBiome, RoadSection, RoadVoxels}, storage::{decode, encode}}, voxel_registry::{VoxelRegistry, voxel_registry}};
+
+    // an endless road stepped like the server does
+    fn stepped_road(seed:u64, steps:usize) -> Road {
+        // other tests may have loaded it already
+        let _ = VoxelRegistry::load_global(PathBuf::from("voxels.json"));
+        let dims = Vec3Df::new(8.0, 8.0, 8.0);
+        let mut road = Road::new(WorldChunkPos::new(0,0,1), Vec3Df::new(1.0, 0.0, 0.0), seed, RoadVoxels::from_registry(voxel_registry()));
+        for _ in 0..steps {
+            road.step_forwards(3.0, &dims);
+        }
+        road
+    }
+
+    #[test]
+    fn same_seed_makes_the_same_road() {
+        let road = stepped_road(42, 50);
+        assert_eq!(road.segment_count(), 50);
+        assert_eq!(road, stepped_road(42, 50));
+    }
+
+    #[test]
+    fn saved_road_keeps_stepping_the_same() {
+        let dims = Vec3Df::new(8.0, 8.0, 8.0);
+        let mut road:Road = decode(&encode(&stepped_road(42, 20))).unwrap();
+        for _ in 0..30 {
+            road.step_forwards(3.0, &dims);
+        }
+        assert_eq!(road, stepped_road(42, 50));
+    }
+
+    #[test]
+    fn other_seed_makes_other_segments() {
+        assert_ne!(stepped_road(42, 50).get_sections(), stepped_road(43, 50).get_sections());
+    }
+
+    #[test]
+    fn too_few_sections_are_refused() {
+        let _ = VoxelRegistry::load_global(PathBuf::from("voxels.json"));
+        let dims = Vec3Df::new(8.0, 8.0, 8.0);
+        let section = |x:f32| {RoadSection { start:Vec3Df::new(x, 0.0, 8.0), biome:RoadBiome::City, width:8.0, banking:0.0 }};
+        let voxels = RoadVoxels::from_registry(voxel_registry());
+        assert!(Road::from_sections(&[], false, 0, voxels.clone(), &dims).is_err());
+        assert!(Road::from_sections(&[section(0.0)], false, 0, voxels.clone(), &dims).is_err());
+        assert!(Road::from_sections(&[section(0.0), section(64.0)], true, 0, voxels.clone(), &dims).is_err());
+        assert!(Road::from_sections(&[section(0.0), section(0.0)], false, 0, voxels.clone(), &dims).is_err());
+        assert!(Road::from_sections(&[section(0.0), section(64.0)], false, 0, voxels, &dims).is_ok());
+    }
+}
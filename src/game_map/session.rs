@@ -0,0 +1,70 @@
+use hord3::horde::game_engine::multiplayer::MustSync;
+use to_from_bytes_derive::{FromBytes, ToBytes};
+
+use super::{checksums::{ResyncRequest, StateChecksums}, lobby::LobbyEvent, players::PlayerEvent, race::RaceEvent, vehicle_states::VehicleState};
+
+// Messages between the server and its clients that don't change the map, carried by GameMapEvent::Session
+// the map keeps them in a single queue, the server and client loops take them once a tick and split them by kind, see SessionMessages
+#[derive(Clone, ToBytes, FromBytes, PartialEq)]
+pub enum SessionMessage {
+    // joining and leaving, see players
+    Player(PlayerEvent),
+    // state of a player's vehicle for client side prediction, see vehicle_states
+    VehicleState(VehicleState),
+    // regular checksums of the server's state, and clients asking for what differs from it, see checksums
+    Checksums(StateChecksums),
+    RequestResync(ResyncRequest),
+    // vehicle picks, ready checks, track setup and race start, see lobby
+    Lobby(LobbyEvent),
+    // checkpoints, live positions and the leaderboard, see race
+    Race(RaceEvent),
+}
+
+impl SessionMessage {
+    pub fn should_sync(&self) -> MustSync {
+        match self {
+            Self::VehicleState(_) | Self::Checksums(_) => MustSync::Server,
+            Self::RequestResync(_) => MustSync::Client,
+            Self::Player(event) => event.should_sync(),
+            Self::Lobby(event) => event.should_sync(),
+            Self::Race(event) => event.should_sync(),
+        }
+    }
+    // player and lobby events are kept by both sides, each side ignores what it sent itself
+    // the others are only kept by the side they are sent to
+    pub fn is_kept_by(&self, authoritative:bool) -> bool {
+        match self {
+            Self::Player(_) | Self::Lobby(_) => true,
+            Self::VehicleState(_) | Self::Checksums(_) | Self::Race(_) => !authoritative,
+            Self::RequestResync(_) => authoritative,
+        }
+    }
+}
+
+// the session messages of a tick, in the order they came in for each kind
+#[derive(Clone, Default)]
+pub struct SessionMessages {
+    pub players:Vec<PlayerEvent>,
+    pub vehicle_states:Vec<VehicleState>,
+    pub checksums:Vec<StateChecksums>,
+    pub resync_requests:Vec<ResyncRequest>,
+    pub lobby:Vec<LobbyEvent>,
+    pub race:Vec<RaceEvent>,
+}
+
+impl SessionMessages {
+    pub fn from_messages(messages:Vec<SessionMessage>) -> Self {
+        let mut sorted = Self::default();
+        for message in messages {
+            match message {
+                SessionMessage::Player(event) => sorted.players.push(event),
+                SessionMessage::VehicleState(state) => sorted.vehicle_states.push(state),
+                SessionMessage::Checksums(checksums) => sorted.checksums.push(checksums),
+                SessionMessage::RequestResync(request) => sorted.resync_requests.push(request),
+                SessionMessage::Lobby(event) => sorted.lobby.push(event),
+                SessionMessage::Race(event) => sorted.race.push(event),
+            }
+        }
+        sorted
+    }
+}
@@ -0,0 +1,371 @@
+use std::{collections::{HashMap, HashSet}, fs::{self, File}, io::{self, BufReader, Read, Seek}, panic::{self, AssertUnwindSafe}, path::{Path, PathBuf}};
+
+use hord3::horde::geometry::vec3d::Vec3D;
+use to_from_bytes::{FromBytes, ToBytes};
+
+use crate::game_map::{GameMap, Generator, MapChunk, Voxel, WorldChunkPos, chunk_compression::CompressedChunk};
+
+// Chunks are grouped in cubes of REGION_SIZE^3 chunks, one file per region
+// region file layout (little endian) :
+// magic "HRG1", u32 SAVE_VERSION, u32 chunk count, then for every chunk : i32 x, i32 y, i32 z, u32 byte length, CompressedChunk bytes
+// generator file layout : magic "HGN1", u32 SAVE_VERSION, then the generator bytes
+pub const REGION_SIZE:i32 = 8;
+const REGION_MAGIC:&[u8 ; 4] = b"HRG1";
+const GENERATOR_MAGIC:&[u8 ; 4] = b"HGN1";
+const GENERATOR_FILE:&str = "generator.bin";
+// bump it whenever the bytes of a saved type change (Road and its segments, MapChunk, CompressedChunk), replays hold them too
+// files of other versions are refused instead of being read as garbage
+pub const SAVE_VERSION:u32 = 1;
+
+pub type RegionPos = Vec3D<i32>;
+
+pub fn get_region_pos(chunk_pos:WorldChunkPos) -> RegionPos {
+    Vec3D::new(
+        chunk_pos.x.div_floor(REGION_SIZE),
+        chunk_pos.y.div_floor(REGION_SIZE),
+        chunk_pos.z.div_floor(REGION_SIZE),
+    )
+}
+
+// every (de)serialization of game data to disk goes through these two
+pub fn encode<T:ToBytes>(value:&T) -> Vec<u8> {
+    value.to_bytes()
+}
+
+// from_bytes panics on truncated or corrupt bytes, that panic is turned into an InvalidData error
+pub fn decode<T:FromBytes>(bytes:&[u8]) -> io::Result<T> {
+    match panic::catch_unwind(AssertUnwindSafe(|| {T::from_bytes(bytes)})) {
+        Ok(value) => Ok(value),
+        Err(_) => Err(io::Error::new(io::ErrorKind::InvalidData, "corrupt data"))
+    }
+}
+
+// magic then SAVE_VERSION, the rest of the bytes if both match
+pub fn check_header<'a>(bytes:&'a [u8], magic:&[u8 ; 4], path:&Path) -> io::Result<&'a [u8]> {
+    if bytes.len() < 8 || &bytes[0..4] != magic {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} is not a {} file", path.display(), String::from_utf8_lossy(magic))));
+    }
+    let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+    if version != SAVE_VERSION {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} was written by version {} of the save format, this build reads version {}", path.display(), version, SAVE_VERSION)));
+    }
+    Ok(&bytes[8..])
+}
+
+fn with_header(magic:&[u8 ; 4], capacity:usize) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(8 + capacity);
+    bytes.extend_from_slice(magic);
+    bytes.extend_from_slice(&SAVE_VERSION.to_le_bytes());
+    bytes
+}
+
+pub struct RegionStorage {
+    folder:PathBuf,
+    // which chunks exist on disk, so that load on demand doesn't touch the disk for chunks that were never saved
+    saved_chunks:HashMap<RegionPos, HashSet<WorldChunkPos>>,
+}
+
+impl RegionStorage {
+    pub fn open(folder:PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&folder)?;
+        let mut saved_chunks = HashMap::with_capacity(16);
+        for entry in fs::read_dir(&folder)? {
+            let path = entry?.path();
+            match parse_region_file_name(&path) {
+                // a damaged region only loses its own chunks, they get generated again
+                Some(region) => match read_region_index(&path) {
+                    Ok(chunks) => {saved_chunks.insert(region, chunks);},
+                    Err(error) => println!("Skipping region file {} : {}", path.display(), error)
+                },
+                None => ()
+            }
+        }
+        println!("Opened world save at {} with {} regions", folder.display(), saved_chunks.len());
+        Ok(Self { folder, saved_chunks })
+    }
+    fn region_path(&self, region:RegionPos) -> PathBuf {
+        self.folder.join(format!("region_{}_{}_{}.bin", region.x, region.y, region.z))
+    }
+    pub fn is_chunk_saved(&self, chunk_pos:WorldChunkPos) -> bool {
+        self.saved_chunks.get(&get_region_pos(chunk_pos)).is_some_and(|chunks| {chunks.contains(&chunk_pos)})
+    }
+    pub fn load_generator<G:FromBytes>(&self) -> io::Result<Option<G>> {
+        let path = self.folder.join(GENERATOR_FILE);
+        if path.exists() {
+            let bytes = fs::read(&path)?;
+            match decode(check_header(&bytes, GENERATOR_MAGIC, &path)?) {
+                Ok(generator) => Ok(Some(generator)),
+                Err(error) => Err(io::Error::new(error.kind(), format!("{} : {}", path.display(), error)))
+            }
+        }
+        else {
+            Ok(None)
+        }
+    }
+    pub fn save_generator<G:ToBytes>(&self, generator:&G) -> io::Result<()> {
+        let generator_bytes = encode(generator);
+        let mut bytes = with_header(GENERATOR_MAGIC, generator_bytes.len());
+        bytes.extend_from_slice(&generator_bytes);
+        write_atomically(&self.folder.join(GENERATOR_FILE), &bytes)
+    }
+    // Loads every saved chunk among the asked positions, reading each region file at most once
+    pub fn load_chunks<V:Voxel>(&self, positions:&[WorldChunkPos]) -> io::Result<Vec<(WorldChunkPos, MapChunk<V>)>> {
+        let mut by_region:HashMap<RegionPos, Vec<WorldChunkPos>> = HashMap::with_capacity(4);
+        for pos in positions {
+            if self.is_chunk_saved(*pos) {
+                by_region.entry(get_region_pos(*pos)).or_insert_with(|| {Vec::with_capacity(8)}).push(*pos);
+            }
+        }
+        let mut out = Vec::with_capacity(positions.len());
+        for (region, wanted) in by_region {
+            let path = self.region_path(region);
+            // a damaged region only loses its own chunks, like in open
+            let mut region_chunks = match read_region_file::<CompressedChunk<V>>(&path, |bytes| {decode(bytes)}) {
+                Ok(region_chunks) => region_chunks,
+                Err(error) => {
+                    println!("Skipping region file {} : {}", path.display(), error);
+                    continue;
+                }
+            };
+            for pos in wanted {
+                match region_chunks.remove(&pos) {
+                    Some(chunk) => out.push((pos, chunk.decompress())),
+                    None => ()
+                }
+            }
+        }
+        Ok(out)
+    }
+    // Writes every chunk edited since the last save, and the ones that were never saved as the road can't generate them again,
+    // merged with what is already in their region files so that chunks not currently in memory are kept
+    pub fn save_dirty<V:Voxel, G:Generator<V>>(&mut self, world:&mut GameMap<V, G>) -> io::Result<usize> {
+        let mut dirty = world.take_dirty_chunks();
+        dirty.extend(world.get_all_chunk_pos().into_iter().filter(|pos| {!self.is_chunk_saved(*pos)}));
+        if dirty.is_empty() {
+            return Ok(0);
+        }
+        let mut by_region:HashMap<RegionPos, Vec<WorldChunkPos>> = HashMap::with_capacity(4);
+        for pos in dirty {
+            by_region.entry(get_region_pos(pos)).or_insert_with(|| {Vec::with_capacity(8)}).push(pos);
+        }
+        let mut saved = 0;
+        for (region, chunks) in by_region {
+            let path = self.region_path(region);
+            let mut region_chunks = if path.exists() {
+                match read_region_file::<Vec<u8>>(&path, |bytes| {Ok(bytes.to_vec())}) {
+                    Ok(region_chunks) => region_chunks,
+                    Err(error) => {
+                        println!("Replacing region file {} : {}", path.display(), error);
+                        HashMap::with_capacity(chunks.len())
+                    }
+                }
+            }
+            else {
+                HashMap::with_capacity(chunks.len())
+            };
+            for pos in chunks {
+                match world.get_chunk_at(pos) {
+                    Some(chunk) => {
+                        region_chunks.insert(pos, encode(&chunk.compress(world.get_chunk_dims())));
+                        saved += 1;
+                    },
+                    None => ()
+                }
+                // edits expanded it, it's compacted again until the next one
+                world.compact_chunk(pos);
+            }
+            write_region_file(&path, &region_chunks)?;
+            self.saved_chunks.insert(region, region_chunks.keys().cloned().collect());
+        }
+        self.save_generator(&world.generator)?;
+        Ok(saved)
+    }
+}
+
+fn parse_region_file_name(path:&Path) -> Option<RegionPos> {
+    let name = path.file_name()?.to_str()?;
+    let coords = name.strip_prefix("region_")?.strip_suffix(".bin")?;
+    let mut split = coords.split('_');
+    let x = split.next()?.parse().ok()?;
+    let y = split.next()?.parse().ok()?;
+    let z = split.next()?.parse().ok()?;
+    Some(Vec3D::new(x, y, z))
+}
+
+fn read_u32(bytes:&[u8], at:&mut usize) -> io::Result<u32> {
+    match bytes.get(*at..*at + 4) {
+        Some(slice) => {
+            *at += 4;
+            Ok(u32::from_le_bytes(slice.try_into().unwrap()))
+        },
+        None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "region file is truncated"))
+    }
+}
+
+// positions of the chunks in a region file, skipping over their bytes instead of reading them
+fn read_region_index(path:&Path) -> io::Result<HashSet<WorldChunkPos>> {
+    let mut file = BufReader::new(File::open(path)?);
+    let file_length = file.get_ref().metadata()?.len();
+    let mut header = [0 ; 12];
+    if file.read_exact(&mut header).is_err() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} is not a HRG1 file", path.display())));
+    }
+    check_header(&header, REGION_MAGIC, path)?;
+    let count = u32::from_le_bytes(header[8..12].try_into().unwrap()) as usize;
+    let mut chunks = HashSet::with_capacity(count);
+    let mut entry = [0 ; 16];
+    for _ in 0..count {
+        file.read_exact(&mut entry)?;
+        let mut at = 0;
+        let x = read_u32(&entry, &mut at)? as i32;
+        let y = read_u32(&entry, &mut at)? as i32;
+        let z = read_u32(&entry, &mut at)? as i32;
+        let length = read_u32(&entry, &mut at)? as i64;
+        if file.stream_position()? + length as u64 > file_length {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("{} is truncated", path.display())));
+        }
+        file.seek_relative(length)?;
+        chunks.insert(Vec3D::new(x, y, z));
+    }
+    Ok(chunks)
+}
+
+fn read_region_file<T>(path:&Path, mut convert:impl FnMut(&[u8]) -> io::Result<T>) -> io::Result<HashMap<WorldChunkPos, T>> {
+    let file_bytes = fs::read(path)?;
+    let bytes = check_header(&file_bytes, REGION_MAGIC, path)?;
+    let mut at = 0;
+    let count = read_u32(&bytes, &mut at)? as usize;
+    let mut chunks = HashMap::with_capacity(count);
+    for _ in 0..count {
+        let x = read_u32(&bytes, &mut at)? as i32;
+        let y = read_u32(&bytes, &mut at)? as i32;
+        let z = read_u32(&bytes, &mut at)? as i32;
+        let length = read_u32(&bytes, &mut at)? as usize;
+        match bytes.get(at..at + length) {
+            Some(chunk_bytes) => {
+                match convert(chunk_bytes) {
+                    Ok(chunk) => {chunks.insert(Vec3D::new(x, y, z), chunk);},
+                    Err(error) => return Err(io::Error::new(error.kind(), format!("chunk ({}, {}, {}) of {} : {}", x, y, z, path.display(), error)))
+                }
+                at += length;
+            },
+            None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("{} is truncated", path.display())))
+        }
+    }
+    Ok(chunks)
+}
+
+fn write_region_file(path:&Path, chunks:&HashMap<WorldChunkPos, Vec<u8>>) -> io::Result<()> {
+    let mut bytes = with_header(REGION_MAGIC, 4 + chunks.values().map(|chunk| {chunk.len() + 16}).sum::<usize>());
+    bytes.extend_from_slice(&(chunks.len() as u32).to_le_bytes());
+    for (pos, chunk) in chunks {
+        bytes.extend_from_slice(&pos.x.to_le_bytes());
+        bytes.extend_from_slice(&pos.y.to_le_bytes());
+        bytes.extend_from_slice(&pos.z.to_le_bytes());
+        bytes.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(chunk);
+    }
+    write_atomically(path, &bytes)
+}
+
+// write to a temporary file then rename it so a crash mid-save never leaves a half written region
+pub fn write_atomically(path:&Path, bytes:&[u8]) -> io::Result<()> {
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, bytes)?;
+    fs::rename(temp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io, path::PathBuf};
+
+    use hord3::horde::geometry::vec3d::{Vec3D, Vec3Df};
+
+    use crate::{game_engine::CoolVoxel, game_map::{ChunkDims, GameMap, Voxel, VoxelLight, WorldChunkPos, road::{Road, RoadVoxels}}, voxel_registry::{VoxelRegistry, voxel_registry}};
+
+    use super::{GENERATOR_FILE, RegionStorage, SAVE_VERSION, encode};
+
+    fn temp_folder(name:&str) -> PathBuf {
+        std::env::temp_dir().join(format!("hord3_storage_{}_{}", name, fastrand::u64(..)))
+    }
+
+    // 2x2x1 chunks of rock under air, with storage in folder
+    fn saved_world(folder:&PathBuf) -> (GameMap<CoolVoxel, Road>, RegionStorage) {
+        // other tests may have loaded it already
+        let _ = VoxelRegistry::load_global(PathBuf::from("voxels.json"));
+        let registry = voxel_registry();
+        let mut world = GameMap::new(16, ChunkDims::new(8, 8, 8), registry.get_voxel_types(), (40,40,40), 1, Road::new(WorldChunkPos::new(0,0,1), Vec3Df::new(1.0, 0.0, 0.0), 3, RoadVoxels::from_registry(registry)));
+        let (rock, air) = (registry.id("rock"), registry.id("air"));
+        world.generate_chunks(Vec3D::new(0, 0, 0), Vec3D::new(2, 2, 1), &mut |pos| {
+            CoolVoxel::new(if pos.z < 4 {rock} else {air}, 0, VoxelLight::zero_light(), None)
+        });
+        world.set_saving(true);
+        (world, RegionStorage::open(folder.clone()).unwrap())
+    }
+
+    #[test]
+    fn saved_chunks_load_back_and_merge() {
+        let folder = temp_folder("merge");
+        let (mut world, mut storage) = saved_world(&folder);
+        let positions = world.get_all_chunk_pos();
+        assert_eq!(storage.save_dirty(&mut world).unwrap(), positions.len());
+        // nothing changed since
+        assert_eq!(storage.save_dirty(&mut world).unwrap(), 0);
+
+        let storage = RegionStorage::open(folder.clone()).unwrap();
+        let dims = world.get_chunk_dims();
+        let loaded = storage.load_chunks::<CoolVoxel>(&positions).unwrap();
+        assert_eq!(loaded.len(), positions.len());
+        for (c_pos, chunk) in &loaded {
+            assert!(encode(&chunk.compress(dims)) == encode(&world.get_chunk_at(*c_pos).unwrap().compress(dims)));
+        }
+
+        // a map holding a single chunk saves its edit without dropping the other chunks of the region
+        let (mut edited, mut storage) = saved_world(&folder);
+        edited.unload_chunks(&positions);
+        let (c_pos, chunk) = loaded.into_iter().next().unwrap();
+        edited.insert_loaded_chunk(c_pos, chunk);
+        let edit_pos = c_pos * 8 + Vec3D::new(1, 1, 1);
+        edited.replace_voxel_at(edit_pos, CoolVoxel::new(voxel_registry().id("sand"), 0, VoxelLight::zero_light(), None));
+        assert_eq!(storage.save_dirty(&mut edited).unwrap(), 1);
+
+        let storage = RegionStorage::open(folder.clone()).unwrap();
+        assert!(positions.iter().all(|c_pos| {storage.is_chunk_saved(*c_pos)}));
+        let (mut reloaded, _) = saved_world(&folder);
+        reloaded.unload_chunks(&positions);
+        for (c_pos, chunk) in storage.load_chunks::<CoolVoxel>(&positions).unwrap() {
+            reloaded.insert_loaded_chunk(c_pos, chunk);
+        }
+        assert_eq!(reloaded.get_voxel_at(edit_pos).unwrap().voxel_id(), voxel_registry().id("sand") as usize);
+        assert_eq!(reloaded.get_voxel_at(edit_pos + Vec3D::new(1, 0, 0)).unwrap().voxel_id(), voxel_registry().id("rock") as usize);
+        fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn damaged_files_are_skipped() {
+        let folder = temp_folder("damaged");
+        let (mut world, mut storage) = saved_world(&folder);
+        storage.save_dirty(&mut world).unwrap();
+        let region_path = folder.join("region_0_0_0.bin");
+        let bytes = fs::read(&region_path).unwrap();
+        // cut in the middle of a chunk, and a file that isn't a region at all
+        fs::write(folder.join("region_1_0_0.bin"), &bytes[..bytes.len() - 10]).unwrap();
+        fs::write(folder.join("region_2_0_0.bin"), b"not a region").unwrap();
+        // an older file without the version
+        let mut unversioned = bytes[0..4].to_vec();
+        unversioned.extend_from_slice(&bytes[8..]);
+        fs::write(folder.join("region_3_0_0.bin"), unversioned).unwrap();
+        // the right header, but bytes no road can be read from
+        let mut generator = b"HGN1".to_vec();
+        generator.extend_from_slice(&SAVE_VERSION.to_le_bytes());
+        generator.extend_from_slice(&[1, 2, 3]);
+        fs::write(folder.join(GENERATOR_FILE), generator).unwrap();
+
+        let storage = RegionStorage::open(folder.clone()).unwrap();
+        assert!(storage.is_chunk_saved(WorldChunkPos::new(0, 0, 0)));
+        assert!(!storage.is_chunk_saved(WorldChunkPos::new(8, 0, 0)));
+        assert!(!storage.is_chunk_saved(WorldChunkPos::new(24, 0, 0)));
+        assert_eq!(storage.load_generator::<Road>().err().map(|error| {error.kind()}), Some(io::ErrorKind::InvalidData));
+        fs::remove_dir_all(folder).unwrap();
+    }
+}
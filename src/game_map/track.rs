@@ -0,0 +1,184 @@
+use std::{fs, io, path::Path};
+
+use hord3::horde::geometry::vec3d::Vec3Df;
+use serde::{Deserialize, Serialize};
+use to_from_bytes_derive::{FromBytes, ToBytes};
+
+use crate::game_map::{road::{DEFAULT_ROAD_WIDTH, Road, RoadBiome, RoadSection, RoadVoxels}, storage::{decode, encode, write_atomically}};
+
+// Finite roads saved to be raced again or shared, see Road::into_closed_track and Road::from_sections
+// files ending in .json are hand-authored tracks (see TrackFile and tracks/example.json), others are binary :
+// magic "HTR1", then the Track bytes
+const TRACK_MAGIC:&[u8 ; 4] = b"HTR1";
+// limits of hand-authored sections, in voxels and degrees
+const MIN_WIDTH:f32 = 4.0;
+const MAX_WIDTH:f32 = 32.0;
+const MAX_BANKING:f32 = 45.0;
+
+#[derive(Clone, ToBytes, FromBytes, PartialEq, Debug)]
+pub struct Track {
+    pub name:String,
+    // every segment planned, none of them built
+    pub road:Road,
+}
+
+impl Track {
+    pub fn save(&self, path:&Path) -> io::Result<()> {
+        if is_json(path) {
+            let text = serde_json::to_string_pretty(&TrackFile::from_road(self.name.clone(), &self.road)).unwrap();
+            return write_atomically(path, text.as_bytes());
+        }
+        let mut bytes = Vec::with_capacity(1024);
+        bytes.extend_from_slice(TRACK_MAGIC);
+        bytes.extend_from_slice(&encode(self));
+        write_atomically(path, &bytes)
+    }
+    // voxel ids of the local registry, chunk dimensions of the world the road goes in
+    pub fn load(path:&Path, voxels:RoadVoxels, chunk_dims:&Vec3Df) -> io::Result<Self> {
+        if is_json(path) {
+            let file:TrackFile = match serde_json::from_str(&fs::read_to_string(path)?) {
+                Ok(file) => file,
+                Err(error) => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("could not parse track file {} : {}", path.display(), error)))
+            };
+            return match file.to_road(voxels, chunk_dims) {
+                Ok(road) => Ok(Self { name:file.name, road }),
+                Err(reason) => Err(io::Error::new(io::ErrorKind::InvalidData, format!("invalid track file {} : {}", path.display(), reason)))
+            };
+        }
+        let bytes = fs::read(path)?;
+        if bytes.len() < 4 || &bytes[0..4] != TRACK_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} is not a track file", path.display())));
+        }
+        let track:Self = decode(&bytes[4..])?;
+        if !track.road.is_finite() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} has no finite road", path.display())));
+        }
+        Ok(Self { name:track.name, road:track.road.with_voxels(voxels) })
+    }
+}
+
+// One control point of a hand-authored track, the road goes straight from it to the next one
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TrackPoint {
+    // in voxels
+    pub position:(f32, f32, f32),
+    // desert, plains, city or ravine
+    pub biome:String,
+    // in voxels between the curbs
+    #[serde(default = "default_width")]
+    pub width:f32,
+    // in degrees, positive lowers the right side of the road
+    #[serde(default)]
+    pub banking:f32,
+}
+
+fn default_width() -> f32 {
+    DEFAULT_ROAD_WIDTH
+}
+
+// Hand-authored track, also what any road is exported to
+// a closed track comes back from its last point to its first one, otherwise the last point is where the road ends
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TrackFile {
+    pub name:String,
+    #[serde(default)]
+    pub seed:u64,
+    pub closed:bool,
+    pub points:Vec<TrackPoint>,
+}
+
+impl TrackFile {
+    // one point per segment, segments made from a long section were already split so they load back as they are
+    // width and banking are clamped to what to_road accepts
+    pub fn from_road(name:String, road:&Road) -> Self {
+        let points = road.get_sections().into_iter().map(|section| {
+            TrackPoint { position:(section.start.x, section.start.y, section.start.z), biome:section.biome.get_name().to_string(), width:section.width.clamp(MIN_WIDTH, MAX_WIDTH), banking:section.banking.to_degrees().clamp(-MAX_BANKING, MAX_BANKING) }
+        }).collect();
+        Self { name, seed:road.get_seed(), closed:road.is_closed(), points }
+    }
+    // one section per point, see Road::from_sections for how many points a track needs
+    pub fn to_road(&self, voxels:RoadVoxels, chunk_dims:&Vec3Df) -> Result<Road, String> {
+        let mut sections = Vec::with_capacity(self.points.len());
+        for (i, point) in self.points.iter().enumerate() {
+            let biome = match RoadBiome::from_name(&point.biome) {
+                Some(biome) => biome,
+                None => return Err(format!("unknown biome \"{}\" at point {}, expected desert, plains, city or ravine", point.biome, i))
+            };
+            if !(MIN_WIDTH..=MAX_WIDTH).contains(&point.width) {
+                return Err(format!("width of point {} must be between {} and {} voxels, got {}", i, MIN_WIDTH, MAX_WIDTH, point.width));
+            }
+            if !(-MAX_BANKING..=MAX_BANKING).contains(&point.banking) {
+                return Err(format!("banking of point {} must be between -{} and {} degrees, got {}", i, MAX_BANKING, MAX_BANKING, point.banking));
+            }
+            let (x, y, z) = point.position;
+            sections.push(RoadSection { start:Vec3Df::new(x, y, z), biome, width:point.width, banking:point.banking.to_radians() });
+        }
+        Road::from_sections(&sections, self.closed, self.seed, voxels, chunk_dims)
+    }
+}
+
+fn is_json(path:&Path) -> bool {
+    path.extension().is_some_and(|extension| {extension == "json"})
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use hord3::horde::geometry::vec3d::Vec3Df;
+
+    use crate::{game_map::{WorldChunkPos, road::{Road, RoadBiome, RoadSection, RoadVoxels}, track::{MAX_BANKING, MAX_WIDTH, TrackFile}}, voxel_registry::{VoxelRegistry, voxel_registry}};
+
+    fn voxels() -> RoadVoxels {
+        // other tests may have loaded it already
+        let _ = VoxelRegistry::load_global(PathBuf::from("voxels.json"));
+        RoadVoxels::from_registry(voxel_registry())
+    }
+
+    // through the json text, like a saved track
+    fn exported_and_loaded(road:&Road, dims:&Vec3Df) -> Road {
+        let text = serde_json::to_string(&TrackFile::from_road(format!("Test"), road)).unwrap();
+        let file:TrackFile = serde_json::from_str(&text).unwrap();
+        file.to_road(voxels(), dims).unwrap()
+    }
+
+    fn assert_same_sections(road:&Road, loaded:&Road) {
+        assert_eq!(road.segment_count(), loaded.segment_count());
+        assert_eq!(road.is_closed(), loaded.is_closed());
+        for (ours, theirs) in road.get_sections().iter().zip(loaded.get_sections()) {
+            assert!(ours.start.dist(&theirs.start) < 0.01, "sections start at {:?} and {:?}", ours.start, theirs.start);
+            assert_eq!(ours.biome, theirs.biome);
+            assert_eq!(ours.width, theirs.width);
+            assert!((ours.banking - theirs.banking).abs() < 0.0001);
+        }
+    }
+
+    #[test]
+    fn closed_track_exports_and_loads_back() {
+        let dims = Vec3Df::new(8.0, 8.0, 8.0);
+        let road = Road::new(WorldChunkPos::new(0,0,1), Vec3Df::new(1.0, 0.0, 0.0), 42, voxels()).into_closed_track(24, &dims);
+        assert_same_sections(&road, &exported_and_loaded(&road, &dims));
+    }
+
+    #[test]
+    fn split_sections_export_and_load_back() {
+        let dims = Vec3Df::new(8.0, 8.0, 8.0);
+        // far apart enough for every side to be split in several segments
+        let sections:Vec<RoadSection> = [(0.0, 0.0), (160.0, 0.0), (160.0, 160.0)].into_iter().map(|(x, y)| {RoadSection { start:Vec3Df::new(x, y, 8.0), biome:RoadBiome::Plains, width:10.0, banking:0.1 }}).collect();
+        let road = Road::from_sections(&sections, true, 7, voxels(), &dims).unwrap();
+        assert!(road.segment_count() > sections.len());
+        assert_same_sections(&road, &exported_and_loaded(&road, &dims));
+    }
+
+    #[test]
+    fn out_of_range_sections_are_clamped() {
+        let dims = Vec3Df::new(8.0, 8.0, 8.0);
+        let sections:Vec<RoadSection> = [0.0, 32.0].into_iter().map(|x| {RoadSection { start:Vec3Df::new(x, 0.0, 8.0), biome:RoadBiome::City, width:100.0, banking:-1.5 }}).collect();
+        let road = Road::from_sections(&sections, false, 0, voxels(), &dims).unwrap();
+        let file = TrackFile::from_road(format!("Test"), &road);
+        assert!(file.points.iter().all(|point| {point.width == MAX_WIDTH && point.banking == -MAX_BANKING}));
+        assert!(file.to_road(voxels(), &dims).is_ok());
+    }
+}
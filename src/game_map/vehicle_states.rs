@@ -0,0 +1,16 @@
+use to_from_bytes_derive::{FromBytes, ToBytes};
+
+use crate::vehicle::{locomotion::Locomotion, position::VehiclePosition};
+
+// Authoritative state of a player's vehicle, sent by the server every tick to correct client side prediction
+// VehiclePosEvents don't say which tick they come from, so these carry the client tick they match :
+// the tick of the latest input the server got from that player, plus the ticks that went by on the server since
+// server_tick is the server tick they were taken at, which lines the server's ticks up with the client's
+#[derive(Clone, ToBytes, FromBytes, PartialEq)]
+pub struct VehicleState {
+    pub vehicle:usize,
+    pub input_tick:usize,
+    pub server_tick:usize,
+    pub position:VehiclePosition,
+    pub locomotion:Locomotion,
+}
@@ -0,0 +1,172 @@
+use std::sync::LazyLock;
+
+use hord3::horde::geometry::vec3d::{Vec3D, Vec3Df};
+use serde::{Deserialize, Serialize};
+use to_from_bytes_derive::{FromBytes, ToBytes};
+
+use super::{TRIS_INDICES_UVS, EXPLORATION, orient_vector};
+
+// Custom voxel models : shapes other than the full cube (slopes, slabs, ramps, stairs... see voxels.json),
+// described as a height field over the underside of the voxel
+// the underside is split in resolution x resolution cells, each with the height of its 4 corners (x-y-, x+y-, x-y+, x+y+)
+// going from 0 (underside) to 1 (top side), the top of a cell being 2 triangles split along its x-y- / x+y+ diagonal
+// Meshing (tops, walls between cells, sides and underside) and collision both come from these heights,
+// turned with the orientation of the voxel like full cubes
+#[derive(Clone, ToBytes, FromBytes, Serialize, Deserialize, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct CustomModel {
+    pub name:String,
+    pub resolution:usize,
+    pub corner_heights:Vec<(f32, f32, f32, f32)>,
+}
+
+// triangle of a custom model in model space (0 to 1 on every axis), side is the side of the voxel it lies on if any
+#[derive(Clone, Debug)]
+pub struct ModelTriangle {
+    pub points:[Vec3Df ; 3],
+    pub side:Option<usize>,
+}
+
+// sign of the normal of the cube faces of the mesher compared to their direction, so custom models are wound the same way
+static WINDING:LazyLock<f32> = LazyLock::new(|| {
+    let face = TRIS_INDICES_UVS.0[0];
+    let indices = TRIS_INDICES_UVS.1;
+    let normal = (face[indices[1]] - face[indices[0]]).cross(&(face[indices[2]] - face[indices[0]]));
+    normal.dot(&Vec3Df::new(EXPLORATION[0].x as f32, EXPLORATION[0].y as f32, EXPLORATION[0].z as f32)).signum()
+});
+
+impl CustomModel {
+    fn cell(&self, x:usize, y:usize) -> (f32, f32, f32, f32) {
+        self.corner_heights[x + y * self.resolution]
+    }
+    fn cell_at(&self, x:f32, y:f32) -> ((f32, f32, f32, f32), f32, f32) {
+        let res = self.resolution as f32;
+        let (cell_x, cell_y) = (((x * res) as usize).min(self.resolution - 1), ((y * res) as usize).min(self.resolution - 1));
+        (self.cell(cell_x, cell_y), (x * res - cell_x as f32).clamp(0.0, 1.0), (y * res - cell_y as f32).clamp(0.0, 1.0))
+    }
+    // height of the top of the model above (x, y) of the underside, in model space
+    pub fn height_at(&self, x:f32, y:f32) -> f32 {
+        let ((h00, h10, h01, h11), fx, fy) = self.cell_at(x, y);
+        if fx >= fy {
+            h00 + (h10 - h00) * fx + (h11 - h10) * fy
+        }
+        else {
+            h00 + (h01 - h00) * fy + (h11 - h01) * fx
+        }
+    }
+    // normal of the top of the model above (x, y), in model space
+    pub fn normal_at(&self, x:f32, y:f32) -> Vec3Df {
+        let ((h00, h10, h01, h11), fx, fy) = self.cell_at(x, y);
+        let res = self.resolution as f32;
+        let (dx, dy) = if fx >= fy {
+            (h10 - h00, h11 - h10)
+        }
+        else {
+            (h11 - h01, h01 - h00)
+        };
+        Vec3Df::new(-dx * res, -dy * res, 1.0).normalise()
+    }
+    pub fn is_solid_at(&self, model_pos:Vec3Df) -> bool {
+        model_pos.z <= self.height_at(model_pos.x, model_pos.y)
+    }
+    // bit i is set if side i of the model (in EXPLORATION order) doesn't cover the whole side of the voxel
+    // the underside is always full
+    pub fn sides_empty(&self) -> u8 {
+        let n = self.resolution;
+        let mut empty = 0;
+        if !self.corner_heights.iter().all(|(h00, h10, h01, h11)| {*h00 >= 1.0 && *h10 >= 1.0 && *h01 >= 1.0 && *h11 >= 1.0}) {
+            empty |= 1 << 0;
+        }
+        if !(0..n).all(|y| {self.cell(n - 1, y).1 >= 1.0 && self.cell(n - 1, y).3 >= 1.0}) {
+            empty |= 1 << 1;
+        }
+        if !(0..n).all(|y| {self.cell(0, y).0 >= 1.0 && self.cell(0, y).2 >= 1.0}) {
+            empty |= 1 << 3;
+        }
+        if !(0..n).all(|x| {self.cell(x, 0).0 >= 1.0 && self.cell(x, 0).1 >= 1.0}) {
+            empty |= 1 << 4;
+        }
+        if !(0..n).all(|x| {self.cell(x, n - 1).2 >= 1.0 && self.cell(x, n - 1).3 >= 1.0}) {
+            empty |= 1 << 5;
+        }
+        empty
+    }
+    // every triangle of the model, wound like the faces of full cubes
+    pub fn triangles(&self) -> Vec<ModelTriangle> {
+        let n = self.resolution;
+        let step = 1.0 / n as f32;
+        let mut triangles = Vec::with_capacity(n * n * 8);
+        for y in 0..n {
+            for x in 0..n {
+                let (x0, x1, y0, y1) = (x as f32 * step, (x + 1) as f32 * step, y as f32 * step, (y + 1) as f32 * step);
+                let (h00, h10, h01, h11) = self.cell(x, y);
+                let top_side = if h00 >= 1.0 && h10 >= 1.0 && h01 >= 1.0 && h11 >= 1.0 {Some(0)} else {None};
+                let up = Vec3Df::new(0.0, 0.0, 1.0);
+                add_triangle(&mut triangles, [Vec3Df::new(x0, y0, h00), Vec3Df::new(x1, y0, h10), Vec3Df::new(x1, y1, h11)], up, top_side);
+                add_triangle(&mut triangles, [Vec3Df::new(x0, y0, h00), Vec3Df::new(x1, y1, h11), Vec3Df::new(x0, y1, h01)], up, top_side);
+                add_quad(&mut triangles, [Vec3Df::new(x0, y0, 0.0), Vec3Df::new(x1, y0, 0.0), Vec3Df::new(x1, y1, 0.0), Vec3Df::new(x0, y1, 0.0)], Vec3Df::new(0.0, 0.0, -1.0), Some(2));
+
+                // walls on the x+ and y+ edges of the cell, against the next cell or the side of the voxel
+                let (next_x, side_x) = if x + 1 < n {(self.cell(x + 1, y).0, self.cell(x + 1, y).2)} else {(0.0, 0.0)};
+                add_wall(&mut triangles, (Vec3Df::new(x1, y0, 0.0), Vec3Df::new(x1, y1, 0.0)), (h10, h11), (next_x, side_x), Vec3Df::new(1.0, 0.0, 0.0), if x + 1 == n {Some(1)} else {None});
+                let (next_y, side_y) = if y + 1 < n {(self.cell(x, y + 1).0, self.cell(x, y + 1).1)} else {(0.0, 0.0)};
+                add_wall(&mut triangles, (Vec3Df::new(x0, y1, 0.0), Vec3Df::new(x1, y1, 0.0)), (h01, h11), (next_y, side_y), Vec3Df::new(0.0, 1.0, 0.0), if y + 1 == n {Some(5)} else {None});
+                if x == 0 {
+                    add_wall(&mut triangles, (Vec3Df::new(x0, y0, 0.0), Vec3Df::new(x0, y1, 0.0)), (h00, h01), (0.0, 0.0), Vec3Df::new(-1.0, 0.0, 0.0), Some(3));
+                }
+                if y == 0 {
+                    add_wall(&mut triangles, (Vec3Df::new(x0, y0, 0.0), Vec3Df::new(x1, y0, 0.0)), (h00, h10), (0.0, 0.0), Vec3Df::new(0.0, -1.0, 0.0), Some(4));
+                }
+            }
+        }
+        triangles
+    }
+}
+
+// wall standing on the edge (start, end), between the heights of the cell and the ones of what is on the other side
+// facing outwards if the cell is the highest, inwards otherwise
+fn add_wall(triangles:&mut Vec<ModelTriangle>, (start, end):(Vec3Df, Vec3Df), (cell_start, cell_end):(f32, f32), (other_start, other_end):(f32, f32), outwards:Vec3Df, side:Option<usize>) {
+    let (low_start, high_start) = (cell_start.min(other_start), cell_start.max(other_start));
+    let (low_end, high_end) = (cell_end.min(other_end), cell_end.max(other_end));
+    let facing = if cell_start + cell_end >= other_start + other_end {outwards} else {outwards * -1.0};
+    let up = Vec3Df::new(0.0, 0.0, 1.0);
+    add_quad(triangles, [start + up * low_start, end + up * low_end, end + up * high_end, start + up * high_start], facing, side);
+}
+
+fn add_quad(triangles:&mut Vec<ModelTriangle>, [p1, p2, p3, p4]:[Vec3Df ; 4], facing:Vec3Df, side:Option<usize>) {
+    add_triangle(triangles, [p1, p2, p3], facing, side);
+    add_triangle(triangles, [p1, p3, p4], facing, side);
+}
+
+// flat triangles (walls between cells of the same height) are left out
+fn add_triangle(triangles:&mut Vec<ModelTriangle>, [p1, p2, p3]:[Vec3Df ; 3], facing:Vec3Df, side:Option<usize>) {
+    let normal = (p2 - p1).cross(&(p3 - p1));
+    if normal.norme() < 0.000001 {
+        return
+    }
+    if normal.dot(&facing) * *WINDING >= 0.0 {
+        triangles.push(ModelTriangle { points: [p1, p2, p3], side });
+    }
+    else {
+        triangles.push(ModelTriangle { points: [p1, p3, p2], side });
+    }
+}
+
+// where the x, y and z axis of the model end up once oriented
+fn orientation_axes(orientation:u8) -> [Vec3Df ; 3] {
+    [Vec3D::new(1, 0, 0), Vec3D::new(0, 1, 0), Vec3D::new(0, 0, 1)].map(|axis| {
+        let oriented = orient_vector(axis, orientation);
+        Vec3Df::new(oriented.x as f32, oriented.y as f32, oriented.z as f32)
+    })
+}
+
+// both work on positions relative to the center of the voxel
+pub fn model_to_world(pos:Vec3Df, orientation:u8) -> Vec3Df {
+    let [x_axis, y_axis, z_axis] = orientation_axes(orientation);
+    x_axis * pos.x + y_axis * pos.y + z_axis * pos.z
+}
+
+pub fn world_to_model(pos:Vec3Df, orientation:u8) -> Vec3Df {
+    let [x_axis, y_axis, z_axis] = orientation_axes(orientation);
+    Vec3Df::new(pos.dot(&x_axis), pos.dot(&y_axis), pos.dot(&z_axis))
+}
@@ -16,7 +16,7 @@ use gui_elements::{list_choice::get_list_choice, number_config::get_number_confi
 use hord3::{defaults::{default_frontends::minifb_frontend::MiniFBWindow, default_rendering::vectorinator_binned::{meshes::{Mesh, MeshID, MeshLODS, MeshLODType}, rendering_spaces::ViewportData, shaders::NoOpShader, textures::{argb_to_rgb, rgb_to_argb, TextureSetID}, triangles::{color_u32_to_u8_simd, simd_rgb_to_argb}, Vectorinator}, default_ui::simple_ui::{SimpleUI, UIDimensions, UIElement, UIElementBackground, UIElementContent, UIElementID, UIEvent, UIUnit, UIUserAction, UIVector}}, horde::{frontend::{HordeWindowDimensions, WindowingHandler}, game_engine::{entity::Renderable, world::{WorldComputeHandler, WorldHandler}}, geometry::{plane::EquationPlane, rotation::{Orientation, Rotation}, vec3d::{Vec3D, Vec3Df}}, rendering::{camera::Camera, framebuffer::HordeColorFormat}, scheduler::{HordeScheduler, HordeTaskQueue, HordeTaskSequence, SequencedTask}, sound::{SoundRequest, WaveIdentification, WavePosition, WaveRequest, WaveSink, Waves}}};
 use tile_editor::{get_tile_voxels, TileEditorData};
 
-use crate::{client::client_func, driver::{actions::{Action, ActionKind, ActionSource, ActionTimer, ActionsEvent, ActionsUpdate, StaticGameActions}, director::{llm_director::LLMDirector, Director, DirectorKind, StaticDirector}, planner::StaticPlanner, GameEntityEvent}, game_map::get_voxel_pos, proxima_link::ProximaLink, server::server_func};
+use crate::{client::client_func, config::{parse_launch_args, LaunchMode, USAGE}, driver::{actions::{Action, ActionKind, ActionSource, ActionTimer, ActionsEvent, ActionsUpdate, StaticGameActions}, director::{llm_director::LLMDirector, Director, DirectorKind, StaticDirector}, planner::StaticPlanner, GameEntityEvent}, game_map::get_voxel_pos, proxima_link::ProximaLink, server::server_func, voxel_registry::VoxelRegistry};
 
 pub mod game_map;
 pub mod flat_game_map;
@@ -33,18 +33,31 @@ pub mod proxima_link;
 pub mod client;
 pub mod server;
 pub mod vehicle;
+pub mod config;
+pub mod voxel_registry;
+pub mod replay;
 
 fn main() {
     
     let args:Vec<String> = std::env::args().collect();
-    if args.len() == 2 {
-        match args[1].trim() {
-            "server" => server_func(),
-            "client" => client_func(),
-            _ => client_func(),
+    let (mode, config) = match parse_launch_args(&args[1..]) {
+        Ok(launch) => launch,
+        Err(error) => {
+            eprintln!("{}", error);
+            eprintln!("{}", USAGE);
+            std::process::exit(1);
+        }
+    };
+    match VoxelRegistry::load_global(PathBuf::from(&config.voxel_registry)) {
+        Ok(registry) => println!("[Voxels] Loaded {} voxel types from {} (hash {:016x})", registry.len(), config.voxel_registry, registry.get_hash()),
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
         }
     }
-    else {
-        client_func();
+    match mode {
+        LaunchMode::Server => server_func(config),
+        LaunchMode::Client => client_func(config, None),
+        LaunchMode::Replay(file) => client_func(config, Some(file)),
     }
 }
@@ -0,0 +1,207 @@
+use std::{fs::{self, File}, io::{self, BufWriter, Write}, path::{Path, PathBuf}};
+
+use to_from_bytes_derive::{FromBytes, ToBytes};
+
+use crate::{driver::{Movement, MovementEventVariant, stats::Stats}, game_engine::{CoolGameEngineBase, CoolVoxel}, game_map::{GameMap, GameMapEvent, road::Road, storage::{SAVE_VERSION, check_header, decode, encode}}, vehicle::{locomotion::{LocomotionEquipment, LocomotionUpdate}, position::{VehiclePosUpdate, VehiclePosition}, vehicle_stats::VehicleStats}};
+
+pub mod player;
+
+// Replay file layout (little endian) :
+// magic "HRP1", u32 SAVE_VERSION (the header and ticks hold roads and chunks), u32 byte length, ReplayHeader bytes,
+// then for every recorded tick : u32 byte length, ReplayTick bytes
+// the first tick holds every chunk that existed when recording started
+const REPLAY_MAGIC:&[u8 ; 4] = b"HRP1";
+// in recorded ticks, a crash loses at most that much of the replay
+const FLUSH_INTERVAL:usize = 90;
+
+#[derive(Clone, ToBytes, FromBytes, PartialEq)]
+pub struct ReplayHeader {
+    pub world_seed:u64,
+    pub tickrate:usize,
+    // the road when recording started, what it generates afterwards comes as chunks
+    pub generator:Road,
+}
+
+// Entities are recorded as the component events that put them where they were on that tick, only when they moved
+#[derive(Clone, ToBytes, FromBytes)]
+pub enum ReplayEvent {
+    // first tick an entity was seen, with its id
+    NewDriver(usize, Movement, Stats),
+    NewVehicle(usize, VehiclePosition, VehicleStats, Vec<LocomotionEquipment>),
+    Movement(usize, MovementEventVariant),
+    VehiclePosition(usize, VehiclePosUpdate),
+    Locomotion(usize, LocomotionUpdate),
+    // chunk events applied by the world that tick
+    World(GameMapEvent<CoolVoxel, Road>),
+}
+
+#[derive(Clone, ToBytes, FromBytes)]
+pub struct ReplayTick {
+    pub tick:usize,
+    pub events:Vec<ReplayEvent>,
+}
+
+pub struct ReplayRecorder {
+    path:PathBuf,
+    writer:BufWriter<File>,
+    // what was last recorded of every entity, to only record changes
+    drivers:Vec<Movement>,
+    vehicles:Vec<(VehiclePosition, Vec<LocomotionEquipment>)>,
+    recorded_ticks:usize,
+}
+
+impl ReplayRecorder {
+    // the world keeps the chunk events it applies from now on, for record_tick
+    pub fn create(path:PathBuf, tickrate:usize, world:&mut GameMap<CoolVoxel, Road>) -> io::Result<Self> {
+        if let Some(folder) = path.parent() && !folder.as_os_str().is_empty() {
+            fs::create_dir_all(folder)?;
+        }
+        let mut writer = BufWriter::new(File::create(&path)?);
+        writer.write_all(REPLAY_MAGIC)?;
+        writer.write_all(&SAVE_VERSION.to_le_bytes())?;
+        write_block(&mut writer, &encode(&ReplayHeader { world_seed:world.generator.get_seed(), tickrate, generator:world.generator.clone() }))?;
+        let chunks = world.get_all_chunk_pos();
+        let mut events = Vec::with_capacity(chunks.len());
+        for c_pos in chunks {
+            match world.get_chunk_at(c_pos) {
+                Some(chunk) => events.push(ReplayEvent::World(GameMapEvent::NewChunk(c_pos, chunk.compress(world.get_chunk_dims())))),
+                None => ()
+            }
+        }
+        write_block(&mut writer, &encode(&ReplayTick { tick:0, events }))?;
+        world.set_recording(true);
+        println!("[Replay] Recording to {}", path.display());
+        Ok(Self { path, writer, drivers:Vec::with_capacity(16), vehicles:Vec::with_capacity(16), recorded_ticks:0 })
+    }
+    pub fn record_tick(&mut self, engine:&CoolGameEngineBase, tick:usize) -> io::Result<()> {
+        let recorded_events = engine.world.world.write().unwrap().take_recorded_events();
+        let mut events = Vec::with_capacity(recorded_events.len() + 16);
+        for event in recorded_events {
+            events.push(ReplayEvent::World(event));
+        }
+        {
+            let drivers = engine.entity_1.get_read();
+            for id in 0..drivers.movement.len() {
+                let movement = &drivers.movement[id];
+                if id >= self.drivers.len() {
+                    events.push(ReplayEvent::NewDriver(id, movement.clone(), drivers.stats[id].clone()));
+                    self.drivers.push(movement.clone());
+                }
+                else if self.drivers[id].pos != movement.pos || self.drivers[id].orient != movement.orient {
+                    events.push(ReplayEvent::Movement(id, MovementEventVariant::UpdatePos(movement.pos)));
+                    events.push(ReplayEvent::Movement(id, MovementEventVariant::UpdateOrient(movement.orient)));
+                    self.drivers[id] = movement.clone();
+                }
+            }
+        }
+        {
+            let vehicles = engine.vehicles.get_read();
+            for id in 0..vehicles.position.len() {
+                let position = &vehicles.position[id];
+                let equipment = &vehicles.locomotion[id].equipment;
+                if id >= self.vehicles.len() {
+                    events.push(ReplayEvent::NewVehicle(id, position.clone(), vehicles.stats[id].clone(), equipment.clone()));
+                    self.vehicles.push((position.clone(), equipment.clone()));
+                    continue;
+                }
+                let (last_position, last_equipment) = &mut self.vehicles[id];
+                if last_position.pos != position.pos || last_position.orientation != position.orientation {
+                    events.push(ReplayEvent::VehiclePosition(id, VehiclePosUpdate::UpdateEveryPos(position.pos, position.orientation)));
+                    *last_position = position.clone();
+                }
+                if *last_equipment != *equipment {
+                    events.push(ReplayEvent::Locomotion(id, LocomotionUpdate::UpdateEverything(equipment.clone())));
+                    *last_equipment = equipment.clone();
+                }
+            }
+        }
+        write_block(&mut self.writer, &encode(&ReplayTick { tick, events }))?;
+        self.recorded_ticks += 1;
+        if self.recorded_ticks % FLUSH_INTERVAL == 0 {
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+    pub fn finish(mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        println!("[Replay] Recorded {} ticks to {}", self.recorded_ticks, self.path.display());
+        Ok(())
+    }
+}
+
+// a replay cut short (crash, full disk) or damaged is read up to its last tick that decodes
+pub fn read_replay(path:&Path) -> io::Result<(ReplayHeader, Vec<ReplayTick>)> {
+    let file_bytes = fs::read(path)?;
+    let bytes = check_header(&file_bytes, REPLAY_MAGIC, path)?;
+    let mut at = 0;
+    let header = match read_block(bytes, &mut at) {
+        Some(header_bytes) => decode(header_bytes)?,
+        None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("{} is truncated", path.display())))
+    };
+    let mut ticks = Vec::with_capacity(1024);
+    while at < bytes.len() {
+        match read_block(bytes, &mut at).map(|tick_bytes| {decode(tick_bytes)}) {
+            Some(Ok(tick)) => ticks.push(tick),
+            Some(Err(error)) => {
+                println!("[Replay] {} has a damaged tick ({}), playing the {} before it", path.display(), error, ticks.len());
+                break
+            },
+            None => {
+                println!("[Replay] {} ends in the middle of a tick, playing the {} complete ones", path.display(), ticks.len());
+                break
+            }
+        }
+    }
+    Ok((header, ticks))
+}
+
+fn write_block(writer:&mut impl Write, bytes:&[u8]) -> io::Result<()> {
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(bytes)
+}
+
+fn read_block<'a>(bytes:&'a [u8], at:&mut usize) -> Option<&'a [u8]> {
+    let length = u32::from_le_bytes(bytes.get(*at..*at + 4)?.try_into().unwrap()) as usize;
+    let block = bytes.get(*at + 4..*at + 4 + length)?;
+    *at += 4 + length;
+    Some(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io, path::PathBuf};
+
+    use hord3::horde::geometry::vec3d::Vec3Df;
+
+    use crate::{game_map::{WorldChunkPos, road::{Road, RoadVoxels}, storage::{SAVE_VERSION, encode}}, voxel_registry::{VoxelRegistry, voxel_registry}};
+
+    use super::{REPLAY_MAGIC, ReplayHeader, ReplayTick, read_replay, write_block};
+
+    // a header then every block as written by ReplayRecorder
+    fn replay_bytes(version:u32, blocks:&[Vec<u8>]) -> Vec<u8> {
+        // other tests may have loaded it already
+        let _ = VoxelRegistry::load_global(PathBuf::from("voxels.json"));
+        let generator = Road::new(WorldChunkPos::new(0,0,1), Vec3Df::new(1.0, 0.0, 0.0), 3, RoadVoxels::from_registry(voxel_registry()));
+        let mut bytes = REPLAY_MAGIC.to_vec();
+        bytes.extend_from_slice(&version.to_le_bytes());
+        write_block(&mut bytes, &encode(&ReplayHeader { world_seed:3, tickrate:30, generator })).unwrap();
+        for block in blocks {
+            write_block(&mut bytes, block).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn replay_stops_at_a_damaged_tick() {
+        let path = std::env::temp_dir().join(format!("hord3_replay_{}.hrp", fastrand::u64(..)));
+        let tick = |tick:usize| {encode(&ReplayTick { tick, events:Vec::new() })};
+        fs::write(&path, replay_bytes(SAVE_VERSION, &[tick(0), tick(1), vec![0xff ; 3], tick(3)])).unwrap();
+        let (header, ticks) = read_replay(&path).unwrap();
+        assert_eq!(header.tickrate, 30);
+        assert_eq!(ticks.iter().map(|tick| {tick.tick}).collect::<Vec<usize>>(), vec![0, 1]);
+
+        fs::write(&path, replay_bytes(SAVE_VERSION + 1, &[tick(0)])).unwrap();
+        assert_eq!(read_replay(&path).err().map(|error| {error.kind()}), Some(io::ErrorKind::InvalidData));
+        fs::remove_file(&path).unwrap();
+    }
+}
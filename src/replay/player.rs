@@ -0,0 +1,135 @@
+use std::{io, path::Path, time::Instant};
+
+use hord3::horde::{frontend::interact::Button, game_engine::{entity::ComponentEvent, multiplayer::MustSync, world::WorldEvent}, geometry::vec3d::Vec3D};
+
+use crate::{driver::{Collider, MovementEvent, NewGameEntity, colliders::AABB, director::{Director, DirectorKind}}, game_engine::{CoolGameEngineBase, CoolGameEngineTID, CoolVoxel}, game_input_handler::GameInputHandler, game_map::{GameMap, GameMapEvent, road::Road}, vehicle::{NewVehicleEntity, locomotion::{LocomotionEvent, LocomotionUpdate}, position::VehiclePosEvent}};
+
+use super::{ReplayEvent, ReplayHeader, ReplayTick, read_replay};
+
+const SPEEDS:[f32 ; 7] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0];
+const NORMAL_SPEED:usize = 2;
+const SEEK_SECONDS:usize = 5;
+
+// Plays a recorded session back by applying its events straight to the engine's components and world, the engine itself doesn't tick
+// P pauses, I and K change the speed, J and L seek backwards and forwards, the camera is the usual free camera
+pub struct ReplayPlayer {
+    header:ReplayHeader,
+    ticks:Vec<ReplayTick>,
+    // ticks before that are applied
+    next:usize,
+    paused:bool,
+    speed:usize,
+    // ticks owed since the last frame at the current speed, applied once whole
+    owed:f32,
+    last_frame:Instant,
+}
+
+impl ReplayPlayer {
+    pub fn open(path:&Path) -> io::Result<Self> {
+        let (header, ticks) = read_replay(path)?;
+        println!("[Replay] Loaded {} ticks ({:.1}s) from {}", ticks.len(), ticks.len() as f32 / header.tickrate as f32, path.display());
+        Ok(Self { header, ticks, next:0, paused:false, speed:NORMAL_SPEED, owed:0.0, last_frame:Instant::now() })
+    }
+    pub fn get_tickrate(&self) -> usize {
+        self.header.tickrate
+    }
+    pub fn get_world_seed(&self) -> u64 {
+        self.header.world_seed
+    }
+    pub fn get_generator(&self) -> Road {
+        self.header.generator.clone()
+    }
+    pub fn handle_keys(&mut self, input_handler:&GameInputHandler, engine:&CoolGameEngineBase) {
+        if input_handler.is_newly_pressed(&Button::P) {
+            self.paused = !self.paused;
+            self.print_status();
+        }
+        if input_handler.is_newly_pressed(&Button::I) && self.speed + 1 < SPEEDS.len() {
+            self.speed += 1;
+            self.print_status();
+        }
+        if input_handler.is_newly_pressed(&Button::K) && self.speed > 0 {
+            self.speed -= 1;
+            self.print_status();
+        }
+        let seek = SEEK_SECONDS * self.header.tickrate;
+        if input_handler.is_newly_pressed(&Button::J) {
+            self.seek(self.next.saturating_sub(seek), engine);
+        }
+        if input_handler.is_newly_pressed(&Button::L) {
+            self.seek((self.next + seek).min(self.ticks.len()), engine);
+        }
+    }
+    // call once per frame, applies the ticks that should have played since the last one
+    pub fn advance(&mut self, engine:&CoolGameEngineBase) {
+        let now = Instant::now();
+        if !self.paused {
+            self.owed += now.duration_since(self.last_frame).as_secs_f32() * self.header.tickrate as f32 * SPEEDS[self.speed];
+        }
+        self.last_frame = now;
+        let whole = self.owed.floor();
+        self.owed -= whole;
+        let target = (self.next + whole as usize).min(self.ticks.len());
+        if target > self.next {
+            self.apply_ticks(self.next, target, engine);
+            self.next = target;
+            if self.next == self.ticks.len() {
+                println!("[Replay] End of the replay, J to go back");
+            }
+        }
+    }
+    // updates are absolute, so going back is playing everything again up to the target, on a world without chunks
+    // entities that only appear later stay where they were last seen
+    fn seek(&mut self, target:usize, engine:&CoolGameEngineBase) {
+        if target < self.next {
+            let mut world_write = engine.world.world.write().unwrap();
+            let chunks = world_write.get_all_chunk_pos();
+            world_write.unload_chunks(&chunks);
+            drop(world_write);
+            self.apply_ticks(0, target, engine);
+        }
+        else {
+            self.apply_ticks(self.next, target, engine);
+        }
+        self.next = target;
+        self.owed = 0.0;
+        self.print_status();
+    }
+    fn apply_ticks(&self, from:usize, to:usize, engine:&CoolGameEngineBase) {
+        let mut drivers = engine.entity_1.get_write();
+        let mut vehicles = engine.vehicles.get_write();
+        let mut world_write = engine.world.world.write().unwrap();
+        for tick in &self.ticks[from..to] {
+            for event in tick.events.iter().cloned() {
+                match event {
+                    ReplayEvent::NewDriver(id, movement, stats) => if id < drivers.movement.len() {
+                        drivers.movement[id] = movement;
+                        drivers.stats[id] = stats;
+                    }
+                    else {
+                        let pos = movement.pos;
+                        drivers.new_ent(NewGameEntity::new(movement, stats, Collider{team:0, collider:AABB::new(pos - Vec3D::all_ones() * 0.5, pos + Vec3D::all_ones() * 0.5)}, Director::new(DirectorKind::Nothing, String::from("Replay")), MustSync::No, None));
+                    },
+                    ReplayEvent::NewVehicle(id, position, stats, equipment) => {
+                        if id < vehicles.position.len() {
+                            vehicles.position[id] = position;
+                            vehicles.stats[id] = stats;
+                        }
+                        else {
+                            vehicles.new_ent(NewVehicleEntity::new(position, stats, MustSync::No, None));
+                        }
+                        LocomotionEvent::<CoolGameEngineTID>::new(id, None, LocomotionUpdate::UpdateEverything(equipment)).apply_to_component(&mut vehicles.locomotion);
+                    },
+                    ReplayEvent::Movement(id, variant) => MovementEvent::<CoolGameEngineTID>::new(id, None, variant).apply_to_component(&mut drivers.movement),
+                    ReplayEvent::VehiclePosition(id, update) => VehiclePosEvent::<CoolGameEngineTID>::new(id, None, update).apply_to_component(&mut vehicles.position),
+                    ReplayEvent::Locomotion(id, update) => LocomotionEvent::<CoolGameEngineTID>::new(id, None, update).apply_to_component(&mut vehicles.locomotion),
+                    ReplayEvent::World(event) => <GameMapEvent<CoolVoxel, Road> as WorldEvent<GameMap<CoolVoxel, Road>, CoolGameEngineTID>>::apply_event(event, &mut world_write),
+                }
+            }
+        }
+    }
+    fn print_status(&self) {
+        let tickrate = self.header.tickrate as f32;
+        println!("[Replay] {:.1}s / {:.1}s at x{}{}", self.next as f32 / tickrate, self.ticks.len() as f32 / tickrate, SPEEDS[self.speed], if self.paused {" (paused)"} else {""});
+    }
+}
@@ -0,0 +1,55 @@
+use hord3::horde::game_engine::multiplayer::MustSync;
+
+use crate::{driver::{GameEntityEvent, MovementEvent, MovementEventVariant, stats::{StatEvent, StatEventVariant}}, game_engine::CoolGameEngineBase, game_map::{GameMapEvent, session::SessionMessage, checksums::{ResyncRequest, StateChecksums, movement_checksum, stats_checksum, vehicle_position_checksum}}, vehicle::{VehicleEntityEvent, position::{VehiclePosEvent, VehiclePosUpdate}}};
+
+// Regular checksums of what clients must agree on, see game_map::checksums
+pub fn send_checksums(engine:&CoolGameEngineBase, tick:usize) {
+    let drivers = engine.entity_1.get_read();
+    let vehicles = engine.vehicles.get_read();
+    let mut world_write = engine.world.world.write().unwrap();
+    let edited = world_write.take_edited_chunks();
+    let chunks = edited.into_iter().filter_map(|c_pos| {world_write.chunk_checksum(c_pos).map(|checksum| {(c_pos, checksum)})}).collect();
+    let checksums = StateChecksums {
+        tick,
+        vehicles:vehicles.position.iter().map(|position| {vehicle_position_checksum(position)}).collect(),
+        movements:drivers.movement.iter().map(|movement| {movement_checksum(movement)}).collect(),
+        stats:drivers.stats.iter().map(|stats| {stats_checksum(stats)}).collect(),
+        chunks
+    };
+    engine.world.tunnels_out.send_event(GameMapEvent::Session(SessionMessage::Checksums(checksums)));
+}
+
+// sends everything that was asked again, to every client since events can't go to a single one
+pub fn handle_resync_requests(requests:Vec<ResyncRequest>, engine:&CoolGameEngineBase) {
+    if requests.is_empty() {
+        return
+    }
+    let drivers = engine.entity_1.get_read();
+    let vehicles = engine.vehicles.get_read();
+    let world_read = engine.world.world.read().unwrap();
+    for request in requests {
+        println!("[Desync] Resending {} vehicles, {} drivers and {} chunks", request.vehicles.len(), request.drivers.len(), request.chunks.len());
+        for vehicle in request.vehicles.into_iter().filter(|vehicle| {*vehicle < vehicles.position.len()}) {
+            let position = &vehicles.position[vehicle];
+            vehicles.tunnels.position_out.send(VehicleEntityEvent::new(MustSync::Server, VehiclePosEvent::new(vehicle, None, VehiclePosUpdate::UpdateEveryPos(position.pos, position.orientation)))).unwrap();
+            vehicles.tunnels.position_out.send(VehicleEntityEvent::new(MustSync::Server, VehiclePosEvent::new(vehicle, None, VehiclePosUpdate::UpdateEverySpeed(position.spd, position.turn_spd)))).unwrap();
+        }
+        for driver in request.drivers.into_iter().filter(|driver| {*driver < drivers.movement.len()}) {
+            let movement = &drivers.movement[driver];
+            drivers.tunnels.movement_out.send(GameEntityEvent::new(MustSync::Server, MovementEvent::new(driver, None, MovementEventVariant::UpdatePos(movement.pos))));
+            drivers.tunnels.movement_out.send(GameEntityEvent::new(MustSync::Server, MovementEvent::new(driver, None, MovementEventVariant::UpdateSpeed(movement.speed))));
+            drivers.tunnels.movement_out.send(GameEntityEvent::new(MustSync::Server, MovementEvent::new(driver, None, MovementEventVariant::UpdateOrient(movement.orient))));
+            let stats = &drivers.stats[driver];
+            drivers.tunnels.stats_out.send(GameEntityEvent::new(MustSync::Server, StatEvent::new(driver, None, StatEventVariant::UpdateHealth(stats.health))));
+            drivers.tunnels.stats_out.send(GameEntityEvent::new(MustSync::Server, StatEvent::new(driver, None, StatEventVariant::UpdateDamage(stats.damage))));
+            drivers.tunnels.stats_out.send(GameEntityEvent::new(MustSync::Server, StatEvent::new(driver, None, StatEventVariant::UpdateStamina(stats.stamina))));
+            drivers.tunnels.stats_out.send(GameEntityEvent::new(MustSync::Server, StatEvent::new(driver, None, StatEventVariant::UpdatePersonalVehicle(stats.personal_vehicle))));
+        }
+        for c_pos in request.chunks {
+            match world_read.get_chunk_at(c_pos) {
+                Some(chunk) => engine.world.tunnels_out.send_event(GameMapEvent::NewChunk(c_pos, chunk.compress(world_read.get_chunk_dims()))),
+                None => ()
+            }
+        }
+    }
+}
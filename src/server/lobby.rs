@@ -0,0 +1,194 @@
+use std::{collections::HashMap, sync::atomic::Ordering};
+
+use hord3::horde::{game_engine::multiplayer::MustSync, geometry::{rotation::Orientation, vec3d::Vec3Df}};
+
+use crate::{game_engine::CoolGameEngineBase, game_map::{GameMapEvent, players::player_id, session::SessionMessage, lobby::{LobbyEvent, LobbyPhase, LobbyPlayer, LobbyState, MatchSetup}}, server::{players::PlayerManager, save_track}, vehicle::{VehicleEntityEvent, position::{VehiclePosEvent, VehiclePosUpdate}}};
+
+const COUNTDOWN_SECONDS:usize = 3;
+
+struct LobbyEntry {
+    vehicle_type:usize,
+    ready:bool,
+}
+
+// Players pick their vehicle type and ready up, the host (the connected player who joined first) picks the track
+// once everyone is ready the road starts generating, and the vehicles are held on its starting grid until the countdown ends
+pub struct Lobby {
+    // without a lobby the race starts with the server, nothing here is used
+    enabled:bool,
+    phase:LobbyPhase,
+    setup:MatchSetup,
+    // the road already started generating or comes from a track file, set_track can't change it anymore
+    setup_locked:bool,
+    // file and name the closed track is saved under once everyone is ready
+    track_export:Option<(String, Option<String>)>,
+    entries:HashMap<u64, LobbyEntry>,
+    // ids of the players in the order they joined
+    join_order:Vec<u64>,
+    // vehicle, position and orientation of every racer on the grid
+    grid:Vec<(usize, Vec3Df, Orientation)>,
+    // None until the first road segment, and the grid built from it, exist
+    countdown_end:Option<usize>,
+    tickrate:usize,
+    changed:bool,
+}
+
+impl Lobby {
+    pub fn new(setup:MatchSetup, setup_locked:bool, tickrate:usize) -> Self {
+        Self { enabled:true, phase:LobbyPhase::Waiting, setup, setup_locked, track_export:None, entries:HashMap::with_capacity(16), join_order:Vec::with_capacity(16), grid:Vec::new(), countdown_end:None, tickrate, changed:false }
+    }
+    pub fn without_lobby(setup:MatchSetup, tickrate:usize) -> Self {
+        Self { enabled:false, phase:LobbyPhase::Racing, ..Self::new(setup, true, tickrate) }
+    }
+    pub fn with_track_export(mut self, track_export:Option<(String, Option<String>)>) -> Self {
+        self.track_export = track_export;
+        self
+    }
+    // the road is only generated once the track can't change anymore
+    pub fn track_ready(&self) -> bool {
+        self.phase != LobbyPhase::Waiting
+    }
+    pub fn handle_events(&mut self, events:Vec<LobbyEvent>, players:&mut PlayerManager, engine:&CoolGameEngineBase) {
+        if !self.enabled {
+            return;
+        }
+        self.sync_players(players, engine);
+        for event in events {
+            match event {
+                LobbyEvent::PickVehicle { token, vehicle_type } => if self.phase == LobbyPhase::Waiting && let Some(entry) = self.entries.get_mut(&player_id(token)) {
+                    if vehicle_type < engine.vehicles.get_read().static_types.len() {
+                        players.change_vehicle(player_id(token), vehicle_type, engine);
+                        entry.vehicle_type = vehicle_type;
+                        self.changed = true;
+                    }
+                },
+                LobbyEvent::Ready { token, ready } => if self.phase == LobbyPhase::Waiting && let Some(entry) = self.entries.get_mut(&player_id(token)) && entry.ready != ready {
+                    entry.ready = ready;
+                    self.changed = true;
+                    match players.get_sessions().get(&player_id(token)) {
+                        Some(session) => println!("[Lobby] {} is {}", session.name, if ready {"ready"} else {"not ready anymore"}),
+                        None => ()
+                    }
+                },
+                LobbyEvent::Setup { token, setup } => if self.phase == LobbyPhase::Waiting && self.join_order.first() == Some(&player_id(token)) && setup != self.setup {
+                    if self.setup_locked {
+                        println!("[Lobby] The road of this world already started generating or comes from a track file, the track can't change");
+                    }
+                    else {
+                        println!("[Lobby] The host picked seed {} through {}", setup.seed, setup.biome_names());
+                        self.setup = setup;
+                        self.changed = true;
+                    }
+                },
+                // our own states coming back
+                LobbyEvent::State(_) => ()
+            }
+        }
+    }
+    pub fn is_racing(&self) -> bool {
+        self.phase == LobbyPhase::Racing
+    }
+    // once per tick after handle_events, true on the tick the race starts
+    pub fn tick(&mut self, players:&PlayerManager, engine:&CoolGameEngineBase) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let mut started = false;
+        let tick = engine.extra_data.tick.load(Ordering::Relaxed);
+        self.sync_players(players, engine);
+        match self.phase.clone() {
+            LobbyPhase::Waiting => if !self.entries.is_empty() && self.entries.values().all(|entry| {entry.ready}) {
+                let mut world_write = engine.world.world.write().unwrap();
+                let dims = world_write.get_chunk_dims_vector_f();
+                if !self.setup_locked {
+                    world_write.generator.set_track(self.setup.seed, self.setup.biomes.clone(), &dims);
+                    self.setup_locked = true;
+                    engine.world.tunnels_out.send_event(GameMapEvent::UpdateGenerator(world_write.generator.clone()));
+                }
+                match &self.track_export {
+                    Some((path, name)) => save_track(path, name.clone(), &world_write.generator, &dims),
+                    None => ()
+                }
+                drop(world_write);
+                println!("[Lobby] Everyone is ready, {} players racing on seed {} through {}", self.entries.len(), self.setup.seed, self.setup.biome_names());
+                self.phase = LobbyPhase::Countdown(COUNTDOWN_SECONDS);
+                self.countdown_end = None;
+                self.changed = true;
+            },
+            LobbyPhase::Countdown(shown) => {
+                if self.countdown_end.is_none() {
+                    let spots = engine.world.world.read().unwrap().generator.starting_grid(self.join_order.len());
+                    if !spots.is_empty() {
+                        let sessions = players.get_sessions();
+                        self.grid = self.join_order.iter().zip(spots).map(|(token, (pos, orient))| {(sessions[token].vehicle, pos, orient)}).collect();
+                        self.countdown_end = Some(tick + COUNTDOWN_SECONDS * self.tickrate);
+                    }
+                }
+                match self.countdown_end {
+                    Some(end) if tick >= end => {
+                        println!("[Lobby] Race started");
+                        self.phase = LobbyPhase::Racing;
+                        self.grid.clear();
+                        self.changed = true;
+                        started = true;
+                    },
+                    Some(end) => {
+                        self.hold_grid(engine);
+                        let left = (end - tick).div_ceil(self.tickrate);
+                        if left != shown {
+                            self.phase = LobbyPhase::Countdown(left);
+                            self.changed = true;
+                        }
+                    },
+                    None => ()
+                }
+            },
+            LobbyPhase::Racing => if self.entries.is_empty() {
+                println!("[Lobby] Everyone left, back to the lobby");
+                self.phase = LobbyPhase::Waiting;
+                self.changed = true;
+            }
+        }
+        if self.changed || tick % self.tickrate == 0 {
+            let state = self.get_state(players, engine);
+            engine.world.tunnels_out.send_event(GameMapEvent::Session(SessionMessage::Lobby(LobbyEvent::State(state))));
+            self.changed = false;
+        }
+        started
+    }
+    // players who joined get an entry, the ones who left lose it
+    fn sync_players(&mut self, players:&PlayerManager, engine:&CoolGameEngineBase) {
+        let sessions = players.get_sessions();
+        let before = self.entries.len();
+        self.entries.retain(|id, _| {sessions.contains_key(id)});
+        self.join_order.retain(|id| {sessions.contains_key(id)});
+        if self.entries.len() != before {
+            self.changed = true;
+        }
+        let vehicles = engine.vehicles.get_read();
+        for (id, session) in sessions {
+            if !self.entries.contains_key(id) {
+                self.entries.insert(*id, LobbyEntry { vehicle_type:vehicles.stats[session.vehicle].static_id, ready:false });
+                self.join_order.push(*id);
+                self.changed = true;
+            }
+        }
+    }
+    // stopped on their spot until the countdown ends, whatever their players press
+    fn hold_grid(&self, engine:&CoolGameEngineBase) {
+        let vehicles = engine.vehicles.get_read();
+        for (vehicle, pos, orient) in &self.grid {
+            vehicles.tunnels.position_out.send(VehicleEntityEvent::new(MustSync::Server, VehiclePosEvent::new(*vehicle, None, VehiclePosUpdate::UpdateEveryPos(*pos, *orient))));
+            vehicles.tunnels.position_out.send(VehicleEntityEvent::new(MustSync::Server, VehiclePosEvent::new(*vehicle, None, VehiclePosUpdate::UpdateEverySpeed(Vec3Df::zero(), Orientation::zero()))));
+        }
+    }
+    fn get_state(&self, players:&PlayerManager, engine:&CoolGameEngineBase) -> LobbyState {
+        let sessions = players.get_sessions();
+        let lobby_players = self.join_order.iter().enumerate().filter_map(|(i, id)| {
+            let session = sessions.get(id)?;
+            let entry = self.entries.get(id)?;
+            Some(LobbyPlayer { name:session.name.clone(), driver:session.driver, vehicle_type:entry.vehicle_type, ready:entry.ready, host:i == 0 })
+        }).collect();
+        LobbyState { phase:self.phase.clone(), players:lobby_players, setup:self.setup.clone(), setup_locked:self.setup_locked, vehicle_types:engine.vehicles.get_read().static_types.len() }
+    }
+}
@@ -1,9 +1,11 @@
 
-use std::{collections::HashMap, f32::consts::PI, net::Ipv4Addr, path::PathBuf, simd::Simd, sync::{atomic::{AtomicUsize, Ordering}, mpmc::{self, channel}, Arc, RwLock}, thread, time::{Duration, Instant}};
+use std::{collections::HashMap, f32::consts::PI, net::Ipv4Addr, path::{Path, PathBuf}, simd::Simd, sync::{atomic::{AtomicUsize, Ordering}, mpmc::{self, channel, Receiver}, Arc, RwLock}, thread, time::{Duration, Instant}};
 
-use crate::{driver::{colliders::AABB, stats::{StaticStats, Stats}}, game_map::{GameMapEvent, WorldChunkPos, road::Road}, server::server_tasks::GameUserEvent, vehicle::{NewVehicleEntity, VehicleEntityVec, default_vehicles::default_car::get_default_car_type, position::VehiclePosition, vehicle_stats::VehicleStats}};
+use crate::{driver::{colliders::AABB, stats::{StaticStats, Stats}}, game_map::{GameMapEvent, WorldChunkPos, chunk_compression::ChunkTransferStats, lobby::MatchSetup, edits::VoxelEditRejected, mesher::MeshingComparison, players::player_id, road::{Road, RoadVoxels}, session::SessionMessages, storage::RegionStorage, track::Track}, replay::ReplayRecorder, server::{lobby::Lobby, players::PlayerManager, race::{RaceCourse, RaceTracker}, server_tasks::GameUserEvent, voxel_edits::VoxelEditValidator}, vehicle::{NewVehicleEntity, VehicleEntityVec, default_vehicles::default_car::get_default_car_type, position::VehiclePosition, vehicle_stats::VehicleStats}};
 use cosmic_text::{Color, Font, Metrics};
 use crate::cutscene::{camera_movement::{CameraMovement, CameraMovementDuration, CameraMovementElement, CameraSequence}, demo_cutscene::{get_demo_cutscene, get_empty_cutscene}, game_shader::GameShader, real_demo_cutscene::get_real_demo_cutscene, write_in_the_air::get_positions_of_air_written_text, written_texture::get_written_texture_buffer};
+use crate::config::GameConfig;
+use crate::voxel_registry::voxel_registry;
 use crate::day_night::DayNight;
 use crate::game_3d_models::{clustered_ent_mesh, grey_sphere_mesh, lit_selection_cube, second_spread_out_ent_mesh, simple_line, sphere_mesh, spread_out_ent_mesh, textured_sphere_mesh, wireframe_sphere_mesh, xyz_mesh};
 use crate::game_engine::{CoolGameEngineBase, CoolVoxel, CoolVoxelType, ExtraData};
@@ -11,19 +13,28 @@ use crate::driver::{Collider, GameEntityVec, Movement, NewGameEntity, StaticColl
 use crate::game_input_handler::GameInputHandler;
 use crate::game_map::{get_f64_pos, get_float_pos, light_spreader::{LightPos, LightSpread}, ChunkDims, GameMap, VoxelLight};
 use crate::gui_elements::{list_choice::get_list_choice, number_config::get_number_config};
-use hord3::{defaults::{default_frontends::minifb_frontend::MiniFBWindow, default_rendering::vectorinator_binned::{Vectorinator, meshes::{Mesh, MeshID, MeshLODS, MeshLODType}, rendering_spaces::ViewportData, shaders::NoOpShader, textures::{TextureSetID, argb_to_rgb, rgb_to_argb}, triangles::{color_u32_to_u8_simd, simd_rgb_to_argb}}, default_ui::simple_ui::{SimpleUI, UIDimensions, UIElement, UIElementBackground, UIElementContent, UIElementID, UIEvent, UIUnit, UIUserAction, UIVector}}, horde::{frontend::{HordeWindowDimensions, WindowingHandler}, game_engine::{entity::Renderable, multiplayer::{HordeMultiModeChoice, MustSync}, world::{WorldComputeHandler, WorldHandler}}, geometry::{plane::EquationPlane, rotation::{Orientation, Rotation}, vec3d::{Vec3D, Vec3Df}}, rendering::{camera::Camera, framebuffer::HordeColorFormat}, scheduler::{HordeScheduler, HordeTaskQueue, HordeTaskSequence, SequencedTask}, sound::{SoundRequest, WaveIdentification, WavePosition, WaveRequest, WaveSink, Waves}}};
+use hord3::{defaults::{default_frontends::minifb_frontend::MiniFBWindow, default_rendering::vectorinator_binned::{Vectorinator, meshes::{Mesh, MeshID, MeshLODS, MeshLODType}, rendering_spaces::ViewportData, shaders::NoOpShader, textures::{TextureSetID, argb_to_rgb, rgb_to_argb}, triangles::{color_u32_to_u8_simd, simd_rgb_to_argb}}, default_ui::simple_ui::{SimpleUI, UIDimensions, UIElement, UIElementBackground, UIElementContent, UIElementID, UIEvent, UIUnit, UIUserAction, UIVector}}, horde::{frontend::{HordeWindowDimensions, WindowingHandler}, game_engine::{entity::Renderable, multiplayer::{HordeMultiModeChoice, MustSync}, world::{WorldComputeHandler, WorldHandler}}, geometry::{plane::EquationPlane, rotation::{Orientation, Rotation}, vec3d::{Vec3D, Vec3Df}}, rendering::{camera::Camera, framebuffer::{HordeColorFormat, HordeFramebuffer}}, scheduler::{HordeScheduler, HordeTask, HordeTaskQueue, HordeTaskSequence, SequencedTask}, sound::{SoundRequest, WaveIdentification, WavePosition, WaveRequest, WaveSink, Waves}}};
 use noise::{NoiseFn, Perlin, Seedable};
 use crate::tile_editor::{get_tile_voxels, TileEditorData};
 use server_tasks::{ServerTask, ServerTaskTaskHandler};
 
-use crate::{driver::{actions::{Action, ActionKind, ActionSource, ActionTimer, ActionsEvent, ActionsUpdate, StaticGameActions}, director::{llm_director::LLMDirector, Director, DirectorKind, StaticDirector}, planner::StaticPlanner, GameEntityEvent}, game_map::get_voxel_pos, proxima_link::ProximaLink};
+use crate::{driver::{actions::{Action, ActionKind, ActionSource, ActionTimer, ActionsEvent, ActionsUpdate, StaticGameActions}, director::{llm_director::LLMDirector, Director, DirectorKind, StaticDirector}, planner::StaticPlanner, GameEntityEvent}, game_map::get_voxel_pos, proxima_link::{HordeProximaAIResponse, ProximaLink}};
 
 pub mod server_tasks;
+pub mod voxel_edits;
+pub mod players;
+pub mod checksums;
+pub mod lobby;
+pub mod race;
 
-pub fn server_func() {
-    const TICKRATE:usize = 90;
-    let mut world = GameMap::new(100, ChunkDims::new(8, 8, 8), get_tile_voxels(), (255,255,255), 1, Road::new(WorldChunkPos::new(0,0,1), Vec3Df::new(1.0, 0.0, 0.0)));
-    let mut perlin = Perlin::new(13095);
+pub fn server_func(config:GameConfig) {
+    let headless = config.headless;
+    let registry = voxel_registry();
+    let mut world = GameMap::new(100, ChunkDims::new(8, 8, 8), get_tile_voxels(), (40,40,40), 1, Road::new(WorldChunkPos::new(0,0,1), Vec3Df::new(1.0, 0.0, 0.0), config.world_seed, RoadVoxels::from_registry(registry))).with_meshing_mode(config.meshing_mode()).with_registry_hash(registry.get_hash()).with_authority(true);
+    let mut perlin = Perlin::new(config.world_seed as u32);
+    let (air, below_water) = (registry.id("air"), registry.id("snow"));
+    // from the water level up
+    let ground_layers = ["sand", "flowers", "grassy_ground", "ground", "rock", "snow"].map(|id| {registry.id(id)});
     let mut world_height = 15.0;
     let mut water_level = 10.0;
     let start = Vec3D::new(-15, -15, -2);
@@ -42,19 +53,97 @@ pub fn server_func() {
                 if let Some(val) =  ground_at.get(ground_pos) && *val < pos.z {
                     ground_at[ground_pos] = water_level as i32;
                 }
-                CoolVoxel {voxel_type:6, orient:0, light:VoxelLight::random_light()}
+                CoolVoxel {voxel_type:below_water, orient:0, light:VoxelLight::zero_light(), sky:0}
             }
             else {
                 if let Some(val) =  ground_at.get(ground_pos) && *val < pos.z {
                     ground_at[ground_pos] = pos.z;
                 }
-                CoolVoxel {voxel_type:1 + ((actual_height - water_level)/(6.0*world_height * (1.0/6.0))).clamp(0.0, 5.99) as u16, orient:0, light:VoxelLight::random_light()}
+                CoolVoxel {voxel_type:ground_layers[((actual_height - water_level)/(6.0*world_height * (1.0/6.0))).clamp(0.0, 5.99) as usize], orient:0, light:VoxelLight::zero_light(), sky:0}
             }
         } else {
-            CoolVoxel {voxel_type:0, orient:0, light:VoxelLight::zero_light()}
+            CoolVoxel {voxel_type:air, orient:0, light:VoxelLight::zero_light(), sky:0}
         }
     }
     );
+    // a saved or hand-authored track, or a closed one planned up front instead of the endless road, a saved world keeps its own road
+    match &config.track_file {
+        Some(file) => match Track::load(Path::new(file), RoadVoxels::from_registry(registry), &world.get_chunk_dims_vector_f()) {
+            Ok(track) if config.race_laps > 1 && !track.road.is_closed() => {
+                println!("Track {} doesn't loop, it can't be raced for {} laps", file, config.race_laps);
+                return;
+            },
+            Ok(track) => {
+                println!("[Road] Racing on track \"{}\" from {}, {} segments", track.name, file, track.road.segment_count());
+                world.generator = track.road;
+            },
+            Err(error) => {
+                println!("Could not load track {} : {}", file, error);
+                return;
+            }
+        },
+        None => if config.track_segments > 0 {
+            let dims = world.get_chunk_dims_vector_f();
+            world.generator = world.generator.clone().into_closed_track(config.track_segments, &dims);
+        }
+    }
+    let edit_validator = VoxelEditValidator::new(config.edit_reach, config.max_edits_per_second, config.tickrate);
+    // the driver and vehicle spawned below go to the first player joining
+    let players = PlayerManager::new(vec![(0, 0)], config.max_players, config.tickrate, config.leavers_to_ai);
+    let storage = match &config.save_folder {
+        Some(folder) => match RegionStorage::open(PathBuf::from(folder)) {
+            Ok(storage) => Some(storage),
+            Err(error) => {
+                println!("Could not open world save at {} : {}, the world will not be saved", folder, error);
+                None
+            }
+        },
+        None => None
+    };
+    match &storage {
+        Some(storage) => {
+            world.set_saving(true);
+            match storage.load_generator::<Road>() {
+                Ok(Some(road)) => {
+                    if config.track_file.is_some() || config.track_segments > 0 {
+                        println!("The world saved in {} already has its road, it can't race another track : use another --save-folder or --no-save", config.save_folder.clone().unwrap_or_default());
+                        return;
+                    }
+                    if road.get_seed() != config.world_seed {
+                        println!("[Road] WARNING : the saved road uses seed {} and not seed {}, the saved road is kept and the track can't be picked in the lobby", road.get_seed(), config.world_seed);
+                    }
+                    world.generator = road;
+                },
+                Ok(None) => (),
+                Err(error) => println!("Could not load saved road : {}", error)
+            }
+            let positions = world.get_all_chunk_pos();
+            match storage.load_chunks(&positions) {
+                Ok(chunks) => for (c_pos, chunk) in chunks {
+                    world.insert_loaded_chunk(c_pos, chunk);
+                },
+                Err(error) => println!("Could not load saved chunks : {}", error)
+            }
+        },
+        None => ()
+    }
+    // the host can only pick the track of a road that didn't start generating, not of a saved one
+    let setup = MatchSetup { seed:world.generator.get_seed(), biomes:world.generator.get_biomes().clone() };
+    let lobby = if config.lobby {
+        Lobby::new(setup, world.generator.has_started() || config.track_file.is_some(), config.tickrate).with_track_export(config.save_track.clone().map(|path| {(path, config.track_name.clone())}))
+    }
+    else {
+        Lobby::without_lobby(setup, config.tickrate)
+    };
+    let mut race = RaceTracker::new(RaceCourse { checkpoint_segments:config.checkpoint_segments, lap_segments:if config.race_segments == 0 {None} else {Some(config.race_segments)}, laps:config.race_laps, closed:false }, config.tickrate);
+    // without a lobby everyone races from the moment they join, on a track that can't change anymore
+    if !config.lobby {
+        race.start(&players, &world.generator, 0, true);
+        match &config.save_track {
+            Some(path) => save_track(path, config.track_name.clone(), &world.generator, &world.get_chunk_dims_vector_f()),
+            None => ()
+        }
+    }
     let mut world_clone = world.clone();
     if false {
         world_clone.change_mesh_vec(10);
@@ -84,7 +173,7 @@ pub fn server_func() {
         }
     }
 
-    let (payload_sender, response_receiver) = match ProximaLink::initialize(String::from("HORDE"), String::from("HORDE"), String::from("http://localhost:8085")) {
+    let (payload_sender, response_receiver) = match ProximaLink::initialize(config.llm_username.clone(), config.llm_password.clone(), config.llm_endpoint.clone()) {
         Ok((s, r)) => (s, r),
         Err(_) => (mpmc::channel().0, mpmc::channel().1)
     };
@@ -96,91 +185,304 @@ pub fn server_func() {
 
         writer.new_ent(NewVehicleEntity::new(VehiclePosition::new().with_pos(Vec3Df::new(0.0, 0.0, 10.5)), VehicleStats {static_id:0, nitro_left:100.0, mass:10.0},  MustSync::Server, None));
     }
-    let windowing = WindowingHandler::new::<MiniFBWindow>(HordeWindowDimensions::new(1280, 720), HordeColorFormat::ARGB8888);
-    let framebuf = windowing.get_outside_framebuf();
-    let mut shader = Arc::new(GameShader::new_default());
-    let viewport_data = {
-        let framebuf = framebuf.read().unwrap();
-        ViewportData {
-            near_clipping_plane: 1.0,
-            half_image_width: (framebuf.get_dims().get_width()/2) as f32,
-            half_image_height: (framebuf.get_dims().get_height()/2) as f32,
-            aspect_ratio: (framebuf.get_dims().get_width() as f32)/(framebuf.get_dims().get_height() as f32),
-            camera_plane: EquationPlane::new(Vec3D::new(0.0, 0.0, 1.0), -1.0),
-            image_height: (framebuf.get_dims().get_height() as f32),
-            image_width: (framebuf.get_dims().get_width() as f32),
-            poscam: Vec3D::zero(),
-            rotat_cam: Rotation::new_from_inverted_orient(Orientation::zero())
-        }
+    // headless servers never open a window nor keep a viewport, see headless_renderer
+    let (windowing, vectorinator, current_render_data) = if headless {
+        (None, headless_renderer(), None)
+    }
+    else {
+        let windowing = WindowingHandler::new::<MiniFBWindow>(HordeWindowDimensions::new(config.window_width, config.window_height), HordeColorFormat::ARGB8888);
+        let framebuf = windowing.get_outside_framebuf();
+        let viewport_data = {
+            let framebuf = framebuf.read().unwrap();
+            ViewportData {
+                near_clipping_plane: 1.0,
+                half_image_width: (framebuf.get_dims().get_width()/2) as f32,
+                half_image_height: (framebuf.get_dims().get_height()/2) as f32,
+                aspect_ratio: (framebuf.get_dims().get_width() as f32)/(framebuf.get_dims().get_height() as f32),
+                camera_plane: EquationPlane::new(Vec3D::new(0.0, 0.0, 1.0), -1.0),
+                image_height: (framebuf.get_dims().get_height() as f32),
+                image_width: (framebuf.get_dims().get_width() as f32),
+                poscam: Vec3D::zero(),
+                rotat_cam: Rotation::new_from_inverted_orient(Orientation::zero())
+            }
+        };
+        let vectorinator = Vectorinator::new(framebuf.clone(), Arc::new(GameShader::new_default()));
+        (Some(windowing), vectorinator, Some(Arc::new(RwLock::new((Camera::empty(), viewport_data)))))
+    };
+    // kept alive for the whole server run, dropping the stream stops audio
+    let audio = if headless {
+        None
+    }
+    else {
+        Some(Waves::new(Vec::new(), 10))
     };
-    let vectorinator = Vectorinator::new(framebuf.clone(), shader);
-    let (waves, waves_handler, stream) = Waves::new(Vec::new(), 10);
     let world_handler = WorldHandler::new(world);
     
     let engine = CoolGameEngineBase::new(
         entity_vec, entity_vec_2, world_handler.clone(), Arc::new(vectorinator.clone()), 
-        HordeMultiModeChoice::Server { adress: (Ipv4Addr::new(127, 0, 0, 1), 5678), max_players: 30, tick_tolerance: 3, tickrate: TICKRATE },
-        ExtraData {payload_sender, tick: Arc::new(AtomicUsize::new(0)), waves:waves_handler.clone(), current_render_data:Arc::new(RwLock::new((Camera::empty(), viewport_data.clone()))),}
+        HordeMultiModeChoice::Server { adress: (config.address, config.port), max_players: config.max_players, tick_tolerance: config.tick_tolerance, tickrate: config.tickrate },
+        ExtraData {payload_sender, tick: Arc::new(AtomicUsize::new(0)), waves:audio.as_ref().map(|(_, waves_handler, _)| waves_handler.clone()), current_render_data,}
         
     );
-    waves_handler.send_gec(engine.clone());
+    match &audio {
+        Some((_, waves_handler, _)) => waves_handler.send_gec(engine.clone()),
+        None => ()
+    }
 
-    let handler = ServerTaskTaskHandler::new(engine.clone(), windowing);
-    
-    let queue = HordeTaskQueue::new(vec![HordeTaskSequence::new(vec![
-        SequencedTask::StartTask(ServerTask::ApplyEvents),
-        SequencedTask::WaitFor(ServerTask::ApplyEvents),
-        SequencedTask::StartTask(ServerTask::Main),
-        SequencedTask::WaitFor(ServerTask::Main),
-        SequencedTask::StartTask(ServerTask::ApplyEvents),
-        SequencedTask::WaitFor(ServerTask::ApplyEvents),
-        SequencedTask::StartTask(ServerTask::AfterMain),
-        SequencedTask::WaitFor(ServerTask::AfterMain),
-        SequencedTask::StartTask(ServerTask::ApplyEvents),
-        SequencedTask::WaitFor(ServerTask::ApplyEvents),
-        SequencedTask::StartTask(ServerTask::SendMustSync),
-        SequencedTask::WaitFor(ServerTask::SendMustSync),
-        SequencedTask::StartTask(ServerTask::MultiFirstPart),
-        SequencedTask::WaitFor(ServerTask::MultiFirstPart),
-        SequencedTask::StartTask(ServerTask::MultiSecondPart),
-        SequencedTask::WaitFor(ServerTask::MultiSecondPart),
-        SequencedTask::StartTask(ServerTask::MultiThirdPart),
-        SequencedTask::WaitFor(ServerTask::MultiThirdPart),
-        ]
-    )], Vec::new());
-    println!("Hello, world!");
+    let recorder = match &config.replay_file {
+        Some(file) => match ReplayRecorder::create(PathBuf::from(file), config.tickrate, &mut engine.world.world.write().unwrap()) {
+            Ok(recorder) => Some(recorder),
+            Err(error) => {
+                println!("[Replay] Could not record to {} : {}, the session will not be recorded", file, error);
+                None
+            }
+        },
+        None => None
+    };
+
+    let mut session = ServerSession { storage, recorder, edit_validator, players, lobby, race };
+    // the server never draws a frame, its tasks are the same with or without a window, which only has to stay open
+    let handler = ServerTaskTaskHandler::new(engine.clone());
+    let queue = server_tick_queue(ServerTask::ApplyEvents, ServerTask::Main, ServerTask::AfterMain, ServerTask::SendMustSync, [ServerTask::MultiFirstPart, ServerTask::MultiSecondPart, ServerTask::MultiThirdPart]);
+    println!("{}", if windowing.is_some() {"Hello, world!"} else {"Starting headless server"});
     let mut scheduler = HordeScheduler::new(queue.clone(), handler, 16);
-    
+    server_loop(&engine, &response_receiver, &config, &mut session, || {
+        scheduler.initialise(queue.clone());
+        scheduler.tick();
+    });
+    scheduler.end_threads();
+    drop(windowing);
+    // the road as far as it was generated, to be edited and raced again
+    match &config.export_road {
+        Some(path) => {
+            let world_read = engine.world.world.read().unwrap();
+            save_track(path, config.track_name.clone(), &world_read.generator, &world_read.get_chunk_dims_vector_f());
+        },
+        None => ()
+    }
+}
+
+// one server tick
+fn server_tick_queue<T:HordeTask>(apply_events:T, main:T, after_main:T, send_must_sync:T, multi_parts:[T ; 3]) -> HordeTaskQueue<T> {
+    let mut steps = vec![apply_events.clone(), main, apply_events.clone(), after_main, apply_events, send_must_sync];
+    steps.extend(multi_parts);
+    let tasks = steps.into_iter().flat_map(|task| {[SequencedTask::StartTask(task.clone()), SequencedTask::WaitFor(task)]}).collect();
+    HordeTaskQueue::new(vec![HordeTaskSequence::new(tasks)], Vec::new())
+}
+
+// the engine type requires a renderer whatever the mode, the server never schedules a rendering task so a headless one
+// only gets a 1x1 framebuffer that is never drawn to
+fn headless_renderer() -> Vectorinator<GameShader> {
+    Vectorinator::new(Arc::new(RwLock::new(HordeFramebuffer::new(HordeWindowDimensions::new(1, 1), HordeColorFormat::ARGB8888))), Arc::new(GameShader::new_default()))
+}
+
+// chunks around vehicles that are saved on disk but not in memory get loaded within that radius
+const LOAD_RADIUS:i32 = 3;
+
+// moves the road one step forwards and sends the chunks it generated to every client
+fn generate_road_step(world:&mut GameMap<CoolVoxel, Road>, engine:&CoolGameEngineBase, chunk_stats:&mut ChunkTransferStats, meshing_comparison:&mut Option<MeshingComparison>) {
+    let dims = world.get_chunk_dims_vector_f();
+    world.generator.step_forwards(3.0, &dims);
+    // clients drive and collide on their own copy of the road
+    engine.world.tunnels_out.send_event(GameMapEvent::UpdateGenerator(world.generator.clone()));
+    let chunks = world.generator.get_chunks_to_generate(3.0, world);
+    let data = world.generate_chunks_with_generator_and_get_them(chunks);
+    for (c_pos, chunk) in data {
+        let compressed = chunk.compress(world.get_chunk_dims());
+        chunk_stats.add(&chunk, &compressed, world.get_chunk_dims());
+        match meshing_comparison {
+            Some(comparison) => match world.count_triangles_per_mode(c_pos) {
+                Some(triangles) => comparison.add(triangles),
+                None => ()
+            },
+            None => ()
+        }
+        engine.world.tunnels_out.send_event(GameMapEvent::NewChunk(c_pos, compressed));
+    }
+    chunk_stats.print();
+    match meshing_comparison {
+        Some(comparison) => comparison.print(),
+        None => ()
+    }
+}
+
+// the road under name or "Track <seed>", endless roads can only be saved to .json files, see game_map::track
+pub fn save_track(path:&str, name:Option<String>, road:&Road, chunk_dims:&Vec3Df) {
+    let track = Track { name:name.unwrap_or_else(|| {format!("Track {}", road.get_seed())}), road:road.as_unbuilt(chunk_dims) };
+    match track.save(Path::new(path)) {
+        Ok(()) => println!("[Road] Saved track \"{}\" to {}", track.name, path),
+        Err(error) => println!("[Road] Could not save track to {} : {}", path, error)
+    }
+}
+
+// everything the server keeps about the session besides the engine
+struct ServerSession {
+    storage:Option<RegionStorage>,
+    recorder:Option<ReplayRecorder>,
+    edit_validator:VoxelEditValidator,
+    players:PlayerManager,
+    lobby:Lobby,
+    race:RaceTracker,
+}
+
+fn server_loop(engine:&CoolGameEngineBase, response_receiver:&Receiver<HordeProximaAIResponse>, config:&GameConfig, session:&mut ServerSession, mut tick_scheduler:impl FnMut()) {
+    let ServerSession { storage, recorder, edit_validator, players, lobby, race } = session;
+    let (tickrate, view_distance, compare_meshing, checksum_interval) = (config.tickrate, config.view_distance, config.compare_meshing, config.checksum_interval);
+    let mut chunk_stats = ChunkTransferStats::new();
+    let mut meshing_comparison = if compare_meshing {Some(MeshingComparison::new())} else {None};
     for i in 0..75000 {
         println!("----------------- NEW TICK {i} ----------------");
 
         let mut start = Instant::now();
         let new_camera = {
-            if i < 100 {
+            let initial_road_done = engine.world.world.read().unwrap().generator.initial_generation_done();
+            // the lobby holds the road back while the host can still change the track
+            if !initial_road_done && lobby.track_ready() {
                 let mut world_write = engine.world.world.write().unwrap();
-                let dims = world_write.get_chunk_dims_vector_f();
-                world_write.generator.step_forwards(3.0, &dims);
-                let chunks = world_write.generator.get_chunks_to_generate(3.0, &world_write);
-                let data = world_write.generate_chunks_with_generator_and_get_them(chunks);
-                for (c_pos, chunk) in data {
-                    engine.world.tunnels_out.send_event(GameMapEvent::NewChunk(c_pos, chunk));
-                }
+                generate_road_step(&mut world_write, engine, &mut chunk_stats, &mut meshing_comparison);
             }
-            else {
+            else if initial_road_done {
                 let engine_read = engine.vehicles.get_read();
-                let first_position = engine_read.position[0].pos; // must be position of first place in reality
                 let mut world_write = engine.world.world.write().unwrap();
-                if world_write.generator.position_within_last(first_position) {
-                    let dims = world_write.get_chunk_dims_vector_f();
-                    world_write.generator.step_forwards(3.0, &dims);
-                    let chunks = world_write.generator.get_chunks_to_generate(3.0, &world_write);
-                    let data = world_write.generate_chunks_with_generator_and_get_them(chunks);
-                    for (c_pos, chunk) in data {
-                        engine.world.tunnels_out.send_event(GameMapEvent::NewChunk(c_pos, chunk));
-                    }
+                // every player has its own vehicle, the road grows as soon as one of them gets close to its end
+                if (0..engine_read.position.len()).any(|v| {world_write.generator.position_within_last(engine_read.position[v].pos)}) {
+                    generate_road_step(&mut world_write, engine, &mut chunk_stats, &mut meshing_comparison);
                 }
                 
             }
+            match storage {
+                Some(storage) => {
+                    let engine_read = engine.vehicles.get_read();
+                    let mut world_write = engine.world.world.write().unwrap();
+                    let mut to_load = Vec::with_capacity(16);
+                    for v in 0..engine_read.position.len() {
+                        let center = world_write.get_chunk_pos(engine_read.position[v].pos);
+                        for xc in (center.x - LOAD_RADIUS)..=(center.x + LOAD_RADIUS) {
+                            for yc in (center.y - LOAD_RADIUS)..=(center.y + LOAD_RADIUS) {
+                                for zc in (center.z - 1)..=(center.z + 1) {
+                                    let c_pos = Vec3D::new(xc, yc, zc);
+                                    if !world_write.does_chunk_exist(c_pos) && storage.is_chunk_saved(c_pos) {
+                                        to_load.push(c_pos);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    if !to_load.is_empty() {
+                        match storage.load_chunks(&to_load) {
+                            Ok(chunks) => for (c_pos, chunk) in chunks {
+                                let compressed = chunk.compress(world_write.get_chunk_dims());
+                                chunk_stats.add(&chunk, &compressed, world_write.get_chunk_dims());
+                                world_write.insert_loaded_chunk(c_pos, chunk);
+                                engine.world.tunnels_out.send_event(GameMapEvent::NewChunk(c_pos, compressed));
+                            },
+                            Err(error) => println!("Could not load saved chunks : {}", error)
+                        }
+                    }
+                    if i % tickrate == 0 {
+                        match storage.save_dirty(&mut world_write) {
+                            Ok(0) => (),
+                            Ok(saved) => println!("Saved {} chunks", saved),
+                            Err(error) => println!("Could not save world : {}", error)
+                        }
+                    }
+                },
+                None => ()
+            }
+            {
+                let mut world_write = engine.world.world.write().unwrap();
+                let requests = world_write.take_chunk_requests();
+                if !requests.is_empty() {
+                    let from_disk = match storage {
+                        Some(storage) => storage.load_chunks::<CoolVoxel>(&requests.iter().filter(|c_pos| {!world_write.does_chunk_exist(**c_pos)}).cloned().collect::<Vec<WorldChunkPos>>()).unwrap_or_else(|error| {println!("Could not load requested chunks : {}", error); Vec::new()}),
+                        None => Vec::new()
+                    };
+                    for (c_pos, chunk) in from_disk {
+                        world_write.insert_loaded_chunk(c_pos, chunk);
+                    }
+                    for c_pos in requests {
+                        match world_write.get_chunk_at(c_pos) {
+                            Some(chunk) => {
+                                let compressed = chunk.compress(world_write.get_chunk_dims());
+                                chunk_stats.add(&chunk, &compressed, world_write.get_chunk_dims());
+                                engine.world.tunnels_out.send_event(GameMapEvent::NewChunk(c_pos, compressed));
+                            },
+                            None => ()
+                        }
+                    }
+                }
+            }
+            let session_messages = SessionMessages::from_messages(engine.world.world.write().unwrap().take_session_messages());
+            {
+                players.handle_events(session_messages.players, engine);
+                if i % tickrate == 0 {
+                    players.drop_silent_players(engine);
+                }
+                players.send_vehicle_states(engine);
+                lobby.handle_events(session_messages.lobby, players, engine);
+                if lobby.tick(players, engine) {
+                    race.start(players, &engine.world.world.read().unwrap().generator, engine.extra_data.tick.load(Ordering::Relaxed), false);
+                }
+                // everyone left, the lobby is waiting for the next race
+                if !lobby.is_racing() && race.is_running() {
+                    race.stop();
+                }
+                race.update(players, engine);
+            }
+            {
+                let drivers = engine.entity_1.get_read();
+                let vehicles = engine.vehicles.get_read();
+                let mut world_write = engine.world.world.write().unwrap();
+                let tick = engine.extra_data.tick.load(Ordering::Relaxed);
+                for request in world_write.take_edit_requests() {
+                    // unknown tokens are dropped, there is no player to tell
+                    let player = match players.get_driver(player_id(request.token)) {
+                        Some(player) => player,
+                        None => {
+                            println!("[Edits] Ignored edit {} from an unknown session", request.edit_id);
+                            continue;
+                        }
+                    };
+                    match edit_validator.check(&request, player, &drivers, &vehicles, &world_write, tick) {
+                        Ok(()) => engine.world.tunnels_out.send_event(GameMapEvent::UpdateVoxelAt(request.pos, request.voxel)),
+                        Err(reason) => {
+                            println!("[Edits] Rejected edit {} of player {} at ({}, {}, {}) : {}", request.edit_id, player, request.pos.x, request.pos.y, request.pos.z, reason);
+                            let voxel = world_write.get_voxel_at(request.pos).cloned();
+                            engine.world.tunnels_out.send_event(GameMapEvent::VoxelEditRejected(VoxelEditRejected { player, edit_id: request.edit_id, pos: request.pos, reason, voxel }));
+                        }
+                    }
+                }
+            }
+            {
+                checksums::handle_resync_requests(session_messages.resync_requests, engine);
+                if checksum_interval > 0 && i % checksum_interval == 0 {
+                    checksums::send_checksums(engine, engine.extra_data.tick.load(Ordering::Relaxed));
+                }
+            }
+            if i % tickrate == 0 {
+                // drop chunks far from every vehicle and from the road head, which is always ahead of them
+                let engine_read = engine.vehicles.get_read();
+                let mut world_write = engine.world.world.write().unwrap();
+                engine.world.tunnels_out.send_event(GameMapEvent::RegistryHash(world_write.get_registry_hash()));
+                let mut anchors = Vec::with_capacity(engine_read.position.len() + 1);
+                for v in 0..engine_read.position.len() {
+                    anchors.push(engine_read.position[v].pos);
+                }
+                anchors.push(world_write.generator.get_head().component_product(&world_write.get_chunk_dims_vector_f()));
+                let far = world_write.get_chunks_farther_than(&anchors, view_distance as f32);
+                // the road never generates a chunk twice, chunks are only dropped once they are saved and can be loaded again
+                match storage {
+                    Some(storage) if !far.is_empty() => match storage.save_dirty(&mut world_write) {
+                        Ok(_) => {
+                            world_write.unload_chunks(&far);
+                            println!("Unloaded {} chunks", far.len());
+                            engine.world.tunnels_out.send_event(GameMapEvent::UnloadChunks(far));
+                        },
+                        Err(error) => println!("Could not save world, keeping far chunks loaded : {}", error)
+                    },
+                    _ => ()
+                }
+            }
             engine.extra_data.tick.fetch_add(1, Ordering::Relaxed);
             0
         };
@@ -196,14 +498,30 @@ pub fn server_func() {
             }
         }
         
-        scheduler.initialise(queue.clone());
-        scheduler.tick();
+        tick_scheduler();
+        if let Some(active) = recorder && let Err(error) = active.record_tick(engine, engine.extra_data.tick.load(Ordering::Relaxed)) {
+            println!("[Replay] Could not record tick {} : {}, recording stopped", i, error);
+            *recorder = None;
+        }
         let frametime = Instant::now().checked_duration_since(start).unwrap().as_secs_f64();
         let mut fps = 1.0/frametime;
         println!("FPS : {}", fps);
-        if fps > TICKRATE as f64 {
-            thread::sleep(Duration::from_secs_f64(1.0/(TICKRATE as f64) - frametime));
+        if fps > tickrate as f64 {
+            thread::sleep(Duration::from_secs_f64(1.0/(tickrate as f64) - frametime));
         }
     }
-    scheduler.end_threads();
+    match storage {
+        Some(storage) => match storage.save_dirty(&mut engine.world.world.write().unwrap()) {
+            Ok(saved) => println!("Saved {} chunks before exiting", saved),
+            Err(error) => println!("Could not save world : {}", error)
+        },
+        None => ()
+    }
+    match recorder.take() {
+        Some(recorder) => match recorder.finish() {
+            Ok(()) => (),
+            Err(error) => println!("[Replay] Could not finish the recording : {}", error)
+        },
+        None => ()
+    }
 }
\ No newline at end of file
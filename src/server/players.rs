@@ -0,0 +1,206 @@
+use std::{collections::HashMap, sync::atomic::Ordering};
+
+use hord3::horde::{game_engine::multiplayer::MustSync, geometry::{rotation::{Orientation, Rotation}, vec3d::{Vec3D, Vec3Df}}};
+
+use crate::{driver::{Collider, GameEntityEvent, Movement, NewGameEntity, actions::{ActionsEvent, ActionsUpdate}, colliders::AABB, director::{Director, DirectorEvent, DirectorKind, DirectorUpdate, llm_director::LLMDirector}, stats::{StatEvent, StatEventVariant, Stats}}, game_engine::CoolGameEngineBase, game_map::{GameMapEvent, players::{PlayerEvent, player_id}, session::SessionMessage, vehicle_states::VehicleState}, vehicle::{NewVehicleEntity, VehicleEntityEvent, position::{VehiclePosEvent, VehiclePosUpdate, VehiclePosition}, vehicle_stats::VehicleStats}};
+
+// players that didn't send a heartbeat for that many seconds have left
+const PLAYER_TIMEOUT_SECONDS:usize = 10;
+// spawn spots, PLAYERS_PER_ROW side by side then rows further back
+const PLAYERS_PER_ROW:usize = 4;
+const SPAWN_SPACING:f32 = 3.0;
+const SPAWN_ROW_SPACING:f32 = 6.0;
+const SPAWN_HEIGHT:f32 = 10.5;
+// vehicles players switched away from wait in a line there
+const PARKING_X:f32 = -40.0;
+
+pub struct PlayerSession {
+    pub name:String,
+    pub driver:usize,
+    pub vehicle:usize,
+    // spawn spot, freed when the player leaves
+    slot:usize,
+    last_heard:usize,
+    // latest input tick of the player seen in its vehicle, and the server tick it was seen at
+    last_input:(usize, usize),
+}
+
+// Driver entities and vehicles of connected players
+// entities can't be removed from the engine, so the ones of players who left are given to the next players joining,
+// unless leaving players are handed to an AI director
+pub struct PlayerManager {
+    // by public id of the player, see game_map::players
+    sessions:HashMap<u64, PlayerSession>,
+    free:Vec<(usize, usize)>,
+    // (vehicle, type) of vehicles nobody drives since their player picked another type
+    parked:Vec<(usize, usize)>,
+    max_players:usize,
+    timeout_ticks:usize,
+    leavers_to_ai:bool,
+}
+
+impl PlayerManager {
+    // free is the (driver, vehicle) pairs spawned with the world
+    pub fn new(free:Vec<(usize, usize)>, max_players:usize, tickrate:usize, leavers_to_ai:bool) -> Self {
+        Self { sessions:HashMap::with_capacity(max_players), free, parked:Vec::new(), max_players, timeout_ticks:PLAYER_TIMEOUT_SECONDS * tickrate, leavers_to_ai }
+    }
+    // driver of the session, None for an id the server doesn't know
+    pub fn get_driver(&self, id:u64) -> Option<usize> {
+        self.sessions.get(&id).map(|session| {session.driver})
+    }
+    pub fn player_count(&self) -> usize {
+        self.sessions.len()
+    }
+    pub fn get_sessions(&self) -> &HashMap<u64, PlayerSession> {
+        &self.sessions
+    }
+    // puts the player in a vehicle of that type where its current one is, the old one gets parked for the next player picking its type
+    pub fn change_vehicle(&mut self, id:u64, vehicle_type:usize, engine:&CoolGameEngineBase) {
+        let session = match self.sessions.get_mut(&id) {
+            Some(session) => session,
+            None => return
+        };
+        let (old_type, pos) = {
+            let vehicles = engine.vehicles.get_read();
+            (vehicles.stats[session.vehicle].static_id, vehicles.position[session.vehicle].pos)
+        };
+        if old_type == vehicle_type {
+            return;
+        }
+        let vehicle = match self.parked.iter().position(|(_, parked_type)| {*parked_type == vehicle_type}) {
+            Some(index) => {
+                let (vehicle, _) = self.parked.swap_remove(index);
+                move_vehicle(vehicle, pos, engine);
+                vehicle
+            },
+            None => spawn_vehicle(pos, vehicle_type, engine)
+        };
+        move_vehicle(session.vehicle, Vec3Df::new(PARKING_X, self.parked.len() as f32 * SPAWN_ROW_SPACING, SPAWN_HEIGHT), engine);
+        self.parked.push((session.vehicle, old_type));
+        let drivers = engine.entity_1.get_read();
+        drivers.tunnels.stats_out.send(GameEntityEvent::new(MustSync::Server, StatEvent::new(session.driver, None, StatEventVariant::UpdatePersonalVehicle(Some(vehicle)))));
+        println!("[Players] {} switched to vehicle {} of type {}", session.name, vehicle, vehicle_type);
+        session.vehicle = vehicle;
+        engine.world.tunnels_out.send_event(GameMapEvent::Session(SessionMessage::Player(PlayerEvent::Assigned { id, driver: session.driver, vehicle })));
+    }
+    pub fn handle_events(&mut self, events:Vec<PlayerEvent>, engine:&CoolGameEngineBase) {
+        let tick = engine.extra_data.tick.load(Ordering::Relaxed);
+        for event in events {
+            match event {
+                // only heartbeats carrying the token keep a session alive, anyone can send a join with an id they heard
+                PlayerEvent::Join { id, name } => match self.sessions.get(&id) {
+                    // the answer got lost, or was sent before the client could hear it
+                    Some(session) => engine.world.tunnels_out.send_event(GameMapEvent::Session(SessionMessage::Player(PlayerEvent::Assigned { id, driver: session.driver, vehicle: session.vehicle }))),
+                    None if self.sessions.len() >= self.max_players => {
+                        println!("[Players] {} can't join, the server is full ({} players)", name, self.max_players);
+                        engine.world.tunnels_out.send_event(GameMapEvent::Session(SessionMessage::Player(PlayerEvent::Refused(id))));
+                    },
+                    None => {
+                        let slot = self.free_slot();
+                        let (driver, vehicle) = self.spawn(&name, slot, engine);
+                        println!("[Players] {} joined, driver {} vehicle {}", name, driver, vehicle);
+                        self.sessions.insert(id, PlayerSession { name, driver, vehicle, slot, last_heard: tick, last_input: (0, tick) });
+                        engine.world.tunnels_out.send_event(GameMapEvent::Session(SessionMessage::Player(PlayerEvent::Assigned { id, driver, vehicle })));
+                        // the road the newcomer started with is only its seed
+                        let road = engine.world.world.read().unwrap().generator.clone();
+                        engine.world.tunnels_out.send_event(GameMapEvent::UpdateGenerator(road));
+                    }
+                },
+                PlayerEvent::Heartbeat(token) => match self.sessions.get_mut(&player_id(token)) {
+                    Some(session) => session.last_heard = tick,
+                    None => ()
+                },
+                PlayerEvent::Leave(token) => self.remove(player_id(token), engine),
+                // our own answers coming back
+                PlayerEvent::Assigned { .. } | PlayerEvent::Refused(_) | PlayerEvent::Left { .. } => ()
+            }
+        }
+    }
+    pub fn drop_silent_players(&mut self, engine:&CoolGameEngineBase) {
+        let tick = engine.extra_data.tick.load(Ordering::Relaxed);
+        let silent:Vec<u64> = self.sessions.iter().filter(|(_, session)| {tick.saturating_sub(session.last_heard) > self.timeout_ticks}).map(|(id, _)| {*id}).collect();
+        for id in silent {
+            println!("[Players] No news from {} for {} seconds", self.sessions[&id].name, PLAYER_TIMEOUT_SECONDS);
+            self.remove(id, engine);
+        }
+    }
+    // the client tick of a state is the latest input of the player plus the server ticks since it came
+    pub fn send_vehicle_states(&mut self, engine:&CoolGameEngineBase) {
+        let tick = engine.extra_data.tick.load(Ordering::Relaxed);
+        let vehicles = engine.vehicles.get_read();
+        for session in self.sessions.values_mut() {
+            let input_tick = vehicles.locomotion[session.vehicle].get_last_input_tick();
+            if input_tick != session.last_input.0 {
+                session.last_input = (input_tick, tick);
+            }
+            let state = VehicleState { vehicle: session.vehicle, input_tick: input_tick + tick.saturating_sub(session.last_input.1), server_tick: tick, position: vehicles.position[session.vehicle].clone(), locomotion: vehicles.locomotion[session.vehicle].clone() };
+            engine.world.tunnels_out.send_event(GameMapEvent::Session(SessionMessage::VehicleState(state)));
+        }
+    }
+    fn remove(&mut self, id:u64, engine:&CoolGameEngineBase) {
+        match self.sessions.remove(&id) {
+            Some(session) => {
+                let drivers = engine.entity_1.get_read();
+                drivers.tunnels.actions_out.send(GameEntityEvent::new(MustSync::Server, ActionsEvent::new(session.driver, None, ActionsUpdate::FlushActions)));
+                if self.leavers_to_ai {
+                    println!("[Players] {} left, an AI takes over driver {}", session.name, session.driver);
+                    let director = LLMDirector::new_with_goals(vec![format!("Keep driving along the road")]);
+                    drivers.tunnels.director_out.send(GameEntityEvent::new(MustSync::Server, DirectorEvent::new(session.driver, None, DirectorUpdate::UpdateKind(DirectorKind::LLM(director)))));
+                }
+                else {
+                    println!("[Players] {} left, driver {} and vehicle {} are free", session.name, session.driver, session.vehicle);
+                    drivers.tunnels.director_out.send(GameEntityEvent::new(MustSync::Server, DirectorEvent::new(session.driver, None, DirectorUpdate::UpdateKind(DirectorKind::Nothing))));
+                    self.free.push((session.driver, session.vehicle));
+                }
+                engine.world.tunnels_out.send_event(GameMapEvent::Session(SessionMessage::Player(PlayerEvent::Left { id, driver: session.driver, vehicle: session.vehicle })));
+            },
+            None => ()
+        }
+    }
+    // the lowest spawn spot no connected player took
+    fn free_slot(&self) -> usize {
+        (0..).find(|slot| {!self.sessions.values().any(|session| {session.slot == *slot})}).unwrap()
+    }
+    fn spawn_position(slot:usize) -> Vec3Df {
+        let (row, column) = (slot / PLAYERS_PER_ROW, slot % PLAYERS_PER_ROW);
+        Vec3Df::new(-(row as f32) * SPAWN_ROW_SPACING, (column as f32 - (PLAYERS_PER_ROW - 1) as f32 * 0.5) * SPAWN_SPACING, SPAWN_HEIGHT)
+    }
+    // reuses free entities first, spawning new ones synced to every client otherwise
+    fn spawn(&mut self, name:&String, slot:usize, engine:&CoolGameEngineBase) -> (usize, usize) {
+        let pos = Self::spawn_position(slot);
+        match self.free.pop() {
+            Some((driver, vehicle)) => {
+                let drivers = engine.entity_1.get_read();
+                drivers.tunnels.director_out.send(GameEntityEvent::new(MustSync::Server, DirectorEvent::new(driver, None, DirectorUpdate::UpdateKind(DirectorKind::Player))));
+                move_vehicle(vehicle, pos, engine);
+                (driver, vehicle)
+            },
+            None => {
+                let vehicle = spawn_vehicle(pos, 0, engine);
+                let driver = {
+                    let mut writer = engine.entity_1.get_write();
+                    let driver = writer.movement.len();
+                    let driver_pos = pos + Vec3D::new(0.0, 0.0, 2.0);
+                    writer.new_ent(NewGameEntity::new(Movement{against_wall:false, touching_ground:false,pos:driver_pos, speed:Vec3D::zero(), orient:Orientation::zero(), rotat:Rotation::from_orientation(Orientation::zero())}, Stats {static_type_id:1, health:0, damage:0, stamina:0, ground_speed:0.2, jump_height:1.0, personal_vehicle:Some(vehicle)}, Collider{team:0, collider:AABB::new(driver_pos - Vec3D::all_ones() * 0.5, driver_pos + Vec3D::all_ones() * 0.5)}, Director::new(DirectorKind::Player, name.clone()), MustSync::Server, None));
+                    driver
+                };
+                (driver, vehicle)
+            }
+        }
+    }
+}
+
+// stopped, facing +x
+fn move_vehicle(vehicle:usize, pos:Vec3Df, engine:&CoolGameEngineBase) {
+    let vehicles = engine.vehicles.get_read();
+    vehicles.tunnels.position_out.send(VehicleEntityEvent::new(MustSync::Server, VehiclePosEvent::new(vehicle, None, VehiclePosUpdate::UpdateEveryPos(pos, Orientation::zero()))));
+    vehicles.tunnels.position_out.send(VehicleEntityEvent::new(MustSync::Server, VehiclePosEvent::new(vehicle, None, VehiclePosUpdate::UpdateEverySpeed(Vec3Df::zero(), Orientation::zero()))));
+}
+
+// synced to every client
+fn spawn_vehicle(pos:Vec3Df, vehicle_type:usize, engine:&CoolGameEngineBase) -> usize {
+    let mut writer = engine.vehicles.get_write();
+    let vehicle = writer.position.len();
+    writer.new_ent(NewVehicleEntity::new(VehiclePosition::new().with_pos(pos), VehicleStats {static_id:vehicle_type, nitro_left:100.0, mass:10.0}, MustSync::Server, None));
+    vehicle
+}
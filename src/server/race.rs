@@ -0,0 +1,209 @@
+use std::{cmp::Ordering as CmpOrdering, collections::HashMap, sync::atomic::Ordering};
+
+use hord3::horde::geometry::vec3d::Vec3Df;
+
+use crate::{game_engine::CoolGameEngineBase, game_map::{GameMapEvent, session::SessionMessage, race::{LeaderboardEntry, RaceEvent, RacePosition, RaceStandings, format_race_time}, road::Road}, server::players::PlayerManager};
+
+// live standings are sent that many times per second
+const STANDINGS_PER_SECOND:usize = 4;
+// segments looked at around the last one of a racer, a road passing close to itself must not move racers along it
+const SEARCH_BEHIND:usize = 2;
+const SEARCH_AHEAD:usize = 6;
+
+// Where checkpoints and the finish are along the road, in segments
+#[derive(Clone, Debug)]
+pub struct RaceCourse {
+    pub checkpoint_segments:usize,
+    // segments in a lap, None for an endless race
+    pub lap_segments:Option<usize>,
+    pub laps:usize,
+    // the road comes back to its first segment after the last one of a lap
+    pub closed:bool,
+}
+
+impl RaceCourse {
+    // checkpoints at every checkpoint_segments segments of a lap, its finish line isn't one
+    pub fn checkpoints_per_lap(&self) -> usize {
+        match self.lap_segments {
+            Some(segments) => segments.saturating_sub(1) / self.checkpoint_segments,
+            None => 0
+        }
+    }
+}
+
+struct Racer {
+    start_tick:usize,
+    // closest segment last time the racer was on the road, None before it crossed the start line
+    segment:Option<usize>,
+    lap:usize,
+    // passed in the current lap
+    checkpoint:usize,
+    distance:f32,
+    finish_tick:Option<usize>,
+}
+
+impl Racer {
+    fn new(start_tick:usize) -> Self {
+        Self { start_tick, segment:None, lap:0, checkpoint:0, distance:0.0, finish_tick:None }
+    }
+}
+
+// Follows every racer along the road : checkpoints, laps and finish, live positions and the leaderboard
+// racers are the players connected when the race starts, or everyone joining with an open entry
+pub struct RaceTracker {
+    course:RaceCourse,
+    tickrate:usize,
+    racers:HashMap<u64, Racer>,
+    // in finishing order, finishers who left stay on it
+    finishers:Vec<LeaderboardEntry>,
+    open_entry:bool,
+    running:bool,
+    complete_sent:bool,
+}
+
+impl RaceTracker {
+    pub fn new(course:RaceCourse, tickrate:usize) -> Self {
+        Self { course, tickrate, racers:HashMap::with_capacity(16), finishers:Vec::new(), open_entry:false, running:false, complete_sent:false }
+    }
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+    pub fn start(&mut self, players:&PlayerManager, road:&Road, tick:usize, open_entry:bool) {
+        // a lap of a closed track is the whole road, the finish of an open one is at the start of its last segment
+        if road.is_finite() {
+            self.course.lap_segments = Some(if road.is_closed() {road.segment_count()} else {road.segment_count() - 1});
+            self.course.closed = road.is_closed();
+        }
+        self.racers = players.get_sessions().keys().map(|id| {(*id, Racer::new(tick))}).collect();
+        self.finishers.clear();
+        self.open_entry = open_entry;
+        self.running = true;
+        self.complete_sent = false;
+        match self.course.lap_segments {
+            Some(segments) => println!("[Race] Started with {} racers, {} lap(s) of {} segments, a checkpoint every {} segments", self.racers.len(), self.course.laps, segments, self.course.checkpoint_segments),
+            None => println!("[Race] Started with {} racers, endless road, a checkpoint every {} segments", self.racers.len(), self.course.checkpoint_segments)
+        }
+    }
+    pub fn stop(&mut self) {
+        self.running = false;
+        self.racers.clear();
+    }
+    // once per tick, after the players and the lobby
+    pub fn update(&mut self, players:&PlayerManager, engine:&CoolGameEngineBase) {
+        if !self.running {
+            return;
+        }
+        let tick = engine.extra_data.tick.load(Ordering::Relaxed);
+        let sessions = players.get_sessions();
+        self.racers.retain(|id, _| {sessions.contains_key(id)});
+        if self.open_entry {
+            for id in sessions.keys() {
+                self.racers.entry(*id).or_insert_with(|| {Racer::new(tick)});
+            }
+        }
+        let per_lap = self.course.checkpoints_per_lap();
+        let vehicles = engine.vehicles.get_read();
+        let world_read = engine.world.world.read().unwrap();
+        let road = &world_read.generator;
+        for (id, racer) in &mut self.racers {
+            if racer.finish_tick.is_some() {
+                continue;
+            }
+            let session = &sessions[id];
+            let pos = vehicles.position[session.vehicle].pos;
+            // off the road, the racer keeps its last progress
+            let segment = match find_segment(&self.course, road, pos, racer.segment) {
+                Some(segment) => segment,
+                None => continue
+            };
+            let previous = racer.segment.replace(segment);
+            while (self.course.lap_segments.is_none() || racer.checkpoint < per_lap) && segment >= (racer.checkpoint + 1) * self.course.checkpoint_segments {
+                racer.checkpoint += 1;
+                let time_ms = ticks_to_ms(tick - racer.start_tick, self.tickrate);
+                engine.world.tunnels_out.send_event(GameMapEvent::Session(SessionMessage::Race(RaceEvent::Checkpoint { driver:session.driver, lap:racer.lap + 1, checkpoint:racer.checkpoint, time_ms })));
+                println!("[Race] {} passed checkpoint {} of lap {} at {}", session.name, racer.checkpoint, racer.lap + 1, format_race_time(time_ms));
+            }
+            let lap_done = match self.course.lap_segments {
+                Some(segments) if racer.checkpoint == per_lap => if self.course.closed {
+                    // back at the start of the loop
+                    previous.is_some_and(|previous| {previous + SEARCH_AHEAD >= segments}) && segment < SEARCH_AHEAD
+                }
+                else {
+                    segment >= segments
+                },
+                _ => false
+            };
+            if lap_done {
+                racer.lap += 1;
+                racer.checkpoint = 0;
+                let time_ms = ticks_to_ms(tick - racer.start_tick, self.tickrate);
+                if racer.lap >= self.course.laps {
+                    racer.finish_tick = Some(tick);
+                    let place = self.finishers.len() + 1;
+                    println!("[Race] {} finished in place {} in {}", session.name, place, format_race_time(time_ms));
+                    self.finishers.push(LeaderboardEntry { place, name:session.name.clone(), driver:session.driver, time_ms });
+                    engine.world.tunnels_out.send_event(GameMapEvent::Session(SessionMessage::Race(RaceEvent::Leaderboard { entries:self.finishers.clone(), complete:false })));
+                }
+                else {
+                    println!("[Race] {} finished lap {} at {}", session.name, racer.lap, format_race_time(time_ms));
+                }
+            }
+            racer.distance = match self.course.lap_segments {
+                Some(segments) if self.course.closed => racer.lap as f32 * road.length_of(segments) + road.distance_along(segment, pos),
+                _ => road.distance_along(segment, pos)
+            };
+        }
+        drop(world_read);
+        if self.course.lap_segments.is_some() && !self.complete_sent && !self.racers.is_empty() && self.racers.values().all(|racer| {racer.finish_tick.is_some()}) {
+            println!("[Race] Everyone finished :");
+            for entry in &self.finishers {
+                println!("[Race] {}. {} in {}", entry.place, entry.name, format_race_time(entry.time_ms));
+            }
+            engine.world.tunnels_out.send_event(GameMapEvent::Session(SessionMessage::Race(RaceEvent::Leaderboard { entries:self.finishers.clone(), complete:true })));
+            self.complete_sent = true;
+        }
+        if tick % (self.tickrate / STANDINGS_PER_SECOND).max(1) == 0 {
+            let standings = self.get_standings(players, tick);
+            engine.world.tunnels_out.send_event(GameMapEvent::Session(SessionMessage::Race(RaceEvent::Standings(standings))));
+        }
+    }
+    fn get_standings(&self, players:&PlayerManager, tick:usize) -> RaceStandings {
+        let sessions = players.get_sessions();
+        let mut positions:Vec<RacePosition> = self.racers.iter().map(|(id, racer)| {
+            let session = &sessions[id];
+            RacePosition { name:session.name.clone(), driver:session.driver, vehicle:session.vehicle, lap:racer.lap, checkpoint:racer.checkpoint, distance:racer.distance, finish_time_ms:racer.finish_tick.map(|finish| {ticks_to_ms(finish - racer.start_tick, self.tickrate)}) }
+        }).collect();
+        // finishers by time, then the others by how far they got
+        positions.sort_by(|a, b| {
+            match (a.finish_time_ms, b.finish_time_ms) {
+                (Some(a_time), Some(b_time)) => a_time.cmp(&b_time),
+                (Some(_), None) => CmpOrdering::Less,
+                (None, Some(_)) => CmpOrdering::Greater,
+                (None, None) => b.distance.total_cmp(&a.distance)
+            }
+        });
+        RaceStandings { tick, laps:if self.course.lap_segments.is_some() {self.course.laps} else {0}, checkpoints_per_lap:self.course.checkpoints_per_lap(), positions }
+    }
+}
+
+fn find_segment(course:&RaceCourse, road:&Road, pos:Vec3Df, last:Option<usize>) -> Option<usize> {
+    match last {
+        Some(last) => {
+            let end = last + SEARCH_AHEAD + 1;
+            match road.closest_segment_in(pos, last.saturating_sub(SEARCH_BEHIND)..end) {
+                Some(segment) => Some(segment),
+                // the next lap of a loop starts at segment 0
+                None => match course.lap_segments {
+                    Some(segments) if course.closed && end > segments => road.closest_segment_in(pos, 0..end - segments),
+                    _ => None
+                }
+            }
+        },
+        // the grid is behind the start line, racers are found once they crossed it
+        None => road.closest_segment_in(pos, 0..SEARCH_AHEAD + 1)
+    }
+}
+
+fn ticks_to_ms(ticks:usize, tickrate:usize) -> u64 {
+    (ticks as u64 * 1000) / tickrate as u64
+}
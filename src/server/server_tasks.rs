@@ -1,4 +1,4 @@
-use hord3::{defaults::{default_rendering::vectorinator_binned::{Vectorinator}, default_ui::simple_ui::{SimpleUI, UserEvent}}, horde::{frontend::WindowingHandler, scheduler::{HordeTask, HordeTaskData, HordeTaskHandler, IndividualTask}, sound::ARWWaves}};
+use hord3::{defaults::{default_rendering::vectorinator_binned::{Vectorinator}, default_ui::simple_ui::{SimpleUI, UserEvent}}, horde::{scheduler::{HordeTask, HordeTaskData, HordeTaskHandler, IndividualTask}, sound::ARWWaves}};
 use task_derive::HordeTask;
 
 use crate::{cutscene::game_shader::GameShader, game_engine::{CoolGameEngine, CoolGameEngineBase}};
@@ -49,18 +49,6 @@ pub enum ServerTask {
     #[max_threads = 1]
     #[type_task_id = 13]
     MultiFourthPart,
-
-
-    #[uses_type = "WindowingHandler"]
-    #[max_threads = 1]
-    #[type_task_id = 0]
-    SendFramebuf,
-
-    #[uses_type = "WindowingHandler"]
-    #[max_threads = 1]
-    #[type_task_id = 1]
-    WaitForPresent,
-    
 }
 
 #[derive(Clone, Hash, PartialEq, Eq)]
@@ -0,0 +1,60 @@
+use std::collections::{HashMap, VecDeque};
+
+use hord3::horde::geometry::vec3d::Vec3Df;
+
+use crate::{driver::GameEntityVecRead, game_engine::{CoolGameEngineTID, CoolVoxel}, game_map::{GameMap, get_float_pos, edits::{EditRejection, VoxelEditRequest}, road::Road}, vehicle::VehicleEntityVecRead};
+
+// Checks every voxel edit asked by a client before the server applies it
+// requests carry the secret session token of the player, its driver is the one the server gave to that session, never one a client names
+pub struct VoxelEditValidator {
+    // in voxels, from the player's driver or its vehicle to the center of the edited voxel
+    reach:f32,
+    max_edits_per_second:usize,
+    tickrate:usize,
+    // ticks of the latest edits of every player, at most max_edits_per_second of them
+    recent_edits:HashMap<usize, VecDeque<usize>>,
+}
+
+impl VoxelEditValidator {
+    pub fn new(reach:f32, max_edits_per_second:usize, tickrate:usize) -> Self {
+        Self { reach, max_edits_per_second, tickrate, recent_edits:HashMap::with_capacity(8) }
+    }
+    // player is the driver of the session the request comes from
+    pub fn check<'a>(&mut self, request:&VoxelEditRequest<CoolVoxel>, player:usize, drivers:&GameEntityVecRead<'a, CoolGameEngineTID>, vehicles:&VehicleEntityVecRead<'a, CoolGameEngineTID>, world:&GameMap<CoolVoxel, Road>, tick:usize) -> Result<(), EditRejection> {
+        if player >= drivers.movement.len() {
+            return Err(EditRejection::UnknownPlayer);
+        }
+        self.count_edit(player, tick)?;
+        if request.voxel.voxel_type as usize >= world.get_voxel_types().len() {
+            return Err(EditRejection::UnknownVoxelType);
+        }
+        if world.get_voxel_at(request.pos).is_none() {
+            return Err(EditRejection::ChunkNotLoaded);
+        }
+        let center = get_float_pos(request.pos) + Vec3Df::all_ones() * 0.5;
+        let mut distance = drivers.movement[player].pos.dist(&center);
+        match drivers.stats[player].personal_vehicle {
+            Some(vehicle) if vehicle < vehicles.position.len() => distance = distance.min(vehicles.position[vehicle].pos.dist(&center)),
+            _ => ()
+        }
+        if distance > self.reach {
+            return Err(EditRejection::OutOfReach);
+        }
+        if world.generator.is_protected(center) {
+            return Err(EditRejection::Protected);
+        }
+        Ok(())
+    }
+    // every request counts, accepted or not, so spamming rejected edits is limited too
+    fn count_edit(&mut self, player:usize, tick:usize) -> Result<(), EditRejection> {
+        let edits = self.recent_edits.entry(player).or_insert_with(|| {VecDeque::with_capacity(self.max_edits_per_second)});
+        while edits.front().is_some_and(|oldest| {tick.saturating_sub(*oldest) >= self.tickrate}) {
+            edits.pop_front();
+        }
+        if edits.len() >= self.max_edits_per_second {
+            return Err(EditRejection::RateLimited);
+        }
+        edits.push_back(tick);
+        Ok(())
+    }
+}
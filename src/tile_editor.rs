@@ -3,26 +3,20 @@ use std::{collections::{HashMap, HashSet, VecDeque}, f32::consts::{PI, SQRT_2},
 use cosmic_text::{Color, Metrics};
 use hord3::{defaults::{default_rendering::vectorinator_binned::{meshes::{Mesh, MeshID, MeshInstance, MeshLODS, MeshLODType}, shaders::NoOpShader, textures::Textures, Vectorinator}, default_ui::simple_ui::{SimpleUI, SimpleUISave, TextCentering, UIElement, UIElementBackground, UIElementContent, UIElementID}}, horde::{frontend::{interact::Button, MouseState}, game_engine::{multiplayer::Identify, world::WorldTunnelsOut}, geometry::{rotation::Orientation, vec3d::{Vec3D, Vec3Df}}, rendering::camera::Camera}};
 
-use crate::{client::client_tasks::GameUserEvent, cutscene::game_shader::GameShader, game_3d_models::{lit_selection_cube, selection_cube}, game_engine::{CoolGameEngineTID, CoolVoxel, CoolVoxelType}, game_input_handler::GameInputHandler, game_map::{GameMap, GameMapEvent, Voxel, VoxelLight, WorldChunkPos, WorldVoxelPos, get_chunk_pos_i, get_float_pos, get_voxel_pos, light_spreader::{LightPos, LightSpread}, raycaster::Ray, road::Road}, gui_elements::{editor_gui_elements::{light_spreader_elts, voxel_type_choice}, list_choice}, vehicle::locomotion::{SurfaceSubType, SurfaceType}};
+use crate::{client::client_tasks::GameUserEvent, cutscene::game_shader::GameShader, game_3d_models::{lit_selection_cube, selection_cube}, game_engine::{CoolGameEngineTID, CoolVoxel, CoolVoxelType}, game_input_handler::GameInputHandler, game_map::{GameMap, GameMapEvent, Voxel, edits::{VoxelEditRejected, VoxelEditRequest}, VoxelLight, WorldChunkPos, WorldVoxelPos, closest_dir, get_chunk_pos_i, orientation_from, quarter_turns_towards, get_float_pos, get_voxel_pos, light_spreader::{LightPos, LightSpread}, raycaster::Ray, road::Road}, gui_elements::{editor_gui_elements::{light_spreader_elts, voxel_type_choice}, list_choice}, voxel_registry::voxel_registry};
 
 
 pub const CHUNK_SIZE:usize = 8;
 pub const CHUNK_SIZE_F:f32 = CHUNK_SIZE as f32;
 
+// see voxels.json
 pub fn get_tile_voxels() -> Vec<CoolVoxelType> {
-    vec![
-        CoolVoxelType::new(0b00111111, 0, VoxelLight::new(247, 255, 255, 255), None, "Air".to_string(), Some(PathBuf::from("textures/arbre.png")), None, SurfaceType::Air, SurfaceSubType::Smooth),
-        CoolVoxelType::new(0, 1, VoxelLight::zero_light(), None, "Sand".to_string(), Some(PathBuf::from("textures/sable.png")), None, SurfaceType::Ground, SurfaceSubType::Smooth),
-        CoolVoxelType::new(0, 2, VoxelLight::zero_light(), None, "Flowers".to_string(), Some(PathBuf::from("textures/terre_herbe.png")), None, SurfaceType::Ground, SurfaceSubType::Rough),
-        CoolVoxelType::new(0, 3, VoxelLight::zero_light(), None, "Grassy Ground".to_string(), Some(PathBuf::from("textures/terre_cail.png")), None, SurfaceType::Ground, SurfaceSubType::Jagged),
-        CoolVoxelType::new(0, 4, VoxelLight::zero_light(), None, "Ground".to_string(), Some(PathBuf::from("textures/terre.png")), None, SurfaceType::Ground, SurfaceSubType::Rough),
-        CoolVoxelType::new(0, 5, VoxelLight::zero_light(), None, "Rock".to_string(), Some(PathBuf::from("textures/roche.png")), None, SurfaceType::Ground, SurfaceSubType::Jagged),
-        CoolVoxelType::new(0, 0, VoxelLight::zero_light(), None, "Snow".to_string(), Some(PathBuf::from("textures/neige.png")), None, SurfaceType::Ground, SurfaceSubType::Smooth),
-        CoolVoxelType::new(0, 6, VoxelLight::zero_light(), None, "Water".to_string(), Some(PathBuf::from("textures/eau.png")), None, SurfaceType::Water, SurfaceSubType::Smooth),
-        CoolVoxelType::new(0, 7, VoxelLight::zero_light(), None, "Deep Water".to_string(), Some(PathBuf::from("textures/eau_prof.png")), None, SurfaceType::Water, SurfaceSubType::Rough),
-        CoolVoxelType::new(0, 8, VoxelLight::zero_light(), None, "Metal".to_string(), Some(PathBuf::from("textures/metal_0.png")), None, SurfaceType::Ground, SurfaceSubType::Industrial),
-        CoolVoxelType::new(0, 3, VoxelLight::zero_light(), None, "Text Test".to_string(), None, None, SurfaceType::Ground, SurfaceSubType::Industrial),
-    ]
+    voxel_registry().get_voxel_types()
+}
+
+// placed voxels have their underside against the surface being looked at, and are turned to the nearest quarter of the camera's heading
+pub fn orientation_from_view(view:Vec3Df) -> u8 {
+    orientation_from(closest_dir(view), quarter_turns_towards(view))
 }
 
 pub fn get_selector_cube_mesh() -> Mesh {
@@ -74,7 +68,8 @@ impl TileEditingTool {
     }
     pub fn handle_mouse_state(&mut self, editor_data:&mut TileEditorData, chunks:&mut GameMap<CoolVoxel, Road>, tunnels:WorldTunnelsOut<GameMap<CoolVoxel, Road>, CoolGameEngineTID>) -> Self {
         editor_data.mouse_state.update_local();
-        let ray = Ray::new(editor_data.cam.pos, Orientation::new(editor_data.cam.orient.yaw - PI/2.0, editor_data.cam.orient.roll - PI/8.0, 0.0).into_vec(), Some(100.0));
+        let view = Orientation::new(editor_data.cam.orient.yaw - PI/2.0, editor_data.cam.orient.roll - PI/8.0, 0.0).into_vec();
+        let ray = Ray::new(editor_data.cam.pos, view, Some(100.0));
         match self {
             TileEditingTool::PlaceAndDestroy {chosen, empty_voxel } => {
                 if editor_data.mouse_state.get_deltas_and_scroll().left >= 2 { // Destroy
@@ -93,7 +88,7 @@ impl TileEditingTool {
 
                                 let mut new_voxel = voxel.clone();
                                 new_voxel.voxel_type = *empty_voxel as u16;
-                                tunnels.send_event(GameMapEvent::UpdateVoxelAt(get_voxel_pos(end.end), new_voxel));
+                                editor_data.request_edit(&tunnels, get_voxel_pos(end.end), new_voxel);
                                 //println!("TEST");
                             }
                         },
@@ -122,7 +117,8 @@ impl TileEditingTool {
                                 // chunks.modified_this_pos_signal_remesh(get_voxel_pos(end.end));
                                 let mut new_voxel = voxel.clone();
                                 new_voxel.voxel_type = *chosen as u16;
-                                tunnels.send_event(GameMapEvent::UpdateVoxelAt(get_voxel_pos(end.end), new_voxel));
+                                new_voxel.orient = orientation_from_view(view);
+                                editor_data.request_edit(&tunnels, get_voxel_pos(end.end), new_voxel);
                             }
                             //println!("TEST");
                         },
@@ -433,7 +429,10 @@ pub struct TileEditorData {
     tools:HashMap<String, TileEditingTool>,
     chosen_tool:String,
     ui:SimpleUI<GameUserEvent>,
-    action_queue:VecDeque<EditorAction>
+    action_queue:VecDeque<EditorAction>,
+    // session token and driver entity of this client, edits are asked to the server with the token, None until the server gave us a driver
+    player:Option<(u64, usize)>,
+    next_edit_id:u32,
 }
 
 pub enum EditorAction {
@@ -446,7 +445,7 @@ impl EditorAction {
     pub fn reverse_action(self, editor_data:&mut TileEditorData, chunks:&mut GameMap<CoolVoxel, Road>, tunnels:WorldTunnelsOut<GameMap<CoolVoxel, Road>, CoolGameEngineTID>) {
         match self {
             EditorAction::ModifyVoxel { position, previous_state } => {
-                tunnels.send_event(GameMapEvent::UpdateVoxelAt(position, previous_state));
+                editor_data.request_edit(&tunnels, position, previous_state);
             },
             EditorAction::ChooseTiles { position, added } => {
                 if added {
@@ -458,7 +457,7 @@ impl EditorAction {
             },
             EditorAction::ModifyVoxels { positions_previous } => {
                 for (position, previous_state) in positions_previous {
-                    tunnels.send_event(GameMapEvent::UpdateVoxelAt(position, previous_state));
+                    editor_data.request_edit(&tunnels, position, previous_state);
                 }
             }
         }
@@ -477,7 +476,34 @@ impl TileEditorData {
             tools:HashMap::from([("LightSpreader".to_string(), TileEditingTool::MakeLight { strength: 255, color: (255,255,255) }),("TerrainModifier".to_string(), TileEditingTool::PlaceAndDestroy { chosen: 0, empty_voxel: 0 }), ("TileChooser".to_string(), TileEditingTool::ChooseTileChunks), ("TerrainZoneModifier".to_string(), TileEditingTool::ZonedPlaceAndDestroy { chosen: 0, empty_voxel: 0, start: Vec3D::zero(), end: Vec3D::zero(), action:None })]),
             chosen_tool: "TerrainModifier".to_string(),
             ui,
-            action_queue:VecDeque::with_capacity(128)
+            action_queue:VecDeque::with_capacity(128),
+            player:None,
+            next_edit_id:0,
+        }
+    }
+    // voxel edits go through the server, that can refuse them (see game_map::edits)
+    fn request_edit(&mut self, tunnels:&WorldTunnelsOut<GameMap<CoolVoxel, Road>, CoolGameEngineTID>, pos:WorldVoxelPos, voxel:CoolVoxel) {
+        match self.player {
+            Some((token, _)) => {
+                tunnels.send_event(GameMapEvent::RequestVoxelEdit(VoxelEditRequest { token, edit_id: self.next_edit_id, pos, voxel }));
+                self.next_edit_id = self.next_edit_id.wrapping_add(1);
+            },
+            None => println!("[Edits] Not in the game yet, the edit at ({}, {}, {}) is ignored", pos.x, pos.y, pos.z)
+        }
+    }
+    pub fn set_player(&mut self, player:Option<(u64, usize)>) {
+        self.player = player;
+    }
+    // the voxels are already back to the server's ones, refused edits just can't be undone anymore
+    pub fn handle_edit_rejections(&mut self, rejections:Vec<VoxelEditRejected<CoolVoxel>>) {
+        for rejected in rejections {
+            if self.player.is_some_and(|(_, driver)| {driver == rejected.player}) {
+                println!("[Edits] The server refused edit {} at ({}, {}, {}) : {}", rejected.edit_id, rejected.pos.x, rejected.pos.y, rejected.pos.z, rejected.reason);
+                match self.action_queue.iter().rposition(|action| {matches!(action, EditorAction::ModifyVoxel { position, .. } if *position == rejected.pos)}) {
+                    Some(index) => {self.action_queue.remove(index);},
+                    None => ()
+                }
+            }
         }
     }
     pub fn do_mouse_handling(&mut self, chunks: &mut GameMap<CoolVoxel, Road>, tunnels:WorldTunnelsOut<GameMap<CoolVoxel, Road>, CoolGameEngineTID>) {
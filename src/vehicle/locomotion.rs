@@ -1,6 +1,7 @@
 use std::{collections::HashMap, fmt::Debug, sync::{LazyLock, mpmc::Sender}};
 
 use hord3::horde::{game_engine::{entity::{Component, ComponentEvent, SimpleComponentEvent, StaticComponent}, multiplayer::{Identify, MustSync}, world::WorldComputeHandler}, geometry::{rotation::{Orientation, Rotation}, vec3d::{Coord, Vec3Df}}};
+use serde::{Deserialize, Serialize};
 use to_from_bytes_derive::{FromBytes, ToBytes};
 
 use crate::{driver::{actions::{Action, ActionKind, ActionResult}, colliders::BoundingCollider}, game_engine::{AIR_RESISTANCE, CoolGameEngineTID, CoolVoxel, GRAVITY, TURN_RESISTANCE, get_nudge_to_nearest_next_whole}, game_map::{GameMap, VoxelLight, VoxelType, get_minimum_nudge, get_voxel_pos, raycaster::{Curve, Ray, get_closest_ground_collision_to}, road::Road}, vehicle::{StaticVehicleEntity, VehicleEntityEvent, hull::HullUpdate, position::{VehiclePosEvent, VehiclePosUpdate, VehiclePosition}, vehicle_stats::VehicleStats}};
@@ -32,11 +33,16 @@ static DEFAULT_COEFS:LazyLock<HashMap<(SurfaceType, SurfaceSubType), SurfaceCoef
 pub struct Locomotion {
     pub equipment:Vec<LocomotionEquipment>,
     driver_actions:Vec<(Action, ActionResult)>,
+    // tick the latest driver action was started at by its driver, for the server to tell clients which of their inputs it used
+    last_input_tick:usize,
 }
 
 impl Locomotion {
     pub fn new(equipment:Vec<LocomotionEquipment>) -> Self {
-        Self { equipment, driver_actions:vec![] }
+        Self { equipment, driver_actions:vec![], last_input_tick:0 }
+    }
+    pub fn get_last_input_tick(&self) -> usize {
+        self.last_input_tick
     }
     pub fn compute_vehicle_and_locomotion_changes(
         &self,
@@ -48,6 +54,21 @@ impl Locomotion {
         loco_events:&Sender<VehicleEntityEvent<LocomotionEvent<CoolGameEngineTID>>>,
         pos_events:&Sender<VehicleEntityEvent<VehiclePosEvent<CoolGameEngineTID>>>
     ) {
+        let (new_eqs, vehicle_spd_change, vehicle_turn_spd_change) = self.get_vehicle_and_locomotion_changes(static_locomotion, world, vehicle_stats, vehicle_position);
+        loco_events.send(VehicleEntityEvent::new(MustSync::Server, LocomotionEvent::new(self_id, None, LocomotionUpdate::UpdateEverything(new_eqs)))).unwrap();
+        if self.driver_actions.len() > 0 {
+            loco_events.send(VehicleEntityEvent::new(MustSync::Server, LocomotionEvent::new(self_id, None, LocomotionUpdate::FlushActions))).unwrap();
+        }
+        pos_events.send(VehicleEntityEvent::new(MustSync::Server,VehiclePosEvent::new(self_id, None, VehiclePosUpdate::AddToEverySpeed(vehicle_spd_change, vehicle_turn_spd_change)))).unwrap();
+    }
+    // new equipment, vehicle speed change and turn speed change, without going through the engine (see client::prediction)
+    pub fn get_vehicle_and_locomotion_changes(
+        &self,
+        static_locomotion:&StaticLocomotion,
+        world:&GameMap<CoolVoxel, Road>,
+        vehicle_stats:&VehicleStats,
+        vehicle_position:&VehiclePosition,
+    ) -> (Vec<LocomotionEquipment>, Vec3Df, Orientation) {
         let mut vehicle_spd_change = Vec3Df::zero();
         let mut vehicle_turn_spd_change = Orientation::zero();
         let vehicle_rotat = Rotation::from_orientation(vehicle_position.orientation);
@@ -98,11 +119,7 @@ impl Locomotion {
         
         vehicle_spd_change += final_spd_add;
         //dbg!(vehicle_spd_change, vehicle_turn_spd_change);
-        loco_events.send(VehicleEntityEvent::new(MustSync::Server, LocomotionEvent::new(self_id, None, LocomotionUpdate::UpdateEverything(new_eqs)))).unwrap();
-        if self.driver_actions.len() > 0 {
-            loco_events.send(VehicleEntityEvent::new(MustSync::Server, LocomotionEvent::new(self_id, None, LocomotionUpdate::FlushActions))).unwrap();
-        }
-        pos_events.send(VehicleEntityEvent::new(MustSync::Server,VehiclePosEvent::new(self_id, None, VehiclePosUpdate::AddToEverySpeed(vehicle_spd_change, vehicle_turn_spd_change)))).unwrap();
+        (new_eqs, vehicle_spd_change, vehicle_turn_spd_change)
     }
     pub fn compute_vehicle_physics(&self,
         self_id:usize,
@@ -115,6 +132,21 @@ impl Locomotion {
         pos_events:&Sender<VehicleEntityEvent<VehiclePosEvent<CoolGameEngineTID>>>,
         loco_events:&Sender<VehicleEntityEvent<LocomotionEvent<CoolGameEngineTID>>>,
     ) {
+        let (new_vehicle_spd, new_vehicle_turn_spd, equipment_pos_adds) = self.get_vehicle_physics(static_locomotion, world, vehicle_stats, vehicle_position);
+        pos_events.send(VehicleEntityEvent::new(MustSync::Server, VehiclePosEvent::new(self_id, Some(CoolGameEngineTID::vehicles(self_id)), VehiclePosUpdate::UpdateEveryPos(vehicle_position.pos + new_vehicle_spd, vehicle_position.orientation + new_vehicle_turn_spd)))).unwrap();
+        pos_events.send(VehicleEntityEvent::new(MustSync::Server, VehiclePosEvent::new(self_id, Some(CoolGameEngineTID::vehicles(self_id)), VehiclePosUpdate::UpdateEverySpeed(new_vehicle_spd, new_vehicle_turn_spd)))).unwrap();
+
+        collider_events.send(VehicleEntityEvent::new(MustSync::Server,SimpleComponentEvent::new(self_id, None, HullUpdate::UpdateCollider(static_type.hull.base_collider.get_moved(vehicle_position.pos + new_vehicle_spd, vehicle_position.orientation + new_vehicle_turn_spd))))).unwrap();
+
+        loco_events.send(VehicleEntityEvent::new(MustSync::Server, LocomotionEvent::new(self_id, None, LocomotionUpdate::AddToEveryPos(equipment_pos_adds)))).unwrap();
+    }
+    // new speed, new turn speed and equipment position changes, without going through the engine (see client::prediction)
+    pub fn get_vehicle_physics(&self,
+        static_locomotion:&StaticLocomotion,
+        world:&GameMap<CoolVoxel, Road>,
+        vehicle_stats:&VehicleStats,
+        vehicle_position:&VehiclePosition,
+    ) -> (Vec3Df, Orientation, Vec<(Vec3Df, Orientation)>) {
         let mut new_vehicle_spd = vehicle_position.spd;
         new_vehicle_spd *= AIR_RESISTANCE;
         let mut new_vehicle_turn_spd = vehicle_position.turn_spd;
@@ -145,12 +177,7 @@ impl Locomotion {
         new_vehicle_turn_spd.yaw *= AIR_RESISTANCE * TURN_RESISTANCE;
         new_vehicle_turn_spd.pitch *= AIR_RESISTANCE * TURN_RESISTANCE;
         new_vehicle_turn_spd.roll *= AIR_RESISTANCE * TURN_RESISTANCE;
-        pos_events.send(VehicleEntityEvent::new(MustSync::Server, VehiclePosEvent::new(self_id, Some(CoolGameEngineTID::vehicles(self_id)), VehiclePosUpdate::UpdateEveryPos(vehicle_position.pos + new_vehicle_spd, vehicle_position.orientation + new_vehicle_turn_spd)))).unwrap();
-        pos_events.send(VehicleEntityEvent::new(MustSync::Server, VehiclePosEvent::new(self_id, Some(CoolGameEngineTID::vehicles(self_id)), VehiclePosUpdate::UpdateEverySpeed(new_vehicle_spd, new_vehicle_turn_spd)))).unwrap();
-
-        collider_events.send(VehicleEntityEvent::new(MustSync::Server,SimpleComponentEvent::new(self_id, None, HullUpdate::UpdateCollider(static_type.hull.base_collider.get_moved(vehicle_position.pos + new_vehicle_spd, vehicle_position.orientation + new_vehicle_turn_spd))))).unwrap();
-
-        loco_events.send(VehicleEntityEvent::new(MustSync::Server, LocomotionEvent::new(self_id, None, LocomotionUpdate::AddToEveryPos(equipment_pos_adds)))).unwrap();
+        (new_vehicle_spd, new_vehicle_turn_spd, equipment_pos_adds)
     }
 }
 
@@ -174,7 +201,7 @@ impl<ID:Identify> Component<ID> for Locomotion {
                 current_collider:eq.collider.clone()
             });
         }
-        Self { equipment, driver_actions:vec![] }
+        Self { equipment, driver_actions:vec![], last_input_tick:0 }
     }
 }
 
@@ -227,7 +254,10 @@ impl<ID:Identify> ComponentEvent<Locomotion, ID> for LocomotionEvent<ID> {
                 eq.current_local_orient += up.1;
             },
             LocomotionUpdate::FlushActions => components[self.id].driver_actions.clear(),
-            LocomotionUpdate::AddAction(act, res) => components[self.id].driver_actions.push((act, res)),
+            LocomotionUpdate::AddAction(act, res) => {
+                components[self.id].last_input_tick = act.get_started_at();
+                components[self.id].driver_actions.push((act, res));
+            },
         }
     }
 }
@@ -713,7 +743,7 @@ impl DriverAction {
     }
 }
 
-#[derive(Clone, Copy, Debug, ToBytes, FromBytes, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, ToBytes, FromBytes, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum SurfaceType {
     Ground,
     Water,
@@ -721,7 +751,7 @@ pub enum SurfaceType {
     Any
 }
 
-#[derive(Clone, Copy, Debug, ToBytes, FromBytes, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, ToBytes, FromBytes, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum SurfaceSubType {
     Industrial,
     Smooth,
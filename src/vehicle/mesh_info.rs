@@ -1,11 +1,13 @@
-use hord3::{defaults::default_rendering::vectorinator_binned::meshes::{Mesh, MeshID}, horde::game_engine::{entity::{Component, SimpleComponentEvent, SimpleComponentUpdate, StaticComponent}, multiplayer::Identify}};
+use hord3::{defaults::default_rendering::vectorinator_binned::meshes::{Mesh, MeshID}, horde::{game_engine::{entity::{Component, SimpleComponentEvent, SimpleComponentUpdate, StaticComponent}, multiplayer::Identify}, geometry::{rotation::Orientation, vec3d::Vec3Df}}};
 use to_from_bytes_derive::{FromBytes, ToBytes};
 
 
 #[derive(Clone, ToBytes, FromBytes, PartialEq)]
 pub struct VehicleMeshInfo {
     pub instance_id:Option<usize>,
-    pub loco_instances_ids:Option<Vec<usize>>
+    pub loco_instances_ids:Option<Vec<usize>>,
+    // rendered there instead of the position when set, see client::interpolation
+    pub shown:Option<(Vec3Df, Orientation)>,
 }
 
 #[derive(Clone)]
@@ -37,6 +39,6 @@ impl<ID:Identify> Component<ID> for VehicleMeshInfo {
     type SC = StaticVMeshInfo;
     type CE = SimpleComponentEvent<ID, VMeshInfoUpdate>;
     fn from_static(static_comp:&Self::SC) -> Self {
-        Self { instance_id: None, loco_instances_ids:None }
+        Self { instance_id: None, loco_instances_ids:None, shown:None }
     }
 }
\ No newline at end of file
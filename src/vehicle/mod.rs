@@ -24,28 +24,28 @@ impl<ID:Identify> NewEntity<VehicleEntity,ID> for NewVehicleEntity<ID> {
 
 impl<'a, ID:Identify> RenderVehicleEntity<VectorinatorWrite<'a>, ID> for VehicleEntity {
     fn do_render_changes(rendering_data: &mut VectorinatorWrite<'a>,position: &mut VehiclePosition,stats: &mut VehicleStats,mesh_info: &mut VehicleMeshInfo, locomotion:&mut Locomotion,static_type: &StaticVehicleEntity<ID>) {
-        
+        let (pos, orientation) = mesh_info.shown.unwrap_or((position.pos, position.orientation));
         match mesh_info.instance_id {
             Some(id) => {
                 let mut instance = rendering_data.meshes.instances[2].get_instance_mut(id);
                 
-                instance.change_pos(position.pos);
-                instance.change_orient(position.orientation);
+                instance.change_pos(pos);
+                instance.change_orient(orientation);
             },
             None => {
                 if !rendering_data.meshes.does_mesh_exist(&static_type.mesh_info.mesh_id) {
                     rendering_data.meshes.add_mesh(static_type.mesh_info.mesh_data.clone());
                 }
-                mesh_info.instance_id = Some(rendering_data.meshes.add_instance(MeshInstance::new(position.pos, position.orientation, static_type.mesh_info.mesh_id.clone(), true, false, false), 2))
+                mesh_info.instance_id = Some(rendering_data.meshes.add_instance(MeshInstance::new(pos, orientation, static_type.mesh_info.mesh_id.clone(), true, false, false), 2))
             }
         }
         match &mesh_info.loco_instances_ids {
             Some(ids) => {
                 for (i, eq) in locomotion.equipment.iter().enumerate() {
                     let mut instance = rendering_data.meshes.instances[2].get_instance_mut(ids[i]);
-                    let rotation = Rotation::from_orientation(position.orientation);
-                    instance.change_pos(position.pos + rotation.rotate(eq.current_local_position));
-                    instance.change_orient(position.orientation + eq.current_local_orient);
+                    let rotation = Rotation::from_orientation(orientation);
+                    instance.change_pos(pos + rotation.rotate(eq.current_local_position));
+                    instance.change_orient(orientation + eq.current_local_orient);
                 }
             },
             None => {
@@ -54,8 +54,8 @@ impl<'a, ID:Identify> RenderVehicleEntity<VectorinatorWrite<'a>, ID> for Vehicle
                     if !rendering_data.meshes.does_mesh_exist(&static_type.mesh_info.eq_mesh_ids[i]) {
                         rendering_data.meshes.add_mesh(static_type.mesh_info.eq_mesh_data[i].clone());
                     }
-                    let rotation = Rotation::from_orientation(position.orientation);
-                    ids.push(rendering_data.meshes.add_instance(MeshInstance::new(position.pos + rotation.rotate(eq.current_local_position), position.orientation + eq.current_local_orient, static_type.mesh_info.eq_mesh_ids[i].clone(), true, false, false), 2))
+                    let rotation = Rotation::from_orientation(orientation);
+                    ids.push(rendering_data.meshes.add_instance(MeshInstance::new(pos + rotation.rotate(eq.current_local_position), orientation + eq.current_local_orient, static_type.mesh_info.eq_mesh_ids[i].clone(), true, false, false), 2))
                 }
                 mesh_info.loco_instances_ids = Some(ids)
             }
@@ -0,0 +1,176 @@
+use std::{collections::HashMap, fmt::Display, fs, path::PathBuf, sync::OnceLock};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{game_engine::CoolVoxelType, game_map::{VoxelLight, voxel_models::CustomModel}, vehicle::locomotion::{SurfaceSubType, SurfaceType}};
+
+// Voxel types are defined in a JSON file (see voxels.json) instead of in the code
+// every type has a string id used by the code (road biomes, terrain) and the position of a type in the file is the
+// number stored in CoolVoxel::voxel_type, so new types must be added at the end to keep saved worlds readable
+// Server and client must load the same registry, the server sends its hash to clients (GameMapEvent::RegistryHash)
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct VoxelTypeDefinition {
+    pub id:String,
+    pub name:String,
+    pub texture:usize,
+    #[serde(default)]
+    pub texture_path:Option<String>,
+    // bit i set = side i (in EXPLORATION order) lets you see through, 63 for fully empty voxels
+    #[serde(default)]
+    pub empty_sides:u8,
+    // (level, r, g, b)
+    #[serde(default)]
+    pub light_passthrough:(u8, u8, u8, u8),
+    #[serde(default)]
+    pub light_source:Option<(u8, u8, u8, u8)>,
+    pub surface_type:SurfaceType,
+    pub surface_subtype:SurfaceSubType,
+    // up, front, down, back, left, right
+    #[serde(default)]
+    pub face_textures:Option<[usize ; 6]>,
+    #[serde(default)]
+    pub model:Option<CustomModel>,
+}
+
+impl VoxelTypeDefinition {
+    pub fn to_voxel_type(&self) -> CoolVoxelType {
+        let (level, r, g, b) = self.light_passthrough;
+        let mut voxel_type = CoolVoxelType::new(
+            self.empty_sides,
+            self.texture,
+            VoxelLight::new(level, r, g, b),
+            self.light_source.map(|(level, r, g, b)| {VoxelLight::new(level, r, g, b)}),
+            self.name.clone(),
+            self.texture_path.clone().map(PathBuf::from),
+            None,
+            self.surface_type,
+            self.surface_subtype
+        );
+        match self.face_textures {
+            Some(face_textures) => voxel_type = voxel_type.with_face_textures(face_textures),
+            None => ()
+        }
+        match &self.model {
+            Some(model) => voxel_type = voxel_type.with_model(model.clone()),
+            None => ()
+        }
+        voxel_type
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct RegistryFile {
+    voxels:Vec<VoxelTypeDefinition>,
+}
+
+#[derive(Debug)]
+pub enum RegistryError {
+    CouldNotReadFile(PathBuf, std::io::Error),
+    CouldNotParseFile(PathBuf, serde_json::Error),
+    DuplicateID(String),
+    TooManyTypes(usize),
+    InvalidModel(String),
+    AlreadyLoaded,
+}
+
+impl Display for RegistryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::CouldNotReadFile(path, error) => write!(f, "could not read voxel registry {} : {}", path.display(), error),
+            Self::CouldNotParseFile(path, error) => write!(f, "could not parse voxel registry {} : {}", path.display(), error),
+            Self::DuplicateID(id) => write!(f, "voxel id \"{}\" is defined more than once", id),
+            Self::TooManyTypes(count) => write!(f, "{} voxel types defined, at most {} fit in a voxel", count, u16::MAX as usize + 1),
+            Self::InvalidModel(id) => write!(f, "custom model of voxel \"{}\" needs resolution * resolution cells", id),
+            Self::AlreadyLoaded => write!(f, "the voxel registry was already loaded"),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {
+
+}
+
+#[derive(Clone, Debug)]
+pub struct VoxelRegistry {
+    definitions:Vec<VoxelTypeDefinition>,
+    ids:HashMap<String, u16>,
+    hash:u64,
+}
+
+static VOXEL_REGISTRY:OnceLock<VoxelRegistry> = OnceLock::new();
+
+impl VoxelRegistry {
+    pub fn from_file(path:PathBuf) -> Result<Self, RegistryError> {
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(error) => return Err(RegistryError::CouldNotReadFile(path, error))
+        };
+        let file:RegistryFile = match serde_json::from_str(&text) {
+            Ok(file) => file,
+            Err(error) => return Err(RegistryError::CouldNotParseFile(path, error))
+        };
+        Self::from_definitions(file.voxels)
+    }
+    pub fn from_definitions(definitions:Vec<VoxelTypeDefinition>) -> Result<Self, RegistryError> {
+        if definitions.len() > u16::MAX as usize + 1 {
+            return Err(RegistryError::TooManyTypes(definitions.len()));
+        }
+        let mut ids = HashMap::with_capacity(definitions.len());
+        for (i, definition) in definitions.iter().enumerate() {
+            if ids.insert(definition.id.clone(), i as u16).is_some() {
+                return Err(RegistryError::DuplicateID(definition.id.clone()));
+            }
+            match &definition.model {
+                Some(model) if model.resolution == 0 || model.corner_heights.len() != model.resolution * model.resolution => return Err(RegistryError::InvalidModel(definition.id.clone())),
+                _ => ()
+            }
+        }
+        let hash = hash_definitions(&definitions);
+        Ok(Self { definitions, ids, hash })
+    }
+    // loads the registry used by the whole process, must be done once before the world is created
+    pub fn load_global(path:PathBuf) -> Result<&'static Self, RegistryError> {
+        let registry = Self::from_file(path)?;
+        match VOXEL_REGISTRY.set(registry) {
+            Ok(()) => Ok(VOXEL_REGISTRY.get().unwrap()),
+            Err(_) => Err(RegistryError::AlreadyLoaded)
+        }
+    }
+    pub fn get_id(&self, id:&str) -> Option<u16> {
+        self.ids.get(id).cloned()
+    }
+    // for the ids the game can't run without
+    pub fn id(&self, id:&str) -> u16 {
+        match self.get_id(id) {
+            Some(number) => number,
+            None => panic!("voxel id \"{}\" is missing from the voxel registry", id)
+        }
+    }
+    pub fn get_voxel_types(&self) -> Vec<CoolVoxelType> {
+        self.definitions.iter().map(|definition| {definition.to_voxel_type()}).collect()
+    }
+    pub fn get_hash(&self) -> u64 {
+        self.hash
+    }
+    pub fn len(&self) -> usize {
+        self.definitions.len()
+    }
+}
+
+pub fn voxel_registry() -> &'static VoxelRegistry {
+    VOXEL_REGISTRY.get().expect("the voxel registry must be loaded before it is used (VoxelRegistry::load_global)")
+}
+
+// FNV-1a of the definitions in order, the same on every platform and build unlike std's hasher
+fn hash_definitions(definitions:&Vec<VoxelTypeDefinition>) -> u64 {
+    let canonical = serde_json::to_string(definitions).unwrap();
+    let mut hash:u64 = 0xcbf29ce484222325;
+    for byte in canonical.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}